@@ -3,3 +3,5 @@
 export RUSTFLAGS="-D warnings"
 
 cargo test --verbose
+cargo test --verbose --all-features
+cargo build --verbose --no-default-features
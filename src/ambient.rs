@@ -0,0 +1,195 @@
+//! Passing a deadline down to nested calls, without passing it explicitly
+//!
+//! Code that calls into a library often has an overall time budget, but the
+//! library uses its own timeouts internally, which know nothing about that
+//! budget. Threading a [`Deadline`] through every function signature would
+//! solve that, but is invasive.
+//!
+//! Instead, the caller can make its deadline the ambient deadline, using
+//! [`enter`], for as long as the returned [`Scope`] lives. Library code then
+//! starts its internal deadlines using [`start`], or limits existing ones
+//! using [`cap`], so they never end later than the ambient deadline. Scopes
+//! can be nested, and an inner scope can only make the ambient deadline
+//! earlier, never later.
+//!
+//! There is only one ambient deadline, which is stored as an absolute clock
+//! value. All code that uses it must use the same clock, the clock must have
+//! ticks of at most 32 bits, and all of it must run in the same execution
+//! context. Don't use this module from interrupt handlers.
+//!
+//! This module is only available, if the `ambient` feature is enabled. It
+//! requires atomic loads and stores, which some targets, like MSP430, don't
+//! support.
+//!
+//! # Example
+//!
+//! ``` rust
+//! use embedded_timeout_macros::{
+//!     ambient,
+//!     Deadline,
+//! };
+//! #
+//! # #[derive(Clone, Copy)]
+//! # struct Clock(u32);
+//! #
+//! # impl embedded_timeout_macros::Clock for Clock {
+//! #     type Ticks = u32;
+//! #     fn now(&mut self) -> u32 { self.0 }
+//! # }
+//!
+//! /// A library function that uses a timeout of its own
+//! fn read_sensor(clock: Clock) -> u32 {
+//!     let deadline = ambient::start(clock, 1000);
+//!     deadline.duration()
+//! }
+//!
+//! let clock = Clock(0);
+//!
+//! // Without an ambient deadline, the library uses its own timeout.
+//! assert_eq!(read_sensor(clock), 1000);
+//!
+//! // The caller only has 300 ticks left, which the library has to respect.
+//! let budget = Deadline::start(clock, 300);
+//! let _scope = ambient::enter(&budget);
+//!
+//! assert_eq!(read_sensor(clock), 300);
+//! ```
+//!
+//! [`Deadline`]: crate::Deadline
+
+
+use core::{
+    marker::PhantomData,
+    sync::atomic::{
+        AtomicBool,
+        AtomicU32,
+        Ordering,
+    },
+};
+
+use crate::{
+    Clock,
+    Deadline,
+    Ticks,
+};
+
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static AT:     AtomicU32  = AtomicU32::new(0);
+
+
+/// Makes a deadline the ambient deadline, for as long as the scope lives
+///
+/// If there already is an ambient deadline that ends earlier, it stays in
+/// effect. When the scope is dropped, the previous ambient deadline is
+/// restored.
+///
+/// # Panics
+///
+/// Panics, if the ticks of the clock are wider than 32 bits.
+pub fn enter<C>(deadline: &Deadline<C>) -> Scope
+    where C: Clock
+{
+    assert!(
+        C::Ticks::BITS <= 32,
+        "Ambient deadlines require clocks with ticks of at most 32 bits"
+    );
+
+    let at = deadline.next_deadline();
+    let previous = current();
+
+    let at = match previous {
+        Some(previous) => {
+            let previous = C::Ticks::saturating_from_u64(previous as u64);
+            if at.wrapping_sub(previous) < C::Ticks::HALF {
+                previous
+            }
+            else {
+                at
+            }
+        }
+        None =>
+            at,
+    };
+
+    set(Some(at.into_u64() as u32));
+
+    Scope {
+        previous,
+        _not_send: PhantomData,
+    }
+}
+
+/// Limits a deadline, so it doesn't end later than the ambient deadline
+///
+/// Does nothing, if there is no ambient deadline, or the deadline ends before
+/// it anyway. If the ambient deadline has already passed, the deadline is
+/// expired afterwards.
+pub fn cap<C>(deadline: &mut Deadline<C>)
+    where C: Clock
+{
+    let at = match current() {
+        Some(at) => C::Ticks::saturating_from_u64(at as u64),
+        None     => return,
+    };
+
+    let end = deadline.next_deadline();
+    let excess = end.wrapping_sub(at);
+    if excess < C::Ticks::HALF {
+        deadline.shorten(excess);
+    }
+}
+
+/// Starts a deadline that doesn't end later than the ambient deadline
+///
+/// Like [`Deadline::start`], followed by [`cap`].
+///
+/// [`Deadline::start`]: crate::Deadline::start
+pub fn start<C>(clock: C, duration: C::Ticks) -> Deadline<C>
+    where C: Clock
+{
+    let mut deadline = Deadline::start(clock, duration);
+    cap(&mut deadline);
+    deadline
+}
+
+
+/// Keeps a deadline the ambient deadline, until it is dropped
+///
+/// Returned by [`enter`]. Scopes must be dropped in the reverse order of
+/// their creation, which is what happens naturally, if they are bound to
+/// local variables.
+#[derive(Debug)]
+#[must_use = "The ambient deadline is reset, once the scope is dropped"]
+pub struct Scope {
+    previous:  Option<u32>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        set(self.previous);
+    }
+}
+
+
+fn current() -> Option<u32> {
+    if ACTIVE.load(Ordering::Acquire) {
+        Some(AT.load(Ordering::Relaxed))
+    }
+    else {
+        None
+    }
+}
+
+fn set(at: Option<u32>) {
+    match at {
+        Some(at) => {
+            AT.store(at, Ordering::Relaxed);
+            ACTIVE.store(true, Ordering::Release);
+        }
+        None => {
+            ACTIVE.store(false, Ordering::Release);
+        }
+    }
+}
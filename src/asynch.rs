@@ -0,0 +1,610 @@
+//! Timeouts for async code
+//!
+//! Async firmware waits for futures instead of polling `nb` operations, but
+//! the timeouts should work the same way. [`with_timeout`] and
+//! [`try_with_timeout`] wrap any future, like the receive futures of channels
+//! and signals from `embassy-sync`, and limit it using the same timers and
+//! [`TimeoutError`] as the blocking code.
+//!
+//! The timer is polled whenever the future is polled. To make sure that
+//! happens even if nothing else wakes the task, the wrapped future wakes it
+//! up again, every time it returns `Poll::Pending`. This keeps the executor
+//! busy until the future completes or the timer expires, which is the async
+//! equivalent of the busy waiting that the blocking macros do.
+//!
+//! Where a timer that has to be polled isn't available, [`timeout`] limits a
+//! future using an [`AsyncDelay`] instead, so the executor can sleep until
+//! either of them completes.
+//!
+//! Firmware that doesn't use an executor can still use individual async
+//! drivers, using [`LoopTask`]. It polls a future once per pass of the main
+//! loop, without blocking.
+//!
+//! With the `retry` feature enabled, [`retry_with_backoff`] retries async
+//! operations, using the same [`Backoff`] strategies and [`Classify`]
+//! implementations as the blocking [`Retrier`].
+//!
+//! This module is only available, if the `async` feature is enabled. It
+//! requires Rust 1.85 or later.
+//!
+//! # Example
+//!
+//! ``` rust
+//! use embedded_timeout_macros::{
+//!     asynch::with_timeout,
+//!     TimeoutError,
+//! };
+//! #
+//! # struct Timer(u32);
+//! #
+//! # impl embedded_timeout_macros::TimeoutTimer for Timer {
+//! #     fn is_expired(&mut self) -> bool {
+//! #         self.0 = self.0.saturating_sub(1);
+//! #         self.0 == 0
+//! #     }
+//! # }
+//! #
+//! # fn block_on<F: core::future::Future>(future: F) -> F::Output {
+//! #     let mut future = core::pin::pin!(future);
+//! #     let waker = core::task::Waker::noop();
+//! #     let mut cx = core::task::Context::from_waker(waker);
+//! #     loop {
+//! #         if let core::task::Poll::Ready(output) =
+//! #             core::future::Future::poll(future.as_mut(), &mut cx)
+//! #         {
+//! #             return output;
+//! #         }
+//! #     }
+//! # }
+//! #
+//! # block_on(async {
+//! let mut timer = Timer(10);
+//!
+//! // With `embassy-sync`, this could be `channel.receive()` or
+//! // `signal.wait()`. This one never completes.
+//! let receive = core::future::pending::<u8>();
+//!
+//! let result = with_timeout(&mut timer, receive).await;
+//! assert_eq!(result, Err(TimeoutError::Timeout));
+//! # });
+//! ```
+//!
+//!
+//! # Embassy
+//!
+//! This crate doesn't depend on `embassy-time`. There is no feature for it,
+//! and no adapters are provided, so connecting the two is up to the
+//! application. It takes a few lines, though. An [`AsyncDelay`] based on
+//! `embassy_time::Timer` makes [`timeout`] and [`retry_with_backoff`] work,
+//! while a [`Clock`] based on `embassy_time::Instant` provides a [`Deadline`]
+//! for everything else, including the blocking macros. The following code
+//! isn't compiled as part of this crate's tests:
+//!
+//! ``` ignore
+//! use embassy_time::{
+//!     Instant,
+//!     Timer,
+//! };
+//! use embedded_timeout_macros::{
+//!     asynch::AsyncDelay,
+//!     Clock,
+//! };
+//!
+//! struct EmbassyDelay;
+//!
+//! impl AsyncDelay for EmbassyDelay {
+//!     async fn delay_us(&mut self, us: u32) {
+//!         Timer::after_micros(us as u64).await
+//!     }
+//! }
+//!
+//! /// Counts at `embassy_time::TICK_HZ`
+//! struct EmbassyClock;
+//!
+//! impl Clock for EmbassyClock {
+//!     type Ticks = u64;
+//!
+//!     fn now(&mut self) -> u64 {
+//!         Instant::now().as_ticks()
+//!     }
+//! }
+//! ```
+//!
+//! [`Backoff`]: crate::retry::Backoff
+//! [`Classify`]: crate::retry::Classify
+//! [`Clock`]: crate::Clock
+//! [`Deadline`]: crate::Deadline
+//! [`Retrier`]: crate::retry::Retrier
+
+
+use core::{
+    future::{
+        poll_fn,
+        Future,
+    },
+    pin::{
+        pin,
+        Pin,
+    },
+    task::{
+        Context,
+        Poll,
+        Waker,
+    },
+};
+
+#[cfg(feature = "retry")]
+use crate::retry::{
+    retry_with_backoff_body,
+    Backoff,
+    Classify,
+    RetryError,
+};
+use crate::{
+    TimeoutError,
+    TimeoutTimer,
+};
+
+
+/// Waits for a future to complete, or for the timer to expire
+///
+/// Returns the output of the future, or [`TimeoutError::Timeout`], if the
+/// timer expired first. The timer must already be running. If the future
+/// completes on the same poll that the timer expires, the output of the
+/// future is returned.
+pub async fn with_timeout<T, F>(timer: &mut T, future: F)
+    -> Result<F::Output, TimeoutError>
+    where
+        T: TimeoutTimer,
+        F: Future,
+{
+    let mut future = pin!(future);
+
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        if timer.is_expired() {
+            return Poll::Ready(Err(TimeoutError::Timeout));
+        }
+
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+        .await
+}
+
+/// Waits for a fallible future to complete, or for the timer to expire
+///
+/// Like [`with_timeout`], but for futures that return a `Result`. Errors are
+/// returned as [`TimeoutError::Other`], so the result has the same type as
+/// that of the blocking macros.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     asynch::try_with_timeout,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # fn block_on<F: core::future::Future>(future: F) -> F::Output {
+/// #     let mut future = core::pin::pin!(future);
+/// #     let waker = core::task::Waker::noop();
+/// #     let mut cx = core::task::Context::from_waker(waker);
+/// #     loop {
+/// #         if let core::task::Poll::Ready(output) =
+/// #             core::future::Future::poll(future.as_mut(), &mut cx)
+/// #         {
+/// #             return output;
+/// #         }
+/// #     }
+/// # }
+/// #
+/// # block_on(async {
+/// let mut timer = Timer;
+///
+/// let transfer = async { Err::<u8, _>("bus error") };
+///
+/// let result = try_with_timeout(&mut timer, transfer).await;
+/// assert_eq!(result, Err(TimeoutError::Other("bus error")));
+/// # });
+/// ```
+pub async fn try_with_timeout<T, F, O, E>(timer: &mut T, future: F)
+    -> Result<O, TimeoutError<E>>
+    where
+        T: TimeoutTimer,
+        F: Future<Output = Result<O, E>>,
+{
+    match with_timeout(timer, future).await {
+        Ok(Ok(output))              => Ok(output),
+        Ok(Err(error))              => Err(TimeoutError::Other(error)),
+        Err(TimeoutError::Timeout)  => Err(TimeoutError::Timeout),
+        Err(TimeoutError::Other(e)) => match e {},
+    }
+}
+
+
+/// Waits for a future to complete, or for a delay to pass
+///
+/// Unlike [`with_timeout`], this doesn't need a timer that has to be polled.
+/// It races the future against an [`AsyncDelay`], which wakes the task when
+/// the delay has passed, so the executor can sleep in the meantime. Returns a
+/// [`TimeoutFuture`], which resolves to the output of the future, or to
+/// [`TimeoutError::Timeout`], if the delay passed first. The future is polled
+/// first, so if both are ready at the same time, its output is returned.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     asynch::{
+///         timeout,
+///         AsyncDelay,
+///     },
+///     TimeoutError,
+/// };
+/// #
+/// # fn block_on<F: core::future::Future>(future: F) -> F::Output {
+/// #     let mut future = core::pin::pin!(future);
+/// #     let waker = core::task::Waker::noop();
+/// #     let mut cx = core::task::Context::from_waker(waker);
+/// #     loop {
+/// #         if let core::task::Poll::Ready(output) =
+/// #             core::future::Future::poll(future.as_mut(), &mut cx)
+/// #         {
+/// #             return output;
+/// #         }
+/// #     }
+/// # }
+///
+/// /// With `embassy-time`, this could call `Timer::after_micros`.
+/// struct Delay;
+///
+/// impl AsyncDelay for Delay {
+///     async fn delay_us(&mut self, _: u32) {}
+/// }
+///
+/// # block_on(async {
+/// let mut delay = Delay;
+///
+/// let result = timeout(&mut delay, 1000, async { 21 }).await;
+/// assert_eq!(result, Ok(21));
+///
+/// let result = timeout(&mut delay, 1000, core::future::pending::<u8>()).await;
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// # });
+/// ```
+pub fn timeout<D, F>(delay: &mut D, us: u32, future: F)
+    -> TimeoutFuture<F, impl Future<Output = ()> + '_>
+    where
+        D: AsyncDelay,
+        F: Future,
+{
+    TimeoutFuture {
+        future,
+        delay: delay.delay_us(us),
+    }
+}
+
+/// Waits for a fallible future to complete, or for a delay to pass
+///
+/// Like [`timeout`], but for futures that return a `Result`. Errors are
+/// returned as [`TimeoutError::Other`], so the result has the same type as
+/// that of the blocking macros.
+pub async fn try_timeout<D, F, O, E>(delay: &mut D, us: u32, future: F)
+    -> Result<O, TimeoutError<E>>
+    where
+        D: AsyncDelay,
+        F: Future<Output = Result<O, E>>,
+{
+    match timeout(delay, us, future).await {
+        Ok(Ok(output))              => Ok(output),
+        Ok(Err(error))              => Err(TimeoutError::Other(error)),
+        Err(TimeoutError::Timeout)  => Err(TimeoutError::Timeout),
+        Err(TimeoutError::Other(e)) => match e {},
+    }
+}
+
+
+/// A future that races another future against a delay
+///
+/// Returned by [`timeout`]. `G` is the future of the delay.
+#[derive(Debug)]
+#[must_use = "Futures do nothing, unless they are awaited or polled"]
+pub struct TimeoutFuture<F, G> {
+    future: F,
+    delay:  G,
+}
+
+impl<F, G> Future for TimeoutFuture<F, G>
+    where
+        F: Future,
+        G: Future<Output = ()>,
+{
+    type Output = Result<F::Output, TimeoutError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // Safe, as neither field is ever moved out of the pinned struct.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let delay  = unsafe { Pin::new_unchecked(&mut this.delay) };
+
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        if delay.poll(cx).is_ready() {
+            return Poll::Ready(Err(TimeoutError::Timeout));
+        }
+
+        Poll::Pending
+    }
+}
+
+
+/// Polls a single future from a superloop, until it completes or times out
+///
+/// Main loops that don't run an executor can use this to drive a future
+/// returned by an async driver. Call [`LoopTask::poll`] once per pass of the
+/// loop. It polls the future once, then checks the timer, and returns right
+/// away. The result has the same shape as that of any other non-blocking
+/// operation, so it can also be passed to the macros.
+///
+/// The future is polled with a waker that does nothing. That's fine, as the
+/// future is polled again on the next pass anyway, but it means futures that
+/// only make progress when woken from an interrupt still work, just not any
+/// faster.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::pin::pin;
+///
+/// use embedded_timeout_macros::{
+///     asynch::LoopTask,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         self.0 == 0
+/// #     }
+/// # }
+/// #
+/// # async fn read_temperature() -> u16 { 21 }
+///
+/// // An async driver API
+/// let read = pin!(read_temperature());
+/// let mut task = LoopTask::new(Timer(100), read);
+///
+/// loop {
+///     match task.poll() {
+///         Ok(temperature) => {
+///             assert_eq!(temperature, 21);
+///             break;
+///         }
+///         Err(nb::Error::WouldBlock) => {
+///             // Not done yet. Do something else in the meantime.
+///         }
+///         Err(nb::Error::Other(TimeoutError::Timeout)) => {
+///             // The sensor didn't answer in time.
+///             break;
+///         }
+///         Err(nb::Error::Other(TimeoutError::Other(error))) =>
+///             match error {},
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct LoopTask<'f, T, F> {
+    timer:  T,
+    future: Pin<&'f mut F>,
+    done:   bool,
+}
+
+impl<'f, T, F> LoopTask<'f, T, F>
+    where
+        T: TimeoutTimer,
+        F: Future,
+{
+    /// Creates a task from a running timer and a pinned future
+    pub fn new(timer: T, future: Pin<&'f mut F>) -> Self {
+        LoopTask {
+            timer,
+            future,
+            done: false,
+        }
+    }
+
+    /// Polls the future once, then checks the timer
+    ///
+    /// Returns the output of the future, once it completes, `WouldBlock`
+    /// while it is pending, and [`TimeoutError::Timeout`], once the timer has
+    /// expired. If the future completes on the same poll that the timer
+    /// expires, its output is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if called again after the future has completed.
+    pub fn poll(&mut self) -> nb::Result<F::Output, TimeoutError> {
+        assert!(!self.done, "`LoopTask` polled after completion");
+
+        let mut cx = Context::from_waker(Waker::noop());
+        if let Poll::Ready(output) = self.future.as_mut().poll(&mut cx) {
+            self.done = true;
+            return Ok(output);
+        }
+        if self.timer.is_expired() {
+            return Err(nb::Error::Other(TimeoutError::Timeout));
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Indicates whether the future has completed
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Provides access to the timer
+    pub fn timer(&mut self) -> &mut T {
+        &mut self.timer
+    }
+
+    /// Consumes the task, returning the timer
+    pub fn free(self) -> T {
+        self.timer
+    }
+}
+
+
+/// Retries an async operation until it succeeds, gives up, or the timer expires
+///
+/// The async counterpart of [`retry::retry_with_backoff`], generated from the
+/// same source. `op` is called to start every attempt, and the future it
+/// returns is awaited. If it fails, `classify`
+/// decides whether the error is worth retrying, and `backoff` how long to
+/// wait before the next attempt. The number of attempts can be limited using
+/// `max_attempts`.
+///
+/// The timer limits the total duration, including the delays between
+/// attempts. It must already be running.
+///
+/// Only available, if the `retry` feature is enabled.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     asynch::{
+///         retry_with_backoff,
+///         AsyncDelay,
+///     },
+///     retry::{
+///         Exponential,
+///         RetryAll,
+///     },
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # fn block_on<F: core::future::Future>(future: F) -> F::Output {
+/// #     let mut future = core::pin::pin!(future);
+/// #     let waker = core::task::Waker::noop();
+/// #     let mut cx = core::task::Context::from_waker(waker);
+/// #     loop {
+/// #         if let core::task::Poll::Ready(output) =
+/// #             core::future::Future::poll(future.as_mut(), &mut cx)
+/// #         {
+/// #             return output;
+/// #         }
+/// #     }
+/// # }
+///
+/// /// With `embassy-time`, this could call `Timer::after_micros`.
+/// struct Delay(u32);
+///
+/// impl AsyncDelay for Delay {
+///     async fn delay_us(&mut self, us: u32) {
+///         self.0 += us;
+///     }
+/// }
+///
+/// # block_on(async {
+/// let mut delay = Delay(0);
+/// let mut attempts = 0;
+///
+/// let result = retry_with_backoff(
+///     &mut Timer,
+///     &mut delay,
+///     &mut Exponential::new(100, 10_000),
+///     &mut RetryAll,
+///     None,
+///     || {
+///         attempts += 1;
+///         let attempt = attempts;
+///         async move {
+///             if attempt < 3 { Err("NAK") } else { Ok(attempt) }
+///         }
+///     },
+/// )
+///     .await;
+///
+/// assert_eq!(result, Ok(3));
+/// assert_eq!(delay.0, 300);
+/// # });
+/// ```
+///
+/// [`retry::retry_with_backoff`]: crate::retry::retry_with_backoff
+#[cfg(feature = "retry")]
+pub async fn retry_with_backoff<T, D, B, C, F, Fut, O, E>(
+    timer:        &mut T,
+    delay:        &mut D,
+    backoff:      &mut B,
+    classify:     &mut C,
+    max_attempts: Option<u32>,
+    mut op:       F,
+)
+    -> Result<O, RetryError<E>>
+    where
+        T:   TimeoutTimer,
+        D:   AsyncDelay,
+        B:   Backoff,
+        C:   Classify<E>,
+        F:   FnMut() -> Fut,
+        Fut: Future<Output = Result<O, E>>,
+{
+    retry_with_backoff_body!(
+        backoff,
+        classify,
+        max_attempts,
+        attempt try_with_timeout(timer, op()).await,
+        delay (delay_us) with_timeout(timer, delay.delay_us(delay_us)).await
+    )
+}
+
+
+/// Anything that can wait asynchronously for a number of microseconds
+///
+/// The async counterpart of [`Delay`]. Used by [`timeout`], and by
+/// [`retry_with_backoff`] to wait between attempts.
+///
+/// Async HALs provide delays through `embedded_hal_async::delay::DelayNs`, or
+/// a timer of their async runtime. This crate doesn't depend on
+/// `embedded-hal-async`, so it can't use `DelayNs` as a bound, or implement
+/// this trait for it. A wrapper bridges the two:
+///
+/// ``` ignore
+/// use embedded_hal_async::delay::DelayNs;
+/// use embedded_timeout_macros::asynch::AsyncDelay;
+///
+/// /// Makes any `DelayNs` usable with this crate
+/// struct Bridge<D>(D);
+///
+/// impl<D> AsyncDelay for Bridge<D>
+///     where D: DelayNs
+/// {
+///     async fn delay_us(&mut self, us: u32) {
+///         self.0.delay_us(us).await
+///     }
+/// }
+/// ```
+///
+/// [`Delay`]: crate::Delay
+pub trait AsyncDelay {
+    /// Waits for the given number of microseconds
+    fn delay_us(&mut self, us: u32) -> impl Future<Output = ()>;
+}
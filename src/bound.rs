@@ -0,0 +1,169 @@
+//! Compile-time limits for wait loops
+
+
+use crate::TimeoutTimer;
+
+
+/// The maximum number of iterations of any wait loop in this crate
+///
+/// Only available, if the `bounded` feature is enabled. With that feature, all
+/// wait loops in this crate, including those generated by the macros, stop
+/// after this many iterations, even if the timer never expires. This
+/// guarantees termination if the timer hardware fails. Reaching the limit is
+/// reported like a timeout. Use [`block_timeout_bounded`] to distinguish
+/// between the two, or to use a different limit for a specific wait.
+///
+/// Defaults to `u32::MAX`. A different limit can be set at build time, using
+/// the `EMBEDDED_TIMEOUT_MACROS_MAX_POLLS` environment variable.
+///
+/// [`block_timeout_bounded`]: crate::block_timeout_bounded
+#[cfg(feature = "bounded")]
+pub const MAX_POLLS: u32 =
+    parse_max_polls(option_env!("EMBEDDED_TIMEOUT_MACROS_MAX_POLLS"));
+
+#[cfg(feature = "bounded")]
+const fn parse_max_polls(value: Option<&str>) -> u32 {
+    let digits = match value {
+        Some(value) => value.as_bytes(),
+        None        => return u32::MAX,
+    };
+
+    assert!(
+        !digits.is_empty(),
+        "EMBEDDED_TIMEOUT_MACROS_MAX_POLLS must not be empty"
+    );
+
+    let mut max: u32 = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        let digit = digits[i];
+        assert!(
+            digit.is_ascii_digit(),
+            "EMBEDDED_TIMEOUT_MACROS_MAX_POLLS must be a decimal number"
+        );
+
+        max = match max.checked_mul(10) {
+            Some(max) =>
+                max,
+            None =>
+                panic!("EMBEDDED_TIMEOUT_MACROS_MAX_POLLS is too large"),
+        };
+        max = match max.checked_add((digit - b'0') as u32) {
+            Some(max) =>
+                max,
+            None =>
+                panic!("EMBEDDED_TIMEOUT_MACROS_MAX_POLLS is too large"),
+        };
+
+        i += 1;
+    }
+
+    max
+}
+
+
+/// Whether the macros ignore their timers
+///
+/// Set at build time, using the `EMBEDDED_TIMEOUT_MACROS_NO_TIMEOUTS`
+/// environment variable. See [crate documentation].
+///
+/// # Example
+///
+/// ``` rust
+/// // Fail the build, if timeouts are disabled in a release build.
+/// #[cfg(not(debug_assertions))]
+/// const _: () = assert!(!embedded_timeout_macros::NO_TIMEOUTS);
+/// ```
+///
+/// [crate documentation]: crate#debugging
+pub const NO_TIMEOUTS: bool =
+    option_env!("EMBEDDED_TIMEOUT_MACROS_NO_TIMEOUTS").is_some();
+
+
+/// Checks a timer, unless timeouts have been disabled using `NO_TIMEOUTS`
+#[inline(always)]
+pub fn is_expired<T>(timer: &mut T) -> bool
+    where T: TimeoutTimer + ?Sized
+{
+    !NO_TIMEOUTS && timer.is_expired()
+}
+
+/// Counts the iterations of a wait loop, to enforce `MAX_POLLS`
+///
+/// Without the `bounded` feature, the bound is never exhausted, and checking
+/// it compiles to nothing.
+#[derive(Debug)]
+pub struct PollBound {
+    #[cfg(feature = "bounded")]
+    remaining: u32,
+
+    #[cfg(all(feature = "check-start", debug_assertions))]
+    polled: bool,
+}
+
+impl PollBound {
+    /// Creates a bound for a new wait loop
+    #[inline(always)]
+    pub fn new() -> Self {
+        PollBound {
+            #[cfg(feature = "bounded")]
+            remaining: MAX_POLLS,
+
+            #[cfg(all(feature = "check-start", debug_assertions))]
+            polled: false,
+        }
+    }
+
+    /// Counts an iteration, indicating whether the bound has been exhausted
+    #[inline(always)]
+    pub fn exhausted(&mut self) -> bool {
+        #[cfg(all(feature = "check-start", debug_assertions))]
+        {
+            self.polled = true;
+        }
+
+        #[cfg(feature = "bounded")]
+        {
+            if self.remaining == 0 {
+                return true;
+            }
+            self.remaining -= 1;
+        }
+
+        false
+    }
+
+    /// Records an iteration, without counting it against the bound
+    ///
+    /// For wait loops that enforce a limit of their own, but still need to
+    /// report an expired timer using [`PollBound::expired`].
+    #[inline(always)]
+    pub fn polled(&mut self) {
+        #[cfg(all(feature = "check-start", debug_assertions))]
+        {
+            self.polled = true;
+        }
+    }
+
+    /// Reports that the timer has expired
+    ///
+    /// With the `check-start` feature, in builds with debug assertions, panics,
+    /// if the timer had already expired when it was first checked. Otherwise,
+    /// this compiles to nothing.
+    #[inline(always)]
+    #[track_caller]
+    pub fn expired(&self) {
+        #[cfg(all(feature = "check-start", debug_assertions))]
+        assert!(
+            self.polled,
+            "Timer had already expired before the first poll. It might not \
+            have been started, or it has expired from a previous use."
+        );
+    }
+}
+
+impl Default for PollBound {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,300 @@
+//! Cancelling waits from another core
+//!
+//! On dual-core microcontrollers, like the RP2040 or the ESP32, one core often
+//! needs to abort what the other one is waiting for, for example when the
+//! user presses a button, or a supervisor decides that the operation is no
+//! longer needed. The types in this module can be placed in a `static`, and
+//! tripped from any core, or from an interrupt handler.
+//!
+//! - [`CancelToken`] cancels waits on request. Pass it to
+//!   [`block_cancellable`], or combine it with a timer, using
+//!   [`CancelToken::guard`].
+//! - [`SharedDeadline`] is a deadline that any core can set, move, or expire.
+//!   For this to work, all cores must read the same hardware clock.
+//!
+//! This module is only available, if the `cancel` feature is enabled. It
+//! requires atomic loads and stores, which some targets, like MSP430, don't
+//! support. Read-modify-write operations are not required, so it works on
+//! Cortex-M0+ cores, like those of the RP2040.
+//!
+//! [`block_cancellable`]: crate::block_cancellable
+
+
+use core::sync::atomic::{
+    AtomicBool,
+    AtomicU32,
+    Ordering,
+};
+
+use crate::{
+    Clock,
+    Deadline,
+    Ticks,
+    TimeoutTimer,
+};
+
+
+/// A flag that cancels waits, once it has been set
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_cancellable,
+///     cancel::CancelToken,
+///     WaitOutcome,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+///
+/// static ABORT: CancelToken = CancelToken::new();
+///
+/// // This would happen on the other core.
+/// ABORT.cancel();
+///
+/// let outcome: WaitOutcome<(), ()> = block_cancellable!(
+///     &mut Timer,
+///     ABORT.is_cancelled(),
+///     Err(nb::Error::WouldBlock)
+/// );
+/// assert_eq!(outcome, WaitOutcome::Cancelled);
+/// ```
+#[derive(Debug, Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+}
+
+impl CancelToken {
+    /// Creates a token that hasn't been cancelled
+    pub const fn new() -> Self {
+        CancelToken {
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// Cancels all waits that use this token
+    ///
+    /// Anything written before this call is visible to the core that notices
+    /// the cancellation.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Indicates whether the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Makes the token usable again, after it has been cancelled
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Release);
+    }
+
+    /// Combines the token with a timer
+    ///
+    /// The result expires when the timer does, or once the token has been
+    /// cancelled. This makes any wait in this crate cancellable, but a
+    /// cancellation is reported like a timeout then. Use
+    /// [`block_cancellable`] to tell them apart.
+    ///
+    /// [`block_cancellable`]: crate::block_cancellable
+    pub fn guard<T>(&self, timer: T) -> Guarded<'_, T>
+        where T: TimeoutTimer
+    {
+        Guarded {
+            token: self,
+            timer,
+        }
+    }
+}
+
+
+/// A timer that also expires, once a [`CancelToken`] has been cancelled
+///
+/// Returned by [`CancelToken::guard`].
+#[derive(Debug)]
+pub struct Guarded<'r, T> {
+    token: &'r CancelToken,
+    timer: T,
+}
+
+impl<T> Guarded<'_, T> {
+    /// Provides access to the timer
+    pub fn timer(&mut self) -> &mut T {
+        &mut self.timer
+    }
+}
+
+impl<T> TimeoutTimer for Guarded<'_, T>
+    where T: TimeoutTimer
+{
+    fn is_expired(&mut self) -> bool {
+        self.token.is_cancelled() || self.timer.is_expired()
+    }
+
+    fn idle(&mut self) {
+        self.timer.idle()
+    }
+}
+
+
+/// A deadline that can be changed from any core
+///
+/// Stores the point in time at which it expires, as an absolute value of a
+/// clock with ticks of at most 32 bits. Every core reads the clock on its own,
+/// using [`SharedDeadline::timer`], so all of them must read the same hardware
+/// counter, like the timer of the RP2040.
+///
+/// A deadline that hasn't been set never expires.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     cancel::SharedDeadline,
+///     TimeoutTimer,
+/// };
+/// #
+/// # #[derive(Clone, Copy)]
+/// # struct Clock;
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { 1_000 }
+/// # }
+///
+/// static DEADLINE: SharedDeadline = SharedDeadline::new();
+///
+/// DEADLINE.set_after(Clock, 500);
+///
+/// let mut timer = DEADLINE.timer(Clock);
+/// assert!(!timer.is_expired());
+///
+/// // This would happen on the other core.
+/// DEADLINE.expire(Clock);
+///
+/// assert!(timer.is_expired());
+/// ```
+#[derive(Debug, Default)]
+pub struct SharedDeadline {
+    at:  AtomicU32,
+    set: AtomicBool,
+}
+
+impl SharedDeadline {
+    /// Creates a deadline that hasn't been set
+    pub const fn new() -> Self {
+        SharedDeadline {
+            at:  AtomicU32::new(0),
+            set: AtomicBool::new(false),
+        }
+    }
+
+    /// Sets the deadline to an absolute value of the clock
+    pub fn set_at(&self, at: u32) {
+        self.at.store(at, Ordering::Relaxed);
+        self.set.store(true, Ordering::Release);
+    }
+
+    /// Sets the deadline to the given number of ticks from now
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the ticks of the clock are wider than 32 bits.
+    pub fn set_after<C>(&self, mut clock: C, ticks: C::Ticks)
+        where C: Clock
+    {
+        check_width::<C>();
+        let at = clock.now().wrapping_add(ticks);
+        self.set_at(at.into_u64() as u32);
+    }
+
+    /// Sets the deadline to the one of a [`Deadline`]
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the ticks of the clock are wider than 32 bits.
+    pub fn set_from<C>(&self, deadline: &Deadline<C>)
+        where C: Clock
+    {
+        check_width::<C>();
+        self.set_at(deadline.next_deadline().into_u64() as u32);
+    }
+
+    /// Makes the deadline expire right away
+    pub fn expire<C>(&self, clock: C)
+        where C: Clock
+    {
+        self.set_after(clock, C::Ticks::ZERO);
+    }
+
+    /// Unsets the deadline, so it never expires
+    pub fn clear(&self) {
+        self.set.store(false, Ordering::Release);
+    }
+
+    /// Returns the deadline, if it has been set
+    pub fn get(&self) -> Option<u32> {
+        if self.set.load(Ordering::Acquire) {
+            Some(self.at.load(Ordering::Relaxed))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Returns a timer that expires, once this deadline has passed
+    ///
+    /// Changes to the deadline affect the timer right away.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the ticks of the clock are wider than 32 bits.
+    pub fn timer<C>(&self, clock: C) -> SharedTimer<'_, C>
+        where C: Clock
+    {
+        check_width::<C>();
+
+        SharedTimer {
+            deadline: self,
+            clock,
+        }
+    }
+}
+
+
+/// A timer that expires once a [`SharedDeadline`] has passed
+///
+/// Returned by [`SharedDeadline::timer`].
+#[derive(Debug)]
+pub struct SharedTimer<'r, C> {
+    deadline: &'r SharedDeadline,
+    clock:    C,
+}
+
+impl<C> TimeoutTimer for SharedTimer<'_, C>
+    where C: Clock
+{
+    fn is_expired(&mut self) -> bool {
+        let at = match self.deadline.get() {
+            Some(at) => C::Ticks::saturating_from_u64(at as u64),
+            None     => return false,
+        };
+
+        self.clock.now().wrapping_sub(at) < C::Ticks::HALF
+    }
+}
+
+
+fn check_width<C>()
+    where C: Clock
+{
+    assert!(
+        C::Ticks::BITS <= 32,
+        "Shared deadlines require clocks with ticks of at most 32 bits"
+    );
+}
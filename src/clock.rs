@@ -0,0 +1,185 @@
+//! Clocks and the tick arithmetic built on top of them
+
+
+use core::{
+    fmt::Debug,
+    time::Duration,
+};
+
+
+/// A free-running clock that can be read at any time
+///
+/// Unlike `embedded_hal::timer::CountDown`, a clock doesn't need to be
+/// started. It just counts up, and wraps around when it reaches the maximum
+/// value of its tick type.
+///
+/// This is the basis for [`Deadline`], which computes elapsed time by
+/// comparing the current value of a clock with the value it had when the
+/// deadline was created.
+///
+/// [`Deadline`]: crate::Deadline
+pub trait Clock {
+    /// The type used to represent the clock's value
+    type Ticks: Ticks;
+
+    /// Returns the current value of the clock
+    fn now(&mut self) -> Self::Ticks;
+}
+
+impl<C> Clock for &mut C
+    where C: Clock + ?Sized
+{
+    type Ticks = C::Ticks;
+
+    fn now(&mut self) -> Self::Ticks {
+        (**self).now()
+    }
+}
+
+
+/// Converts a `Duration` into ticks of a clock with the given frequency
+///
+/// `frequency` is in Hz. The result is rounded up, so a timeout based on it is
+/// never shorter than the requested duration. Returns `None`, if the result
+/// doesn't fit into 64 bits.
+///
+/// This is meant as a building block for implementations of
+/// [`FromDuration`]. It can be used in constant expressions, and is what
+/// [`ticks!`] uses to convert durations at compile time.
+///
+/// [`FromDuration`]: crate::FromDuration
+/// [`ticks!`]: crate::ticks
+pub const fn ticks_from_duration(duration: Duration, frequency: u32)
+    -> Option<u64>
+{
+    let nanos_per_second = 1_000_000_000u128;
+
+    // Rounds up. `u128::div_ceil` would do that too, but requires Rust 1.73.
+    let ticks = duration.as_nanos() * frequency as u128;
+    let ticks = ticks / nanos_per_second
+        + (ticks % nanos_per_second != 0) as u128;
+
+    if ticks > u64::MAX as u128 {
+        return None;
+    }
+
+    Some(ticks as u64)
+}
+
+
+/// An unsigned integer type that can be used to represent clock ticks
+///
+/// All arithmetic on ticks is wrap-safe. Differences between two clock values
+/// are computed using wrapping subtraction, while sums saturate at the maximum
+/// value instead of overflowing.
+///
+/// # 8- and 16-bit targets
+///
+/// `Ticks` is implemented for `u8`, `u16`, `u32`, and `u64`. All arithmetic
+/// done while waiting (checking a [`Deadline`], an [`Alarm`], and so on) is
+/// done in the tick type of the clock, so on targets like AVR or MSP430, a
+/// clock with 16-bit ticks results in 16-bit arithmetic in the wait loop.
+///
+/// Conversions from milliseconds, microseconds, or `Duration` into ticks are
+/// done using wider types, but only happen when setting up a timeout. These
+/// conversions are explicit about overflow: they either saturate or return
+/// `None`, and their documentation says which.
+///
+/// [`Alarm`]: crate::Alarm
+/// [`Deadline`]: crate::Deadline
+pub trait Ticks: Copy + Ord + Debug {
+    /// The width of the type in bits
+    const BITS: u32;
+
+    /// The zero value
+    const ZERO: Self;
+
+    /// The maximum value
+    const MAX: Self;
+
+    /// Half of the range of the type
+    ///
+    /// When comparing two absolute clock values, the one that is less than
+    /// half the range ahead of the other is considered to be later.
+    const HALF: Self;
+
+    /// Adds `other` to `self`, wrapping around at the boundary
+    fn wrapping_add(self, other: Self) -> Self;
+
+    /// Subtracts `other` from `self`, wrapping around at the boundary
+    fn wrapping_sub(self, other: Self) -> Self;
+
+    /// Adds `other` to `self`, saturating at the maximum value
+    fn saturating_add(self, other: Self) -> Self;
+
+    /// Subtracts `other` from `self`, saturating at zero
+    fn saturating_sub(self, other: Self) -> Self;
+
+    /// Adds `other` to `self`, returning `None` on overflow
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    /// Keeps the lowest `bits` bits of `self`, setting the rest to zero
+    fn low_bits(self, bits: u32) -> Self;
+
+    /// Converts from `u64`, returning `None`, if the value doesn't fit
+    fn checked_from_u64(value: u64) -> Option<Self>;
+
+    /// Converts from `u64`, saturating at the maximum value
+    fn saturating_from_u64(value: u64) -> Self {
+        Self::checked_from_u64(value).unwrap_or(Self::MAX)
+    }
+
+    /// Converts into `u64`
+    fn into_u64(self) -> u64;
+}
+
+macro_rules! impl_ticks {
+    ($($ty:ty),*) => {
+        $(
+            impl Ticks for $ty {
+                const BITS: u32 = <$ty>::BITS;
+                const ZERO: Self = 0;
+                const MAX: Self = <$ty>::MAX;
+                const HALF: Self = <$ty>::MAX / 2 + 1;
+
+                fn wrapping_add(self, other: Self) -> Self {
+                    <$ty>::wrapping_add(self, other)
+                }
+
+                fn wrapping_sub(self, other: Self) -> Self {
+                    <$ty>::wrapping_sub(self, other)
+                }
+
+                fn saturating_add(self, other: Self) -> Self {
+                    <$ty>::saturating_add(self, other)
+                }
+
+                fn saturating_sub(self, other: Self) -> Self {
+                    <$ty>::saturating_sub(self, other)
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$ty>::checked_add(self, other)
+                }
+
+                fn low_bits(self, bits: u32) -> Self {
+                    if bits >= Self::BITS {
+                        return self;
+                    }
+
+                    self & ((1 << bits) - 1)
+                }
+
+                fn checked_from_u64(value: u64) -> Option<Self> {
+                    core::convert::TryFrom::try_from(value).ok()
+                }
+
+                fn into_u64(self) -> u64 {
+                    self as u64
+                }
+            }
+        )*
+    }
+}
+
+impl_ticks!(u8, u16, u32, u64);
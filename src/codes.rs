@@ -0,0 +1,239 @@
+//! Stable numeric codes for the errors in this crate
+//!
+//! Every error variant defined by this crate has a numeric code, which is
+//! returned by the `code` method of the error type. The codes are stable, so
+//! they can be sent through constrained channels (like a CAN payload or a LoRa
+//! uplink) and decoded on the other end, using [`TABLE`].
+//!
+//! Codes are never reused. The errors of operations are not covered by these
+//! codes. If you need to transmit those too, combine them with the code in a
+//! way that suits your application.
+//!
+//! | Code     | Variant                          |
+//! | -------- | -------------------------------- |
+//! | `0x0001` | `TimeoutError::Timeout`          |
+//! | `0x0002` | `TimeoutError::Other`            |
+//! | `0x0101` | `RetryError::Timeout`            |
+//! | `0x0102` | `RetryError::Exhausted`          |
+//! | `0x0103` | `RetryError::Fatal`              |
+//! | `0x0201` | `StartTimeoutError::Start`       |
+//! | `0x0202` | `StartTimeoutError::Timeout`     |
+//! | `0x0203` | `StartTimeoutError::Other`       |
+//! | `0x0301` | `PatternError::Timeout`          |
+//! | `0x0302` | `PatternError::InterByteTimeout` |
+//! | `0x0303` | `PatternError::Serial`           |
+//! | `0x0401` | `FrameError::Timeout`            |
+//! | `0x0402` | `FrameError::Overflow`           |
+//! | `0x0403` | `FrameError::Serial`             |
+//! | `0x0404` | `FrameError::Gap`                |
+//! | `0x0501` | `ReadyError::NotWarm`            |
+//! | `0x0502` | `ReadyError::NotReady`           |
+//! | `0x0503` | `ReadyError::Other`              |
+//! | `0x0601` | `BoundedError::Timeout`          |
+//! | `0x0602` | `BoundedError::LimitReached`     |
+//! | `0x0603` | `BoundedError::Other`            |
+//! | `0x0701` | `FeedError::TooEarly`            |
+//! | `0x0702` | `FeedError::TooLate`             |
+//! | `0x0801` | `FlashError::Timeout`            |
+//! | `0x0802` | `FlashError::Flash`              |
+//! | `0x0901` | `ScriptError::Deadline`          |
+//! | `0x0902` | `ScriptError::Timeout`           |
+//! | `0x0903` | `ScriptError::Other`             |
+//! | `0x0A01` | `DecodeError::UnexpectedEnd`     |
+//! | `0x0A02` | `DecodeError::Invalid`           |
+//! | `0x0B01` | `AbortError::Aborted`            |
+//! | `0x0B02` | `AbortError::AbortFailed`        |
+//! | `0x0B03` | `AbortError::AbortTimeout`       |
+//! | `0x0B04` | `AbortError::Other`              |
+//! | `0x0C01` | `DurationError::OutOfRange`      |
+//! | `0x0C02` | `DurationError::Start`           |
+//! | `0x0D01` | `ClockTimeoutError::Clock`       |
+//! | `0x0D02` | `ClockTimeoutError::Timeout`     |
+//! | `0x0D03` | `ClockTimeoutError::Other`       |
+
+
+/// `TimeoutError::Timeout`
+pub const TIMEOUT: u16 = 0x0001;
+
+/// `TimeoutError::Other`
+pub const OTHER: u16 = 0x0002;
+
+/// `RetryError::Timeout`
+pub const RETRY_TIMEOUT: u16 = 0x0101;
+
+/// `RetryError::Exhausted`
+pub const RETRY_EXHAUSTED: u16 = 0x0102;
+
+/// `RetryError::Fatal`
+pub const RETRY_FATAL: u16 = 0x0103;
+
+/// `StartTimeoutError::Start`
+pub const START_FAILED: u16 = 0x0201;
+
+/// `StartTimeoutError::Timeout`
+pub const START_TIMEOUT: u16 = 0x0202;
+
+/// `StartTimeoutError::Other`
+pub const START_OTHER: u16 = 0x0203;
+
+/// `PatternError::Timeout`
+pub const PATTERN_TIMEOUT: u16 = 0x0301;
+
+/// `PatternError::InterByteTimeout`
+pub const PATTERN_INTER_BYTE: u16 = 0x0302;
+
+/// `PatternError::Serial`
+pub const PATTERN_SERIAL: u16 = 0x0303;
+
+/// `FrameError::Timeout`
+pub const FRAME_TIMEOUT: u16 = 0x0401;
+
+/// `FrameError::Overflow`
+pub const FRAME_OVERFLOW: u16 = 0x0402;
+
+/// `FrameError::Serial`
+pub const FRAME_SERIAL: u16 = 0x0403;
+
+/// `FrameError::Gap`
+pub const FRAME_GAP: u16 = 0x0404;
+
+/// `ReadyError::NotWarm`
+pub const READY_NOT_WARM: u16 = 0x0501;
+
+/// `ReadyError::NotReady`
+pub const READY_NOT_READY: u16 = 0x0502;
+
+/// `ReadyError::Other`
+pub const READY_OTHER: u16 = 0x0503;
+
+/// `BoundedError::Timeout`
+pub const BOUNDED_TIMEOUT: u16 = 0x0601;
+
+/// `BoundedError::LimitReached`
+pub const BOUNDED_LIMIT: u16 = 0x0602;
+
+/// `BoundedError::Other`
+pub const BOUNDED_OTHER: u16 = 0x0603;
+
+/// `FeedError::TooEarly`
+pub const FEED_TOO_EARLY: u16 = 0x0701;
+
+/// `FeedError::TooLate`
+pub const FEED_TOO_LATE: u16 = 0x0702;
+
+/// `FlashError::Timeout`
+pub const FLASH_TIMEOUT: u16 = 0x0801;
+
+/// `FlashError::Flash`
+pub const FLASH_ERROR: u16 = 0x0802;
+
+/// `ScriptError::Deadline`
+pub const SCRIPT_DEADLINE: u16 = 0x0901;
+
+/// `ScriptError::Timeout`
+pub const SCRIPT_TIMEOUT: u16 = 0x0902;
+
+/// `ScriptError::Other`
+pub const SCRIPT_OTHER: u16 = 0x0903;
+
+/// `DecodeError::UnexpectedEnd`
+pub const DECODE_UNEXPECTED_END: u16 = 0x0A01;
+
+/// `DecodeError::Invalid`
+pub const DECODE_INVALID: u16 = 0x0A02;
+
+/// `AbortError::Aborted`
+pub const ABORT_ABORTED: u16 = 0x0B01;
+
+/// `AbortError::AbortFailed`
+pub const ABORT_FAILED: u16 = 0x0B02;
+
+/// `AbortError::AbortTimeout`
+pub const ABORT_TIMEOUT: u16 = 0x0B03;
+
+/// `AbortError::Other`
+pub const ABORT_OTHER: u16 = 0x0B04;
+
+/// `DurationError::OutOfRange`
+pub const DURATION_OUT_OF_RANGE: u16 = 0x0C01;
+
+/// `DurationError::Start`
+pub const DURATION_START: u16 = 0x0C02;
+
+/// `ClockTimeoutError::Clock`
+pub const CLOCK_FAILED: u16 = 0x0D01;
+
+/// `ClockTimeoutError::Timeout`
+pub const CLOCK_TIMEOUT: u16 = 0x0D02;
+
+/// `ClockTimeoutError::Other`
+pub const CLOCK_OTHER: u16 = 0x0D03;
+
+
+/// All error codes, along with the name of the variant they identify
+///
+/// Can be used on the receiving end to decode error codes, or to generate a
+/// table for documentation purposes.
+pub const TABLE: &[(u16, &str)] = &[
+    (TIMEOUT,               "TimeoutError::Timeout"),
+    (OTHER,                 "TimeoutError::Other"),
+    (RETRY_TIMEOUT,         "RetryError::Timeout"),
+    (RETRY_EXHAUSTED,       "RetryError::Exhausted"),
+    (RETRY_FATAL,           "RetryError::Fatal"),
+    (START_FAILED,          "StartTimeoutError::Start"),
+    (START_TIMEOUT,         "StartTimeoutError::Timeout"),
+    (START_OTHER,           "StartTimeoutError::Other"),
+    (PATTERN_TIMEOUT,       "PatternError::Timeout"),
+    (PATTERN_INTER_BYTE,    "PatternError::InterByteTimeout"),
+    (PATTERN_SERIAL,        "PatternError::Serial"),
+    (FRAME_TIMEOUT,         "FrameError::Timeout"),
+    (FRAME_OVERFLOW,        "FrameError::Overflow"),
+    (FRAME_SERIAL,          "FrameError::Serial"),
+    (FRAME_GAP,             "FrameError::Gap"),
+    (READY_NOT_WARM,        "ReadyError::NotWarm"),
+    (READY_NOT_READY,       "ReadyError::NotReady"),
+    (READY_OTHER,           "ReadyError::Other"),
+    (BOUNDED_TIMEOUT,       "BoundedError::Timeout"),
+    (BOUNDED_LIMIT,         "BoundedError::LimitReached"),
+    (BOUNDED_OTHER,         "BoundedError::Other"),
+    (FEED_TOO_EARLY,        "FeedError::TooEarly"),
+    (FEED_TOO_LATE,         "FeedError::TooLate"),
+    (FLASH_TIMEOUT,         "FlashError::Timeout"),
+    (FLASH_ERROR,           "FlashError::Flash"),
+    (SCRIPT_DEADLINE,       "ScriptError::Deadline"),
+    (SCRIPT_TIMEOUT,        "ScriptError::Timeout"),
+    (SCRIPT_OTHER,          "ScriptError::Other"),
+    (DECODE_UNEXPECTED_END, "DecodeError::UnexpectedEnd"),
+    (DECODE_INVALID,        "DecodeError::Invalid"),
+    (ABORT_ABORTED,         "AbortError::Aborted"),
+    (ABORT_FAILED,          "AbortError::AbortFailed"),
+    (ABORT_TIMEOUT,         "AbortError::AbortTimeout"),
+    (ABORT_OTHER,           "AbortError::Other"),
+    (DURATION_OUT_OF_RANGE, "DurationError::OutOfRange"),
+    (DURATION_START,        "DurationError::Start"),
+    (CLOCK_FAILED,          "ClockTimeoutError::Clock"),
+    (CLOCK_TIMEOUT,         "ClockTimeoutError::Timeout"),
+    (CLOCK_OTHER,           "ClockTimeoutError::Other"),
+];
+
+/// Returns the name of the variant identified by the given code
+///
+/// Returns `None`, if the code is unknown.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     codes,
+///     TimeoutError,
+/// };
+///
+/// let code = TimeoutError::<()>::Timeout.code();
+/// assert_eq!(codes::describe(code), Some("TimeoutError::Timeout"));
+/// ```
+pub fn describe(code: u16) -> Option<&'static str> {
+    TABLE
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+}
@@ -0,0 +1,212 @@
+//! Timeouts that can be changed at runtime
+//!
+//! Products often need their timeouts tuned in the field, without reflashing
+//! the firmware. [`TimeoutConfig`] holds a table of timeouts, which can be
+//! placed in a `static`. Each timeout is identified by a [`TimeoutId`], which
+//! the application defines as a constant. The value is read whenever a timeout
+//! is started, so changes, made from a settings store or a debug console, take
+//! effect the next time the timeout is used.
+//!
+//! This module is only available, if the `config` feature is enabled. It
+//! requires atomic loads and stores, which some targets, like MSP430, don't
+//! support, and Rust 1.79 or later.
+//!
+//! # Example
+//!
+//! ``` rust
+//! use embedded_timeout_macros::config::{
+//!     TimeoutConfig,
+//!     TimeoutId,
+//! };
+//! #
+//! # #[derive(Clone, Copy)]
+//! # struct Clock;
+//! #
+//! # impl embedded_timeout_macros::Clock for Clock {
+//! #     type Ticks = u32;
+//! #     fn now(&mut self) -> u32 { 0 }
+//! # }
+//!
+//! const MODEM_RESPONSE: TimeoutId = TimeoutId::new(0, "modem-response");
+//! const SENSOR_READY:   TimeoutId = TimeoutId::new(1, "sensor-ready");
+//!
+//! static TIMEOUTS: TimeoutConfig<2> = TimeoutConfig::new(
+//!     [MODEM_RESPONSE, SENSOR_READY],
+//!     [5_000, 200],
+//! );
+//!
+//! let deadline = TIMEOUTS.start(Clock, MODEM_RESPONSE);
+//! assert_eq!(deadline.duration(), 5_000);
+//!
+//! // Received from a debug console
+//! let (name, value) = ("modem-response", 8_000);
+//! TIMEOUTS.set_by_name(name, value).unwrap();
+//!
+//! let deadline = TIMEOUTS.start(Clock, MODEM_RESPONSE);
+//! assert_eq!(deadline.duration(), 8_000);
+//! ```
+
+
+use core::sync::atomic::{
+    AtomicU32,
+    Ordering,
+};
+
+use crate::{
+    Clock,
+    Deadline,
+    Ticks,
+};
+
+
+/// Identifies a timeout in a [`TimeoutConfig`]
+///
+/// Consists of the index of the timeout in the table, and a name that
+/// identifies it to humans, or to a settings store.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TimeoutId {
+    index: usize,
+    name:  &'static str,
+}
+
+impl TimeoutId {
+    /// Creates an ID
+    pub const fn new(index: usize, name: &'static str) -> Self {
+        TimeoutId {
+            index,
+            name,
+        }
+    }
+
+    /// Returns the index of the timeout
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the name of the timeout
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+
+/// A table of `N` timeouts, that can be changed at runtime
+///
+/// The values don't have a unit. Whatever the application uses them for
+/// defines their unit, for example ticks of a clock, when starting a
+/// [`Deadline`] using [`TimeoutConfig::start`].
+///
+/// All methods take `&self`, so the table can be placed in a `static`, and be
+/// changed from anywhere, including interrupt handlers.
+#[derive(Debug)]
+pub struct TimeoutConfig<const N: usize> {
+    ids:      [TimeoutId; N],
+    defaults: [u32; N],
+    values:   [AtomicU32; N],
+}
+
+impl<const N: usize> TimeoutConfig<N> {
+    /// Creates a table with the given IDs, and their default values
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the index of an ID doesn't match its position in `ids`. In
+    /// a `static`, this is caught at compile time.
+    pub const fn new(ids: [TimeoutId; N], defaults: [u32; N]) -> Self {
+        let mut values = [const { AtomicU32::new(0) }; N];
+
+        let mut i = 0;
+        while i < N {
+            assert!(ids[i].index == i, "Index of timeout ID must match table");
+            values[i] = AtomicU32::new(defaults[i]);
+            i += 1;
+        }
+
+        TimeoutConfig {
+            ids,
+            defaults,
+            values,
+        }
+    }
+
+    /// Returns the current value of a timeout
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the ID doesn't belong to this table.
+    pub fn get(&self, id: TimeoutId) -> u32 {
+        self.values[self.check(id)].load(Ordering::Relaxed)
+    }
+
+    /// Changes the value of a timeout
+    ///
+    /// Timeouts that have already been started are not affected.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the ID doesn't belong to this table.
+    pub fn set(&self, id: TimeoutId, value: u32) {
+        self.values[self.check(id)].store(value, Ordering::Relaxed)
+    }
+
+    /// Changes the value of the timeout with the given name
+    ///
+    /// Returns `Err(UnknownName)`, if there is no such timeout.
+    pub fn set_by_name(&self, name: &str, value: u32)
+        -> Result<(), UnknownName>
+    {
+        let id = self.find(name)
+            .ok_or(UnknownName)?;
+        self.set(id, value);
+        Ok(())
+    }
+
+    /// Returns the ID of the timeout with the given name
+    pub fn find(&self, name: &str) -> Option<TimeoutId> {
+        self.ids.iter().copied().find(|id| id.name == name)
+    }
+
+    /// Resets all timeouts to their default values
+    pub fn reset(&self) {
+        for (value, default) in self.values.iter().zip(self.defaults) {
+            value.store(default, Ordering::Relaxed);
+        }
+    }
+
+    /// Iterates over the IDs and current values of all timeouts
+    ///
+    /// Can be used to list the timeouts in a debug console, or to save them to
+    /// a settings store.
+    pub fn iter(&self) -> impl Iterator<Item = (TimeoutId, u32)> + '_ {
+        self.ids.iter()
+            .zip(&self.values)
+            .map(|(&id, value)| (id, value.load(Ordering::Relaxed)))
+    }
+
+    /// Starts a deadline, with the current value of a timeout, in ticks
+    ///
+    /// Saturates, if the value doesn't fit into the ticks of the clock.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if the ID doesn't belong to this table.
+    pub fn start<C>(&self, clock: C, id: TimeoutId) -> Deadline<C>
+        where C: Clock
+    {
+        let ticks = C::Ticks::saturating_from_u64(self.get(id) as u64);
+        Deadline::start(clock, ticks)
+    }
+
+    fn check(&self, id: TimeoutId) -> usize {
+        assert!(
+            self.ids.get(id.index) == Some(&id),
+            "Timeout ID doesn't belong to this table"
+        );
+        id.index
+    }
+}
+
+
+/// Returned by [`TimeoutConfig::set_by_name`], if the name is not known
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnknownName;
@@ -0,0 +1,292 @@
+//! Deadlines based on a free-running clock
+
+
+use crate::{
+    Clock,
+    Ticks,
+};
+
+
+/// A point in time, measured by a [`Clock`], after which a timeout expires
+///
+/// A deadline remembers the value of the clock at the time it was created, as
+/// well as a duration. It expires once that duration has elapsed.
+///
+/// `Deadline` implements [`TimeoutTimer`], so it can be passed to all macros in
+/// this crate, in place of a `CountDown` timer.
+///
+/// Since the macros evaluate their timer argument anew in every iteration, the
+/// deadline isn't borrowed while the operation is being evaluated. This means
+/// the operation can use [`Deadline::extend`] to push the deadline out, if it
+/// detects that progress is being made, just slowly.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     Deadline,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+/// #
+/// # let clock = Clock(0);
+/// # let mut progress = 0;
+///
+/// let mut deadline = Deadline::start(clock, 10);
+///
+/// let result: Result<u32, _> = block_timeout!(
+///     &mut deadline,
+///     {
+///         progress += 1;
+///
+///         if progress == 100 {
+///             Ok(progress)
+///         }
+///         else {
+///             // Still busy, but we're making progress. Give the operation
+///             // some more time.
+///             deadline.extend(1);
+///             Err(nb::Error::<()>::WouldBlock)
+///         }
+///     }
+/// );
+///
+/// assert_eq!(result.unwrap(), 100);
+/// ```
+///
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[derive(Debug)]
+pub struct Deadline<C: Clock> {
+    clock:    C,
+    start:    C::Ticks,
+    duration: C::Ticks,
+}
+
+impl<C> Deadline<C>
+    where C: Clock
+{
+    /// Creates a deadline that expires once `duration` ticks have elapsed
+    ///
+    /// The current value of the clock is read immediately, and counting starts
+    /// from there.
+    ///
+    /// If you want to keep using the clock after creating the deadline, you
+    /// can pass a mutable reference to it.
+    pub fn start(mut clock: C, duration: C::Ticks) -> Self {
+        let start = clock.now();
+
+        Deadline {
+            clock,
+            start,
+            duration,
+        }
+    }
+
+    /// Restarts the deadline from the current value of the clock
+    ///
+    /// The duration, including any extensions, stays the same.
+    pub fn restart(&mut self) {
+        self.start = self.clock.now();
+    }
+
+    /// Restarts the deadline from the current value of the clock, with a new
+    /// duration
+    pub fn restart_with(&mut self, duration: C::Ticks) {
+        self.restart();
+        self.duration = duration;
+    }
+
+    /// Returns the number of ticks that have elapsed since the deadline was
+    /// created
+    pub fn elapsed(&mut self) -> C::Ticks {
+        self.clock.now().wrapping_sub(self.start)
+    }
+
+    /// Returns the number of ticks left until the deadline expires
+    ///
+    /// Returns zero, if the deadline has already expired.
+    pub fn remaining(&mut self) -> C::Ticks {
+        self.duration.saturating_sub(self.elapsed())
+    }
+
+    /// Returns how much of the duration has elapsed, as a fraction of `scale`
+    ///
+    /// Pass 100 to get a percentage, for example. The result saturates at
+    /// `scale`, once the deadline has expired. A deadline with a duration of
+    /// zero is always fully elapsed.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// use embedded_timeout_macros::Deadline;
+    /// #
+    /// # struct Clock(u32);
+    /// #
+    /// # impl embedded_timeout_macros::Clock for Clock {
+    /// #     type Ticks = u32;
+    /// #     fn now(&mut self) -> u32 { self.0 }
+    /// # }
+    ///
+    /// let mut deadline = Deadline::start(Clock(0), 400);
+    /// deadline.clock().0 = 100;
+    ///
+    /// assert_eq!(deadline.fraction_elapsed(100), 25);
+    /// ```
+    pub fn fraction_elapsed(&mut self, scale: u32) -> u32 {
+        let duration = self.duration.into_u64();
+        if duration == 0 {
+            return scale;
+        }
+
+        let elapsed = self.elapsed().into_u64().min(duration);
+        (elapsed as u128 * scale as u128 / duration as u128) as u32
+    }
+
+    /// Returns the value of the clock at which the deadline expires
+    ///
+    /// Low-power applications can use this to program a wakeup, instead of
+    /// polling the deadline periodically.
+    pub fn next_deadline(&self) -> C::Ticks {
+        self.start.wrapping_add(self.duration)
+    }
+
+    /// Indicates whether the deadline has passed
+    pub fn is_expired(&mut self) -> bool {
+        self.elapsed() >= self.duration
+    }
+
+    /// Pushes the deadline out by the given number of ticks
+    ///
+    /// The total duration of the deadline saturates at the maximum value of the
+    /// tick type.
+    pub fn extend(&mut self, by: C::Ticks) {
+        self.duration = self.duration.saturating_add(by);
+    }
+
+    /// Pulls the deadline in by the given number of ticks
+    ///
+    /// The total duration of the deadline saturates at zero.
+    pub fn shorten(&mut self, by: C::Ticks) {
+        self.duration = self.duration.saturating_sub(by);
+    }
+
+    /// Extends the deadline, if necessary, so at least `ticks` are left
+    ///
+    /// Does nothing, if more time than that is left already. The total
+    /// duration saturates at the maximum value of the tick type.
+    pub fn ensure_remaining(&mut self, ticks: C::Ticks) {
+        let remaining = self.remaining();
+        if remaining < ticks {
+            self.extend(ticks.saturating_sub(remaining));
+        }
+    }
+
+    /// Creates a deadline for part of the work, that ends no later than this
+    ///
+    /// The new deadline starts now, and expires after `ticks`, or when this
+    /// deadline expires, whichever comes first. It borrows the clock of this
+    /// deadline, so it can be passed to a lower layer, which doesn't need to
+    /// know about the overall budget.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// use embedded_timeout_macros::Deadline;
+    /// #
+    /// # struct Clock(u32);
+    /// #
+    /// # impl embedded_timeout_macros::Clock for Clock {
+    /// #     type Ticks = u32;
+    /// #     fn now(&mut self) -> u32 { self.0 }
+    /// # }
+    ///
+    /// let mut deadline = Deadline::start(Clock(0), 1000);
+    /// deadline.clock().0 = 900;
+    ///
+    /// // Only 100 ticks are left, so that's all the sub-deadline gets.
+    /// let sub = deadline.sub_deadline(500);
+    /// assert_eq!(sub.duration(), 100);
+    /// ```
+    pub fn sub_deadline(&mut self, ticks: C::Ticks) -> Deadline<&mut C> {
+        self.sub_deadline_with(|remaining| ticks.min(remaining))
+    }
+
+    /// Creates a deadline for a fraction of the remaining time
+    ///
+    /// Like [`Deadline::sub_deadline`], but the new deadline gets
+    /// `numerator / denominator` of the time that is left, rounded down. The
+    /// fraction saturates at 1, and a `denominator` of zero results in a
+    /// deadline that has expired already.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// use embedded_timeout_macros::Deadline;
+    /// #
+    /// # struct Clock(u32);
+    /// #
+    /// # impl embedded_timeout_macros::Clock for Clock {
+    /// #     type Ticks = u32;
+    /// #     fn now(&mut self) -> u32 { self.0 }
+    /// # }
+    ///
+    /// let mut deadline = Deadline::start(Clock(0), 1000);
+    ///
+    /// // Leave half of the budget for the retries.
+    /// let first_try = deadline.split(1, 2);
+    /// assert_eq!(first_try.duration(), 500);
+    /// ```
+    pub fn split(&mut self, numerator: u32, denominator: u32)
+        -> Deadline<&mut C>
+    {
+        self.sub_deadline_with(|remaining| {
+            if denominator == 0 {
+                return C::Ticks::ZERO;
+            }
+
+            let numerator = numerator.min(denominator);
+            let duration = remaining.into_u64() as u128 * numerator as u128
+                / denominator as u128;
+
+            // Never larger than `remaining`, so this never saturates.
+            C::Ticks::saturating_from_u64(duration as u64)
+        })
+    }
+
+    /// Returns the duration of the deadline, including any extensions
+    pub fn duration(&self) -> C::Ticks {
+        self.duration
+    }
+
+    /// Provides access to the clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Provides shared access to the clock
+    pub fn clock_ref(&self) -> &C {
+        &self.clock
+    }
+
+    fn sub_deadline_with<F>(&mut self, duration: F) -> Deadline<&mut C>
+        where F: FnOnce(C::Ticks) -> C::Ticks
+    {
+        // Read the clock only once, so the new deadline can't end later than
+        // this one.
+        let now = self.clock.now();
+        let remaining = self.duration
+            .saturating_sub(now.wrapping_sub(self.start));
+
+        Deadline {
+            clock:    &mut self.clock,
+            start:    now,
+            duration: duration(remaining),
+        }
+    }
+}
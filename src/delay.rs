@@ -0,0 +1,25 @@
+//! The abstraction over delays that the helpers in this crate are built on
+
+
+/// Anything that can wait for a number of microseconds
+///
+/// Used by the helpers that wait between attempts, like [`Retrier`] or
+/// [`poll_until`]. With the `eh0` feature enabled, this is implemented for all
+/// implementations of `embedded_hal::blocking::delay::DelayUs<u32>` from
+/// embedded-hal 0.2. For other delay providers, implement it directly.
+///
+/// [`Retrier`]: crate::retry::Retrier
+/// [`poll_until`]: crate::io::poll_until
+pub trait Delay {
+    /// Waits for the given number of microseconds
+    fn delay_us(&mut self, us: u32);
+}
+
+#[cfg(feature = "eh0")]
+impl<D> Delay for D
+    where D: embedded_hal::blocking::delay::DelayUs<u32>
+{
+    fn delay_us(&mut self, us: u32) {
+        embedded_hal::blocking::delay::DelayUs::delay_us(self, us)
+    }
+}
@@ -0,0 +1,88 @@
+//! Dynamically dispatched variants of the macros
+//!
+//! The macros in this crate are expanded at every call site, and generic code
+//! that uses them is monomorphized for every combination of timer and
+//! operation. That's usually fine, but in applications where code size is
+//! critical, it can add up.
+//!
+//! The functions in this module take the timer and the operation as trait
+//! objects. They are only instantiated once per result and error type, no
+//! matter how many different timers and operations they're used with. This
+//! comes at the cost of dynamic dispatch, and the restrictions of closures
+//! that the macros don't have (see [crate documentation]).
+//!
+//! [`TimeoutTimer`] is object-safe, so `&mut dyn TimeoutTimer` can also be
+//! passed to the macros directly.
+//!
+//! # Example
+//!
+//! ``` rust
+//! use embedded_timeout_macros::{
+//!     dynamic,
+//!     TimeoutTimer,
+//! };
+//! #
+//! # struct Timer;
+//! #
+//! # impl embedded_hal::timer::CountDown for Timer {
+//! #     type Time = ();
+//! #     fn start<T>(&mut self, _: T) {}
+//! #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+//! #         Err(nb::Error::WouldBlock)
+//! #     }
+//! # }
+//! #
+//! # let mut timer = Timer;
+//!
+//! let timer: &mut dyn TimeoutTimer = &mut timer;
+//!
+//! let result = dynamic::block_timeout::<_, ()>(timer, &mut || Ok(5));
+//! assert_eq!(result, Ok(5));
+//! ```
+//!
+//! [crate documentation]: crate
+//! [`TimeoutTimer`]: crate::TimeoutTimer
+
+
+use crate::{
+    block_timeout as block_timeout_macro,
+    repeat_timeout as repeat_timeout_macro,
+    TimeoutError,
+    TimeoutTimer,
+};
+
+
+/// Blocks on a non-blocking operation until a timer times out
+///
+/// Like [`block_timeout!`], but using dynamic dispatch.
+///
+/// [`block_timeout!`]: crate::block_timeout
+#[inline(never)]
+pub fn block_timeout<T, E>(
+    timer: &mut dyn TimeoutTimer,
+    op:    &mut dyn FnMut() -> nb::Result<T, E>,
+)
+    -> Result<T, TimeoutError<E>>
+{
+    block_timeout_macro!(timer, op())
+}
+
+/// Repeats an operation until a timer times out
+///
+/// Like [`repeat_timeout!`], but using dynamic dispatch.
+///
+/// [`repeat_timeout!`]: crate::repeat_timeout
+#[inline(never)]
+pub fn repeat_timeout<T, E>(
+    timer:      &mut dyn TimeoutTimer,
+    op:         &mut dyn FnMut() -> Result<T, E>,
+    on_success: &mut dyn FnMut(T),
+    on_error:   &mut dyn FnMut(E),
+) {
+    repeat_timeout_macro!(
+        timer,
+        op(),
+        (result) on_success(result);
+        (error) on_error(error);
+    )
+}
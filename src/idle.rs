@@ -0,0 +1,87 @@
+//! The crate-wide default for waiting between polls
+
+
+#[cfg(feature = "idle-yield")]
+use core::{
+    mem,
+    sync::atomic::{
+        AtomicPtr,
+        Ordering,
+    },
+};
+
+
+#[cfg(feature = "idle-yield")]
+static YIELD_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+
+/// Sets the function that yields to the RTOS between polls
+///
+/// Only available, if the `idle-yield` feature is enabled. With that feature,
+/// all wait loops in this crate call this function between two polls of an
+/// operation that isn't finished yet, unless the timer overrides
+/// [`TimeoutTimer::idle`]. Until a function has been set, they don't wait
+/// between polls at all.
+///
+/// # Example
+///
+/// ``` rust
+/// # fn taskYIELD() {}
+/// fn yield_to_rtos() {
+///     taskYIELD();
+/// }
+///
+/// embedded_timeout_macros::set_yield_hook(yield_to_rtos);
+/// ```
+///
+/// [`TimeoutTimer::idle`]: crate::TimeoutTimer::idle
+#[cfg(feature = "idle-yield")]
+pub fn set_yield_hook(hook: fn()) {
+    YIELD_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+
+/// Waits between two polls, as selected by the cargo features
+///
+/// With `idle-yield`, calls the yield hook, if one has been set. Otherwise,
+/// with `idle-wfi`, sleeps until the next interrupt. Without either feature,
+/// returns right away.
+#[inline(always)]
+pub fn idle() {
+    #[cfg(feature = "idle-yield")]
+    {
+        let hook = YIELD_HOOK.load(Ordering::Acquire);
+        if !hook.is_null() {
+            // Safe, as only `fn()` pointers are ever stored in `YIELD_HOOK`.
+            let hook: fn() = unsafe { mem::transmute(hook) };
+            hook();
+        }
+    }
+
+    #[cfg(all(feature = "idle-wfi", not(feature = "idle-yield")))]
+    wfi();
+}
+
+#[cfg(all(feature = "idle-wfi", not(feature = "idle-yield")))]
+#[inline(always)]
+fn wfi() {
+    #[cfg(any(
+        target_arch = "arm",
+        target_arch = "aarch64",
+        target_arch = "riscv32",
+        target_arch = "riscv64",
+    ))]
+    unsafe {
+        core::arch::asm!("wfi", options(nostack, preserves_flags));
+    }
+
+    // There's no portable equivalent, so on other targets, like those used
+    // for running tests on the host, we just spin.
+    #[cfg(not(any(
+        target_arch = "arm",
+        target_arch = "aarch64",
+        target_arch = "riscv32",
+        target_arch = "riscv64",
+    )))]
+    core::hint::spin_loop();
+}
@@ -0,0 +1,530 @@
+//! Per-transaction deadlines for shared buses
+//!
+//! When several drivers share a bus, a single device that hangs the bus (for
+//! example an I2C slave that holds SDA low) stalls every driver using it. The
+//! [`TimedBus`] wrapper in this module puts a deadline on every transaction,
+//! and can optionally run a recovery routine, if a transaction times out.
+//!
+//! [`TimedBus`] doesn't implement any bus traits itself. It owns the bus, and
+//! runs each transaction as a closure passed to [`TimedBus::transaction`],
+//! which gets the bus and returns `nb::Result`. To share it between drivers,
+//! put the [`TimedBus`] itself into a `RefCell` or mutex, and have each
+//! driver call [`TimedBus::transaction`] through it.
+//!
+//! Drivers that use the blocking bus traits of embedded-hal 0.2 can't be made
+//! to poll. For them, [`DeadlineSpi`] and [`DeadlineI2c`] implement the same
+//! traits, and check a deadline between the chunks of an SPI transfer, or
+//! after each I2C transaction, instead.
+
+
+use core::convert::Infallible;
+
+use embedded_hal::{
+    blocking::{
+        i2c,
+        spi,
+    },
+    spi::FullDuplex,
+};
+
+use crate::{
+    block_timeout,
+    Clock,
+    Deadline,
+    StartTimer,
+    TimeoutError,
+};
+
+
+/// A bus that enforces a deadline on every transaction
+///
+/// The timer is restarted with the configured duration at the beginning of
+/// each transaction. If the transaction doesn't finish before the timer
+/// expires, [`TimeoutError::Timeout`] is returned, after the recovery routine
+/// (see [`TimedBus::recover`]) has been run.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     io::bus::TimedBus,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = u32;
+/// #     fn start<T>(&mut self, _: T) { self.0 = 3; }
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # struct I2c { recovered: bool }
+/// #
+/// # impl I2c {
+/// #     fn poll_read(&mut self, _: u8) -> nb::Result<u8, ()> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// #     fn clock_out_stuck_slave(&mut self) { self.recovered = true; }
+/// # }
+/// #
+/// # let i2c = I2c { recovered: false };
+/// # let timer = Timer(0);
+///
+/// let mut bus = TimedBus::new(i2c, timer, 10_000)
+///     .recover(|i2c: &mut I2c| i2c.clock_out_stuck_slave());
+///
+/// // The device hangs the bus, so the transaction times out.
+/// let result = bus.transaction(|i2c| i2c.poll_read(0x48));
+///
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// assert!(bus.bus().recovered);
+/// ```
+#[derive(Debug)]
+pub struct TimedBus<B, T: StartTimer, R> {
+    bus:      B,
+    timer:    T,
+    duration: T::Duration,
+    recover:  R,
+}
+
+impl<B, T> TimedBus<B, T, NoRecovery>
+    where T: StartTimer
+{
+    /// Wraps a bus
+    ///
+    /// Every transaction is limited to `duration`. By default, nothing special
+    /// happens when a transaction times out, apart from the error being
+    /// returned.
+    pub fn new(bus: B, timer: T, duration: T::Duration) -> Self {
+        TimedBus {
+            bus,
+            timer,
+            duration,
+            recover: NoRecovery,
+        }
+    }
+}
+
+impl<B, T, R> TimedBus<B, T, R>
+    where T: StartTimer
+{
+    /// Sets the routine that recovers the bus after a timeout
+    ///
+    /// Any closure that takes a mutable reference to the bus can be used. A
+    /// typical recovery routine for I2C clocks out up to 9 pulses on SCL, until
+    /// the stuck slave releases SDA.
+    pub fn recover<R2>(self, recover: R2) -> TimedBus<B, T, R2>
+        where R2: Recover<B>
+    {
+        TimedBus {
+            bus:      self.bus,
+            timer:    self.timer,
+            duration: self.duration,
+            recover,
+        }
+    }
+
+    /// Provides access to the wrapped bus
+    ///
+    /// Operations done directly on the bus are not limited by a deadline.
+    pub fn bus(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Returns the wrapped bus and timer
+    pub fn free(self) -> (B, T) {
+        (self.bus, self.timer)
+    }
+}
+
+impl<B, T, R> TimedBus<B, T, R>
+    where
+        T:           StartTimer<Error = Infallible>,
+        T::Duration: Clone,
+        R:           Recover<B>,
+{
+    /// Runs a transaction, limited by the deadline
+    ///
+    /// `op` is polled until it returns something other than `WouldBlock`, or
+    /// until the deadline has passed.
+    pub fn transaction<F, V, E>(&mut self, mut op: F)
+        -> Result<V, TimeoutError<E>>
+        where F: FnMut(&mut B) -> nb::Result<V, E>
+    {
+        let result = block_timeout!(
+            &mut self.timer,
+            self.duration.clone(),
+            op(&mut self.bus)
+        );
+        let result = result.map_err(TimeoutError::from);
+
+        if let Err(TimeoutError::Timeout) = result {
+            self.recover.recover(&mut self.bus);
+        }
+
+        result
+    }
+
+    /// Transfers words over an SPI bus, limited by the deadline
+    ///
+    /// Sends each word in `words` and replaces it with the word received at
+    /// the same time. The deadline applies to the whole transfer, not to each
+    /// word.
+    pub fn transfer<W>(&mut self, words: &mut [W])
+        -> Result<(), TimeoutError<B::Error>>
+        where
+            B: FullDuplex<W>,
+            W: Copy,
+    {
+        let mut i = 0;
+        let mut sent = false;
+
+        self.transaction(|bus| {
+            while i < words.len() {
+                if !sent {
+                    bus.send(words[i])?;
+                    sent = true;
+                }
+
+                words[i] = bus.read()?;
+                sent = false;
+                i += 1;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+
+/// Recovers a bus after a transaction has timed out
+///
+/// Implemented for all closures that take a mutable reference to the bus.
+pub trait Recover<B> {
+    /// Recovers the bus
+    fn recover(&mut self, bus: &mut B);
+}
+
+impl<B, F> Recover<B> for F
+    where F: FnMut(&mut B)
+{
+    fn recover(&mut self, bus: &mut B) {
+        self(bus)
+    }
+}
+
+/// Doesn't do anything to recover the bus
+#[derive(Clone, Copy, Debug)]
+pub struct NoRecovery;
+
+impl<B> Recover<B> for NoRecovery {
+    fn recover(&mut self, _: &mut B) {}
+}
+
+
+/// Limits the blocking transfers of an SPI bus, using a deadline
+///
+/// Implements the blocking SPI traits of embedded-hal 0.2, for 8- and 16-bit
+/// words, so it can be handed to any driver that uses them. Each transfer is
+/// split into chunks of at most [`DeadlineSpi::chunk_size`] words, and the
+/// deadline is checked before each chunk. If it has passed, the transfer is
+/// aborted, and [`TimeoutError::Timeout`] is returned. A single chunk that
+/// hangs can't be interrupted, so keep chunks short, if the bus can hang while
+/// transferring.
+///
+/// The deadline is started with the configured duration at the beginning of
+/// every transfer.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::cell::Cell;
+///
+/// use embedded_hal::blocking::spi::Write;
+/// use embedded_timeout_macros::{
+///     io::bus::DeadlineSpi,
+///     TimeoutError,
+/// };
+/// #
+/// # #[derive(Clone, Copy)]
+/// # struct Clock<'r>(&'r Cell<u32>);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock<'_> {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0.get() }
+/// # }
+///
+/// /// A display that takes 10 ticks per 16 bytes
+/// struct Display<'r>(Clock<'r>);
+///
+/// impl Write<u8> for Display<'_> {
+///     type Error = ();
+///
+///     fn write(&mut self, words: &[u8]) -> Result<(), ()> {
+///         let now = (self.0).0.get();
+///         (self.0).0.set(now + 10 * words.len() as u32 / 16);
+///         Ok(())
+///     }
+/// }
+///
+/// let ticks = Cell::new(0);
+/// let clock = Clock(&ticks);
+/// let display = Display(clock);
+///
+/// let mut spi = DeadlineSpi::new(display, clock, 50).chunk_size(16);
+///
+/// assert_eq!(spi.write(&[0; 64]), Ok(()));
+/// assert_eq!(spi.write(&[0; 1024]), Err(TimeoutError::Timeout));
+/// ```
+#[derive(Debug)]
+pub struct DeadlineSpi<S, C: Clock> {
+    spi:        S,
+    clock:      C,
+    duration:   C::Ticks,
+    chunk_size: usize,
+}
+
+impl<S, C> DeadlineSpi<S, C>
+    where C: Clock
+{
+    /// Wraps an SPI bus
+    ///
+    /// Every transfer is limited to `duration` ticks of the clock. Chunks are
+    /// 32 words long, by default.
+    pub fn new(spi: S, clock: C, duration: C::Ticks) -> Self {
+        DeadlineSpi {
+            spi,
+            clock,
+            duration,
+            chunk_size: 32,
+        }
+    }
+
+    /// Sets the maximum number of words per chunk
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `chunk_size` is zero.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "Chunks must contain at least one word");
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Provides access to the wrapped bus
+    pub fn spi(&mut self) -> &mut S {
+        &mut self.spi
+    }
+
+    /// Returns the wrapped bus and clock
+    pub fn free(self) -> (S, C) {
+        (self.spi, self.clock)
+    }
+}
+
+// embedded-hal's blanket implementations for `FullDuplex` rule out an
+// implementation that is generic over the word type.
+macro_rules! impl_deadline_spi {
+    ($($word:ty),*) => {
+        $(
+            impl<S, C> spi::Write<$word> for DeadlineSpi<S, C>
+                where
+                    S: spi::Write<$word>,
+                    C: Clock,
+            {
+                type Error = TimeoutError<S::Error>;
+
+                fn write(&mut self, words: &[$word])
+                    -> Result<(), Self::Error>
+                {
+                    let mut deadline =
+                        Deadline::start(&mut self.clock, self.duration);
+
+                    for chunk in words.chunks(self.chunk_size) {
+                        if deadline.is_expired() {
+                            return Err(TimeoutError::Timeout);
+                        }
+
+                        self.spi.write(chunk)
+                            .map_err(TimeoutError::Other)?;
+                    }
+
+                    Ok(())
+                }
+            }
+
+            impl<S, C> spi::Transfer<$word> for DeadlineSpi<S, C>
+                where
+                    S: spi::Transfer<$word>,
+                    C: Clock,
+            {
+                type Error = TimeoutError<S::Error>;
+
+                fn transfer<'w>(&mut self, words: &'w mut [$word])
+                    -> Result<&'w [$word], Self::Error>
+                {
+                    let mut deadline =
+                        Deadline::start(&mut self.clock, self.duration);
+
+                    for chunk in words.chunks_mut(self.chunk_size) {
+                        if deadline.is_expired() {
+                            return Err(TimeoutError::Timeout);
+                        }
+
+                        // Like embedded-hal's default implementation, this
+                        // expects the received words in the buffer.
+                        self.spi.transfer(chunk)
+                            .map_err(TimeoutError::Other)?;
+                    }
+
+                    Ok(words)
+                }
+            }
+        )*
+    };
+}
+
+impl_deadline_spi!(u8, u16);
+
+
+/// Limits the blocking transactions of an I2C bus, using a deadline
+///
+/// Implements the blocking I2C traits of embedded-hal 0.2, so it can be handed
+/// to any driver that uses them. I2C transactions can't be split into chunks
+/// without changing their meaning on the bus, so the deadline is checked after
+/// each transaction. A transaction that finishes after the deadline has passed
+/// still returns its result, as it went through. Overrunning the deadline
+/// usually means that a slave stretches the clock for too long, though, so
+/// the next transaction isn't started, and returns [`TimeoutError::Timeout`]
+/// instead. The one after that is attempted again.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_hal::blocking::i2c::WriteRead;
+/// use embedded_timeout_macros::{
+///     io::bus::DeadlineI2c,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 150; self.0 }
+/// # }
+/// #
+/// # struct I2c;
+/// #
+/// # impl WriteRead for I2c {
+/// #     type Error = ();
+/// #     fn write_read(&mut self, _: u8, _: &[u8], _: &mut [u8])
+/// #         -> Result<(), ()>
+/// #     {
+/// #         Ok(())
+/// #     }
+/// # }
+///
+/// // The sensor stretches the clock for too long.
+/// let mut i2c = DeadlineI2c::new(I2c, Clock(0), 100);
+///
+/// // The transaction went through, even though it took too long...
+/// let mut buffer = [0; 2];
+/// assert_eq!(i2c.write_read(0x48, &[0x00], &mut buffer), Ok(()));
+///
+/// // ...but the next one isn't started.
+/// let result = i2c.write_read(0x48, &[0x00], &mut buffer);
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// ```
+#[derive(Debug)]
+pub struct DeadlineI2c<I, C: Clock> {
+    i2c:      I,
+    clock:    C,
+    duration: C::Ticks,
+    overrun:  bool,
+}
+
+impl<I, C> DeadlineI2c<I, C>
+    where C: Clock
+{
+    /// Wraps an I2C bus
+    ///
+    /// Every transaction is limited to `duration` ticks of the clock.
+    pub fn new(i2c: I, clock: C, duration: C::Ticks) -> Self {
+        DeadlineI2c {
+            i2c,
+            clock,
+            duration,
+            overrun: false,
+        }
+    }
+
+    /// Provides access to the wrapped bus
+    pub fn i2c(&mut self) -> &mut I {
+        &mut self.i2c
+    }
+
+    /// Returns the wrapped bus and clock
+    pub fn free(self) -> (I, C) {
+        (self.i2c, self.clock)
+    }
+
+    fn limit<F, E>(&mut self, transaction: F) -> Result<(), TimeoutError<E>>
+        where F: FnOnce(&mut I) -> Result<(), E>
+    {
+        if self.overrun {
+            self.overrun = false;
+            return Err(TimeoutError::Timeout);
+        }
+
+        let mut deadline = Deadline::start(&mut self.clock, self.duration);
+        let result = transaction(&mut self.i2c);
+        self.overrun = deadline.is_expired();
+
+        result.map_err(TimeoutError::Other)
+    }
+}
+
+impl<I, C> i2c::Write for DeadlineI2c<I, C>
+    where
+        I: i2c::Write,
+        C: Clock,
+{
+    type Error = TimeoutError<I::Error>;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.limit(|i2c| i2c.write(address, bytes))
+    }
+}
+
+impl<I, C> i2c::Read for DeadlineI2c<I, C>
+    where
+        I: i2c::Read,
+        C: Clock,
+{
+    type Error = TimeoutError<I::Error>;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8])
+        -> Result<(), Self::Error>
+    {
+        self.limit(|i2c| i2c.read(address, buffer))
+    }
+}
+
+impl<I, C> i2c::WriteRead for DeadlineI2c<I, C>
+    where
+        I: i2c::WriteRead,
+        C: Clock,
+{
+    type Error = TimeoutError<I::Error>;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8])
+        -> Result<(), Self::Error>
+    {
+        self.limit(|i2c| i2c.write_read(address, bytes, buffer))
+    }
+}
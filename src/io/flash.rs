@@ -0,0 +1,288 @@
+//! Deadlines for NOR flash operations
+//!
+//! Erasing or programming NOR flash can take a long time, and if something
+//! goes wrong (for example after a voltage dip), the flash might never report
+//! that it is done. The `NorFlash` traits from `embedded-storage` are blocking,
+//! so there's no way to put a deadline on them from the outside. Instead, the
+//! helpers in this module work with drivers that implement [`PollFlash`],
+//! which splits every operation into starting it and polling until the flash
+//! is no longer busy.
+//!
+//! If an operation times out, the error reports how far it got, so the caller
+//! can decide whether to retry the rest, or give up on the flash.
+
+
+use crate::{
+    bound::PollBound,
+    codes,
+    TimeoutTimer,
+};
+
+
+/// A NOR flash, whose operations can be started and then polled
+///
+/// Implemented by flash drivers. Offsets are in bytes, from the start of the
+/// flash.
+pub trait PollFlash {
+    /// The error that the flash can return
+    type Error;
+
+    /// The size of an erasable sector, in bytes
+    const SECTOR_SIZE: u32;
+
+    /// The number of bytes that can be programmed in one go
+    ///
+    /// Writes are split into chunks, which never cross a boundary of this
+    /// size.
+    const PAGE_SIZE: u32;
+
+    /// Reads bytes, starting at the given offset
+    ///
+    /// Returns `WouldBlock`, until all of `bytes` have been filled.
+    fn read(&mut self, offset: u32, bytes: &mut [u8])
+        -> nb::Result<(), Self::Error>;
+
+    /// Starts erasing the sector at the given offset
+    fn start_erase(&mut self, offset: u32) -> Result<(), Self::Error>;
+
+    /// Starts programming bytes, starting at the given offset
+    ///
+    /// `bytes` never crosses a page boundary.
+    fn start_write(&mut self, offset: u32, bytes: &[u8])
+        -> Result<(), Self::Error>;
+
+    /// Checks whether the last erase or write has finished
+    ///
+    /// Returns `WouldBlock`, while the flash is busy.
+    fn poll_busy(&mut self) -> nb::Result<(), Self::Error>;
+}
+
+
+/// Reads from the flash, until the timer expires
+///
+/// The timer must already be running.
+pub fn read<F, T>(flash: &mut F, timer: &mut T, offset: u32, bytes: &mut [u8])
+    -> Result<(), FlashError<F::Error>>
+    where
+        F: PollFlash,
+        T: TimeoutTimer,
+{
+    let mut bound = PollBound::new();
+
+    loop {
+        if timer.is_expired() || bound.exhausted() {
+            bound.expired();
+            return Err(FlashError::Timeout { completed: 0 });
+        }
+
+        match flash.read(offset, bytes) {
+            Ok(()) =>
+                return Ok(()),
+            Err(nb::Error::WouldBlock) =>
+                timer.idle(),
+            Err(nb::Error::Other(error)) =>
+                return Err(FlashError::Flash { error, completed: 0 }),
+        }
+    }
+}
+
+/// Erases the sectors from `from` up to, but not including, `to`
+///
+/// `from` and `to` must be aligned to [`PollFlash::SECTOR_SIZE`]. The timer
+/// must already be running, and limits the whole erase, not each sector. If
+/// the erase doesn't finish in time, the error reports the number of bytes
+/// that have been erased, starting at `from`.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::io::flash::{
+///     erase,
+///     FlashError,
+///     PollFlash,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         self.0 == 0
+/// #     }
+/// # }
+///
+/// /// A flash that hangs, while erasing the third sector
+/// struct Flash {
+///     erasing: u32,
+/// }
+///
+/// impl PollFlash for Flash {
+///     type Error = ();
+///
+///     const SECTOR_SIZE: u32 = 4096;
+///     const PAGE_SIZE:   u32 = 256;
+///
+///     fn read(&mut self, _: u32, _: &mut [u8]) -> nb::Result<(), ()> {
+///         Ok(())
+///     }
+///     fn start_erase(&mut self, offset: u32) -> Result<(), ()> {
+///         self.erasing = offset;
+///         Ok(())
+///     }
+///     fn start_write(&mut self, _: u32, _: &[u8]) -> Result<(), ()> {
+///         Ok(())
+///     }
+///     fn poll_busy(&mut self) -> nb::Result<(), ()> {
+///         if self.erasing == 8192 {
+///             return Err(nb::Error::WouldBlock);
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let mut flash = Flash { erasing: 0 };
+/// let mut timer = Timer(100);
+///
+/// let result = erase(&mut flash, &mut timer, 0, 16384);
+/// assert_eq!(result, Err(FlashError::Timeout { completed: 8192 }));
+/// ```
+///
+/// # Panics
+///
+/// Panics, if `from` or `to` are not aligned, or `from` is larger than `to`.
+pub fn erase<F, T>(flash: &mut F, timer: &mut T, from: u32, to: u32)
+    -> Result<(), FlashError<F::Error>>
+    where
+        F: PollFlash,
+        T: TimeoutTimer,
+{
+    assert!(from <= to, "Erase range must not end before it starts");
+    assert!(
+        from % F::SECTOR_SIZE == 0 && to % F::SECTOR_SIZE == 0,
+        "Erase range must be aligned to sectors"
+    );
+
+    let mut offset = from;
+
+    while offset < to {
+        let completed = offset - from;
+
+        if timer.is_expired() {
+            return Err(FlashError::Timeout { completed });
+        }
+        flash.start_erase(offset)
+            .map_err(|error| FlashError::Flash { error, completed })?;
+        wait_until_idle(flash, timer, completed)?;
+
+        offset += F::SECTOR_SIZE;
+    }
+
+    Ok(())
+}
+
+/// Programs bytes, starting at the given offset
+///
+/// The bytes are written in chunks, which never cross a page boundary (see
+/// [`PollFlash::PAGE_SIZE`]). The flash must have been erased before. The
+/// timer must already be running, and limits the whole write. If the write
+/// doesn't finish in time, the error reports the number of bytes that have
+/// been written.
+pub fn write<F, T>(flash: &mut F, timer: &mut T, offset: u32, bytes: &[u8])
+    -> Result<(), FlashError<F::Error>>
+    where
+        F: PollFlash,
+        T: TimeoutTimer,
+{
+    let mut written = 0;
+
+    while written < bytes.len() {
+        let completed = written as u32;
+        let address = offset + completed;
+
+        let page_left = F::PAGE_SIZE - address % F::PAGE_SIZE;
+        let len = (bytes.len() - written).min(page_left as usize);
+
+        if timer.is_expired() {
+            return Err(FlashError::Timeout { completed });
+        }
+        flash.start_write(address, &bytes[written..written + len])
+            .map_err(|error| FlashError::Flash { error, completed })?;
+        wait_until_idle(flash, timer, completed)?;
+
+        written += len;
+    }
+
+    Ok(())
+}
+
+
+fn wait_until_idle<F, T>(flash: &mut F, timer: &mut T, completed: u32)
+    -> Result<(), FlashError<F::Error>>
+    where
+        F: PollFlash,
+        T: TimeoutTimer,
+{
+    let mut bound = PollBound::new();
+
+    loop {
+        // The operation has been started already, so check the flash before
+        // the timer. If it finished in the meantime, that's a success.
+        match flash.poll_busy() {
+            Ok(()) =>
+                return Ok(()),
+            Err(nb::Error::WouldBlock) =>
+                (),
+            Err(nb::Error::Other(error)) =>
+                return Err(FlashError::Flash { error, completed }),
+        }
+
+        if timer.is_expired() || bound.exhausted() {
+            return Err(FlashError::Timeout { completed });
+        }
+
+        timer.idle();
+    }
+}
+
+
+/// An error returned by the functions in this module
+///
+/// `completed` is the number of bytes that have been erased or written
+/// successfully, before the error occurred. It is always zero for reads.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FlashError<E> {
+    /// The timer expired, before the operation finished
+    Timeout {
+        /// The number of bytes completed
+        completed: u32,
+    },
+
+    /// The flash returned an error
+    Flash {
+        /// The error returned by the flash
+        error: E,
+
+        /// The number of bytes completed
+        completed: u32,
+    },
+}
+
+impl<E> FlashError<E> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            FlashError::Timeout { .. } => codes::FLASH_TIMEOUT,
+            FlashError::Flash { .. }   => codes::FLASH_ERROR,
+        }
+    }
+
+    /// Returns the number of bytes completed, before the error occurred
+    pub fn completed(&self) -> u32 {
+        match *self {
+            FlashError::Timeout { completed }  => completed,
+            FlashError::Flash { completed, .. } => completed,
+        }
+    }
+}
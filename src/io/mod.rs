@@ -0,0 +1,514 @@
+//! Timeout-aware helpers for I/O peripherals
+//!
+//! This module is only available, if the `io` feature is enabled (which it is
+//! by default).
+
+
+#[cfg(feature = "eh0")]
+pub mod bus;
+
+pub mod flash;
+pub mod multiplex;
+pub mod script;
+pub mod tasklet;
+pub mod usb;
+
+
+use core::convert::Infallible;
+
+#[cfg(feature = "eh0")]
+use embedded_hal::serial;
+
+#[cfg(feature = "eh0")]
+use crate::StartTimer;
+use crate::{
+    bound::PollBound,
+    codes,
+    Delay,
+    TimeoutError,
+    TimeoutTimer,
+};
+
+
+/// Reads from a serial port, until one of several patterns has been received
+///
+/// This is useful for talking to modems and other devices that are controlled
+/// using AT commands, where the response can be one of several strings (like
+/// `OK`, `ERROR`, or `+CME ERROR:`). Returns the index of the pattern that was
+/// received.
+///
+/// Two timeouts apply:
+///
+/// - `total` limits the whole wait. It must already be running.
+/// - `inter_byte` limits the time between two bytes. It is started with
+///   `inter_byte_duration` after each byte received, so it only applies once
+///   the first byte has been received.
+///
+/// The most recent `L` bytes are kept in a buffer, to match them against the
+/// patterns. Patterns that are longer than `L` can never match.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::io::{
+///     wait_for_patterns,
+///     PatternError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = u32;
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # struct Serial(&'static [u8]);
+/// #
+/// # impl embedded_hal::serial::Read<u8> for Serial {
+/// #     type Error = ();
+/// #     fn read(&mut self) -> nb::Result<u8, ()> {
+/// #         let (&byte, rest) = self.0.split_first()
+/// #             .ok_or(nb::Error::WouldBlock)?;
+/// #         self.0 = rest;
+/// #         Ok(byte)
+/// #     }
+/// # }
+/// #
+/// # let mut serial = Serial(b"AT+CSQ\r\n+CSQ: 20,0\r\n\r\nOK\r\n");
+/// # let mut total = Timer;
+/// # let mut inter_byte = Timer;
+///
+/// const OK:    usize = 0;
+/// const ERROR: usize = 1;
+///
+/// let result = wait_for_patterns::<_, _, _, 16>(
+///     &mut serial,
+///     &mut total,
+///     &mut inter_byte,
+///     10_000,
+///     &[b"\r\nOK\r\n", b"\r\nERROR\r\n"],
+/// );
+///
+/// assert_eq!(result, Ok(OK));
+/// ```
+#[cfg(feature = "eh0")]
+pub fn wait_for_patterns<S, T, U, const L: usize>(
+    serial:              &mut S,
+    total:               &mut T,
+    inter_byte:          &mut U,
+    inter_byte_duration: U::Duration,
+    patterns:            &[&[u8]],
+)
+    -> Result<usize, PatternError<S::Error>>
+    where
+        S:           serial::Read<u8>,
+        T:           TimeoutTimer,
+        U:           StartTimer<Error = Infallible>,
+        U::Duration: Clone,
+{
+    let mut buffer = [0; L];
+    let mut len = 0;
+    let mut bound = PollBound::new();
+
+    loop {
+        if total.is_expired() || bound.exhausted() {
+            bound.expired();
+            return Err(PatternError::Timeout);
+        }
+        if len > 0 && inter_byte.is_expired() {
+            return Err(PatternError::InterByteTimeout);
+        }
+
+        let byte = match serial.read() {
+            Ok(byte) =>
+                byte,
+            Err(nb::Error::WouldBlock) => {
+                total.idle();
+                continue;
+            }
+            Err(nb::Error::Other(error)) =>
+                return Err(PatternError::Serial(error)),
+        };
+
+        match inter_byte.try_start(inter_byte_duration.clone()) {
+            Ok(()) => (),
+            Err(error) => match error {},
+        }
+
+        if L == 0 {
+            continue;
+        }
+        if len == L {
+            buffer.copy_within(1.., 0);
+            len -= 1;
+        }
+        buffer[len] = byte;
+        len += 1;
+
+        let received = &buffer[..len];
+        for (i, pattern) in patterns.iter().enumerate() {
+            if !pattern.is_empty() && received.ends_with(pattern) {
+                return Ok(i);
+            }
+        }
+    }
+}
+
+
+/// Drives a network stack, until a condition becomes true
+///
+/// Network stacks like `smoltcp` need to be polled regularly to make progress.
+/// This function alternates between polling the stack (using `poll`) and
+/// checking whether whatever we're waiting for is ready (using `ready`), for
+/// example whether a socket is connected or data is available. `ready` returns
+/// `Some`, once that is the case, and its value is returned.
+///
+/// Between iterations, `delay` is used to wait for `pace_us` microseconds, so
+/// the stack isn't hammered more often than necessary. Pass zero, to poll as
+/// fast as possible.
+///
+/// Errors returned by `poll` end the wait immediately.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     io::poll_until,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # struct Delay;
+/// #
+/// # impl embedded_hal::blocking::delay::DelayUs<u32> for Delay {
+/// #     fn delay_us(&mut self, _: u32) {}
+/// # }
+/// #
+/// # struct Stack { polls: u32 }
+/// # impl Stack {
+/// #     fn poll(&mut self) -> Result<(), ()> { self.polls += 1; Ok(()) }
+/// #     fn is_connected(&self) -> bool { self.polls >= 3 }
+/// # }
+/// #
+/// # let mut timer = Timer;
+/// # let mut delay = Delay;
+/// # let mut stack = Stack { polls: 0 };
+/// use core::cell::RefCell;
+///
+/// let stack = RefCell::new(stack);
+///
+/// let result: Result<(), TimeoutError<()>> = poll_until(
+///     &mut timer,
+///     &mut delay,
+///     1_000,
+///     || stack.borrow_mut().poll(),
+///     || if stack.borrow().is_connected() { Some(()) } else { None },
+/// );
+///
+/// assert_eq!(result, Ok(()));
+/// ```
+pub fn poll_until<T, D, P, R, V, E>(
+    timer:     &mut T,
+    delay:     &mut D,
+    pace_us:   u32,
+    mut poll:  P,
+    mut ready: R,
+)
+    -> Result<V, TimeoutError<E>>
+    where
+        T: TimeoutTimer,
+        D: Delay,
+        P: FnMut() -> Result<(), E>,
+        R: FnMut() -> Option<V>,
+{
+    let mut bound = PollBound::new();
+
+    loop {
+        poll()
+            .map_err(TimeoutError::Other)?;
+
+        if let Some(value) = ready() {
+            return Ok(value);
+        }
+        if timer.is_expired() || bound.exhausted() {
+            return Err(TimeoutError::Timeout);
+        }
+
+        if pace_us > 0 {
+            Delay::delay_us(delay, pace_us);
+        }
+    }
+}
+
+
+/// Timing parameters of a Modbus RTU serial line
+///
+/// Modbus RTU frames are delimited by silence on the line. A gap of 1.5
+/// character times within a frame is an error, and a gap of 3.5 character times
+/// marks the end of a frame. A character is 11 bits long (start bit, 8 data
+/// bits, parity or second stop bit, stop bit).
+///
+/// Above 19200 baud, the specification fixes the gaps at 750 µs and 1750 µs,
+/// respectively, which [`FrameTiming::for_baud`] takes into account.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::io::FrameTiming;
+///
+/// let timing = FrameTiming::for_baud(9600);
+/// assert_eq!(timing.t1_5_us, 1719);
+/// assert_eq!(timing.t3_5_us, 4011);
+///
+/// let timing = FrameTiming::for_baud(115_200);
+/// assert_eq!(timing.t1_5_us, 750);
+/// assert_eq!(timing.t3_5_us, 1750);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FrameTiming {
+    /// The maximum gap between two characters of a frame, in microseconds
+    pub t1_5_us: u32,
+
+    /// The minimum gap between two frames, in microseconds
+    pub t3_5_us: u32,
+}
+
+impl FrameTiming {
+    /// Computes the timing parameters for the given baud rate
+    ///
+    /// Results are rounded up to the next microsecond.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `baud` is zero.
+    pub fn for_baud(baud: u32) -> Self {
+        assert!(baud > 0, "Baud rate must not be zero");
+
+        if baud > 19_200 {
+            return FrameTiming {
+                t1_5_us: 750,
+                t3_5_us: 1750,
+            };
+        }
+
+        // The bit counts are multiplied by 2, to represent 1.5 and 3.5
+        // characters as integers.
+        let gap = |half_chars: u32| {
+            let us = half_chars * 11 * 1_000_000 / 2;
+            us / baud + (us % baud != 0) as u32
+        };
+
+        FrameTiming {
+            t1_5_us: gap(3),
+            t3_5_us: gap(7),
+        }
+    }
+}
+
+
+/// Reads a Modbus RTU frame from a serial port
+///
+/// Waits for the first byte of a frame, then reads bytes into `buffer`, until
+/// the line has been silent for the frame gap. Returns the length of the frame.
+///
+/// Three timers are used:
+///
+/// - `response` limits the time until the first byte of the frame has been
+///   received. It must already be running. Once the frame has started, it is
+///   no longer checked, so a slow frame is never cut off.
+/// - `char_gap` detects an incomplete frame. It is started with
+///   `char_gap_duration` after each byte received, which should correspond to
+///   [`FrameTiming::t1_5_us`] of the baud rate in use. If it has expired by
+///   the time the next byte of the frame arrives, [`FrameError::Gap`] is
+///   returned, as the Modbus specification requires such a frame to be
+///   discarded.
+/// - `gap` detects the end of the frame. It is started with `gap_duration`
+///   after each byte received, which should correspond to
+///   [`FrameTiming::t3_5_us`] of the baud rate in use.
+///
+/// This function doesn't check the CRC or the contents of the frame.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::io::{
+///     read_rtu_frame,
+///     FrameTiming,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = u32;
+/// #     fn start<T>(&mut self, _: T) { self.0 = 0; }
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 += 1;
+/// #         if self.0 > 8 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # struct Serial(&'static [u8]);
+/// #
+/// # impl embedded_hal::serial::Read<u8> for Serial {
+/// #     type Error = ();
+/// #     fn read(&mut self) -> nb::Result<u8, ()> {
+/// #         let (&byte, rest) = self.0.split_first()
+/// #             .ok_or(nb::Error::WouldBlock)?;
+/// #         self.0 = rest;
+/// #         Ok(byte)
+/// #     }
+/// # }
+/// #
+/// # let mut serial = Serial(&[0x11, 0x03, 0x02, 0x00, 0x2a, 0x39, 0x9f]);
+/// # let mut response = Timer(0);
+/// # let mut char_gap = Timer(0);
+/// # let mut gap = Timer(0);
+///
+/// let timing = FrameTiming::for_baud(19_200);
+///
+/// let mut buffer = [0; 256];
+/// let len = read_rtu_frame(
+///     &mut serial,
+///     &mut response,
+///     &mut char_gap,
+///     timing.t1_5_us,
+///     &mut gap,
+///     timing.t3_5_us,
+///     &mut buffer,
+/// );
+///
+/// assert_eq!(len, Ok(7));
+/// ```
+#[cfg(feature = "eh0")]
+pub fn read_rtu_frame<S, T, U, V>(
+    serial:            &mut S,
+    response:          &mut T,
+    char_gap:          &mut V,
+    char_gap_duration: V::Duration,
+    gap:               &mut U,
+    gap_duration:      U::Duration,
+    buffer:            &mut [u8],
+)
+    -> Result<usize, FrameError<S::Error>>
+    where
+        S:           serial::Read<u8>,
+        T:           TimeoutTimer,
+        U:           StartTimer<Error = Infallible>,
+        U::Duration: Clone,
+        V:           StartTimer<Error = Infallible>,
+        V::Duration: Clone,
+{
+    let mut len = 0;
+    let mut bound = PollBound::new();
+
+    loop {
+        if (len == 0 && response.is_expired()) || bound.exhausted() {
+            bound.expired();
+            return Err(FrameError::Timeout);
+        }
+        if len > 0 && gap.is_expired() {
+            return Ok(len);
+        }
+
+        let byte = match serial.read() {
+            Ok(byte) =>
+                byte,
+            Err(nb::Error::WouldBlock) => {
+                gap.idle();
+                continue;
+            }
+            Err(nb::Error::Other(error)) =>
+                return Err(FrameError::Serial(error)),
+        };
+
+        if len > 0 && char_gap.is_expired() {
+            return Err(FrameError::Gap);
+        }
+
+        match char_gap.try_start(char_gap_duration.clone()) {
+            Ok(()) => (),
+            Err(error) => match error {},
+        }
+        match gap.try_start(gap_duration.clone()) {
+            Ok(()) => (),
+            Err(error) => match error {},
+        }
+
+        let slot = buffer.get_mut(len)
+            .ok_or(FrameError::Overflow)?;
+        *slot = byte;
+        len += 1;
+    }
+}
+
+
+/// An error returned by [`wait_for_patterns`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PatternError<E = Infallible> {
+    /// None of the patterns was received before the total timeout
+    Timeout,
+
+    /// The time between two bytes exceeded the inter-byte timeout
+    InterByteTimeout,
+
+    /// The serial port returned an error
+    Serial(E),
+}
+
+impl<E> PatternError<E> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            PatternError::Timeout          => codes::PATTERN_TIMEOUT,
+            PatternError::InterByteTimeout => codes::PATTERN_INTER_BYTE,
+            PatternError::Serial(_)        => codes::PATTERN_SERIAL,
+        }
+    }
+}
+
+
+/// An error returned by [`read_rtu_frame`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameError<E = Infallible> {
+    /// No frame was started before the response timeout
+    Timeout,
+
+    /// The frame didn't fit into the buffer
+    Overflow,
+
+    /// The gap between two characters of the frame was too long
+    ///
+    /// The frame is incomplete and should be discarded.
+    Gap,
+
+    /// The serial port returned an error
+    Serial(E),
+}
+
+impl<E> FrameError<E> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            FrameError::Timeout   => codes::FRAME_TIMEOUT,
+            FrameError::Overflow  => codes::FRAME_OVERFLOW,
+            FrameError::Gap       => codes::FRAME_GAP,
+            FrameError::Serial(_) => codes::FRAME_SERIAL,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Polling several operations at once, with different priorities
+//!
+//! Sometimes a single wait loop needs to service several operations at once,
+//! for example a fault flag and a few sensors, under the same deadline. These
+//! operations are not necessarily equally important. [`poll_weighted`] polls
+//! each of them in proportion to its weight, so a latency-critical operation
+//! can be polled more often than background work.
+//!
+//! Like the rest of the [`io`](crate::io) module, this module is only
+//! available, if the `io` feature is enabled.
+
+
+use crate::{
+    bound::PollBound,
+    TimedOperation,
+    TimeoutError,
+    TimeoutTimer,
+};
+
+
+/// An operation that is polled by [`poll_weighted`], along with its weight
+///
+/// The operation can be anything that implements [`TimedOperation`], including
+/// closures that return `nb::Result`.
+pub struct Source<'r, T, E> {
+    op:      &'r mut dyn TimedOperation<Output = T, Error = E>,
+    weight:  u16,
+    current: i32,
+}
+
+impl<'r, T, E> Source<'r, T, E> {
+    /// Creates a source with the given weight
+    ///
+    /// Operations are polled in proportion to their weight. An operation with
+    /// a weight of zero is never polled.
+    pub fn new(
+        weight: u16,
+        op:     &'r mut dyn TimedOperation<Output = T, Error = E>,
+    )
+        -> Self
+    {
+        Source {
+            op,
+            weight,
+            current: 0,
+        }
+    }
+
+    /// Returns the weight of the source
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+}
+
+
+/// Polls several operations, until one of them finishes, or the timer expires
+///
+/// Uses a smooth weighted round-robin schedule: Over any stretch of polls,
+/// each operation is polled in proportion to its weight, and polls of the
+/// heavier operations are spread out between those of the lighter ones,
+/// instead of being bunched together. This keeps the latency of a heavily
+/// weighted operation low, no matter how many others there are.
+///
+/// Returns the index of the operation that finished first, along with its
+/// result. If an operation returns an error, waiting ends with that error.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     io::multiplex::{
+///         poll_weighted,
+///         Source,
+///     },
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 -= 1;
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer(11);
+/// use core::cell::Cell;
+///
+/// const FAULT:    usize = 0;
+/// const SENSOR_A: usize = 1;
+/// const SENSOR_B: usize = 2;
+///
+/// let polls = [Cell::new(0), Cell::new(0), Cell::new(0)];
+/// let poll = |i: usize| {
+///     polls[i].set(polls[i].get() + 1);
+///     Err::<(), _>(nb::Error::<()>::WouldBlock)
+/// };
+///
+/// let mut fault    = || poll(FAULT);
+/// let mut sensor_a = || poll(SENSOR_A);
+/// let mut sensor_b = || poll(SENSOR_B);
+///
+/// let result = poll_weighted(&mut timer, &mut [
+///     Source::new(8, &mut fault),
+///     Source::new(1, &mut sensor_a),
+///     Source::new(1, &mut sensor_b),
+/// ]);
+///
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// assert_eq!(polls.map(|polls| polls.get()), [8, 1, 1]);
+/// ```
+pub fn poll_weighted<T, E, Tm>(
+    timer:   &mut Tm,
+    sources: &mut [Source<'_, T, E>],
+)
+    -> Result<(usize, T), TimeoutError<E>>
+    where Tm: TimeoutTimer + ?Sized
+{
+    let total: i32 = sources.iter()
+        .map(|source| source.weight as i32)
+        .sum();
+
+    for source in sources.iter_mut() {
+        source.current = 0;
+    }
+
+    let mut bound = PollBound::new();
+
+    loop {
+        if timer.is_expired() || bound.exhausted() {
+            bound.expired();
+            return Err(TimeoutError::Timeout);
+        }
+        if total == 0 {
+            continue;
+        }
+
+        let mut next = 0;
+        for i in 0..sources.len() {
+            sources[i].current += sources[i].weight as i32;
+            if sources[i].current > sources[next].current {
+                next = i;
+            }
+        }
+
+        let source = &mut sources[next];
+        source.current -= total;
+
+        match source.op.poll() {
+            Ok(value) =>
+                return Ok((next, value)),
+            Err(nb::Error::WouldBlock) =>
+                timer.idle(),
+            Err(nb::Error::Other(error)) =>
+                return Err(TimeoutError::Other(error)),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Running initialization sequences, step by step
+//!
+//! Displays, codecs, and many other devices are initialized by sending them a
+//! fixed sequence of commands, with delays in between, and waiting for the
+//! device to confirm some of them. [`run_script`] executes such a sequence
+//! from a table of [`Step`]s, with a timeout for each confirmation and a
+//! deadline for the whole sequence. If something goes wrong, the error tells
+//! which step failed.
+
+
+use core::convert::Infallible;
+
+use crate::{
+    bound::PollBound,
+    codes,
+    Delay,
+    StartTimer,
+    TimeoutTimer,
+};
+
+
+/// A step of an initialization sequence
+///
+/// `C` is the type of the commands, and `V` the type of the verifications.
+/// What they are depends on the device. For a display, a command could be a
+/// command byte, followed by its parameters, and a verification could be a
+/// status bit to wait for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Step<C, V, U> {
+    /// Sends a command to the device
+    Send(C),
+
+    /// Waits for the given number of microseconds
+    Delay(u32),
+
+    /// Polls a verification, until it succeeds, or the timeout expires
+    Verify {
+        /// What to verify
+        check: V,
+
+        /// The timeout of this step
+        timeout: U,
+    },
+}
+
+
+/// Runs an initialization sequence
+///
+/// Executes `steps` in order. `send` is called for [`Step::Send`], and
+/// `verify` is polled for [`Step::Verify`], until it returns something other
+/// than `WouldBlock`. Both receive `target`, which is whatever they need
+/// mutable access to, like the driver of the device.
+///
+/// `step_timer` is started with the timeout of each verification. `deadline`
+/// limits the whole sequence, and is checked before each step, as well as
+/// while verifying. It must already be running.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::io::script::{
+///     run_script,
+///     ScriptError,
+///     Step,
+/// };
+/// #
+/// # struct Deadline;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Deadline {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         self.0 == 0
+/// #     }
+/// # }
+/// #
+/// # impl embedded_timeout_macros::StartTimer for Timer {
+/// #     type Duration = u32;
+/// #     type Error    = core::convert::Infallible;
+/// #     fn try_start(&mut self, duration: u32)
+/// #         -> Result<(), core::convert::Infallible>
+/// #     {
+/// #         self.0 = duration;
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # struct Delay;
+/// #
+/// # impl embedded_timeout_macros::Delay for Delay {
+/// #     fn delay_us(&mut self, _: u32) {}
+/// # }
+///
+/// /// A display that never finishes waking up
+/// struct Display {
+///     sent: Vec<u8>,
+/// }
+///
+/// const SLEEP_OUT:  u8 = 0x11;
+/// const DISPLAY_ON: u8 = 0x29;
+///
+/// const INIT: &[Step<u8, (), u32>] = &[
+///     Step::Send(SLEEP_OUT),
+///     Step::Delay(5_000),
+///     Step::Verify { check: (), timeout: 10 },
+///     Step::Send(DISPLAY_ON),
+/// ];
+///
+/// let mut display = Display { sent: Vec::new() };
+///
+/// let result = run_script(
+///     &mut display,
+///     INIT,
+///     &mut Deadline,
+///     &mut Timer(0),
+///     &mut Delay,
+///     |display: &mut Display, &command| {
+///         display.sent.push(command);
+///         Ok::<_, ()>(())
+///     },
+///     |_, _| Err(nb::Error::WouldBlock),
+/// );
+///
+/// assert_eq!(result, Err(ScriptError::Timeout { step: 2 }));
+/// assert_eq!(display.sent, [SLEEP_OUT]);
+/// ```
+pub fn run_script<D, C, V, T, U, L, S, P, E>(
+    target:     &mut D,
+    steps:      &[Step<C, V, U::Duration>],
+    deadline:   &mut T,
+    step_timer: &mut U,
+    delay:      &mut L,
+    mut send:   S,
+    mut verify: P,
+)
+    -> Result<(), ScriptError<E>>
+    where
+        T:           TimeoutTimer,
+        U:           StartTimer<Error = Infallible>,
+        U::Duration: Clone,
+        L:           Delay,
+        S:           FnMut(&mut D, &C) -> Result<(), E>,
+        P:           FnMut(&mut D, &V) -> nb::Result<(), E>,
+{
+    for (step, action) in steps.iter().enumerate() {
+        if deadline.is_expired() {
+            return Err(ScriptError::Deadline { step });
+        }
+
+        match action {
+            Step::Send(command) => {
+                send(target, command)
+                    .map_err(|error| ScriptError::Other { step, error })?;
+            }
+            Step::Delay(us) => {
+                Delay::delay_us(delay, *us);
+            }
+            Step::Verify { check, timeout } => {
+                match step_timer.try_start(timeout.clone()) {
+                    Ok(()) => (),
+                    Err(error) => match error {},
+                }
+
+                let mut bound = PollBound::new();
+
+                loop {
+                    match verify(target, check) {
+                        Ok(()) =>
+                            break,
+                        Err(nb::Error::WouldBlock) =>
+                            (),
+                        Err(nb::Error::Other(error)) =>
+                            return Err(ScriptError::Other { step, error }),
+                    }
+
+                    if deadline.is_expired() {
+                        return Err(ScriptError::Deadline { step });
+                    }
+                    if step_timer.is_expired() || bound.exhausted() {
+                        return Err(ScriptError::Timeout { step });
+                    }
+
+                    step_timer.idle();
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+/// An error returned by [`run_script`]
+///
+/// `step` is the index of the step that failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScriptError<E> {
+    /// The deadline for the whole sequence has passed
+    Deadline {
+        /// The index of the step
+        step: usize,
+    },
+
+    /// A verification didn't succeed before the timeout of its step
+    Timeout {
+        /// The index of the step
+        step: usize,
+    },
+
+    /// Sending a command, or verifying, returned an error
+    Other {
+        /// The index of the step
+        step: usize,
+
+        /// The error that was returned
+        error: E,
+    },
+}
+
+impl<E> ScriptError<E> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            ScriptError::Deadline { .. } => codes::SCRIPT_DEADLINE,
+            ScriptError::Timeout { .. }  => codes::SCRIPT_TIMEOUT,
+            ScriptError::Other { .. }    => codes::SCRIPT_OTHER,
+        }
+    }
+
+    /// Returns the index of the step that failed
+    pub fn step(&self) -> usize {
+        match *self {
+            ScriptError::Deadline { step }  => step,
+            ScriptError::Timeout { step }   => step,
+            ScriptError::Other { step, .. } => step,
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Running several timeout-bound operations side by side
+//!
+//! Firmware built around a main loop often needs several things to happen at
+//! once, each limited by its own timeout, like waiting for a modem while a
+//! sensor is being read. [`TaskletRunner`] holds a fixed number of such
+//! operations, along with their timers, and steps each of them once whenever
+//! the main loop calls [`TaskletRunner::poll`]. Operations that have finished
+//! or timed out are reported through the returned iterator, and removed.
+//!
+//! Like the rest of the [`io`](crate::io) module, this module is only
+//! available, if the `io` feature is enabled.
+
+
+use crate::{
+    TimedOperation,
+    TimeoutError,
+    TimeoutTimer,
+};
+
+
+/// Runs up to `N` operations, each limited by its own timer
+///
+/// The operations can be anything that implements [`TimedOperation`],
+/// including closures that return `nb::Result`. All of them must have the
+/// same output and error types, and all timers the same type.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     io::tasklet::TaskletRunner,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         self.0 == 0
+/// #     }
+/// # }
+///
+/// let mut polls = 0;
+/// let mut sensor = || {
+///     polls += 1;
+///     if polls < 3 { Err(nb::Error::WouldBlock) } else { Ok(21) }
+/// };
+/// let mut modem = || Err::<u16, nb::Error<()>>(nb::Error::WouldBlock);
+///
+/// let mut runner = TaskletRunner::<_, _, _, 4>::new();
+/// let sensor_id = runner.spawn(Timer(10), &mut sensor).unwrap();
+/// let modem_id  = runner.spawn(Timer(5), &mut modem).unwrap();
+///
+/// let mut results = Vec::new();
+/// while !runner.is_empty() {
+///     // Do other work here.
+///
+///     for (id, result) in runner.poll() {
+///         results.push((id, result));
+///     }
+/// }
+///
+/// assert_eq!(results, [
+///     (sensor_id, Ok(21)),
+///     (modem_id, Err(TimeoutError::Timeout)),
+/// ]);
+/// ```
+pub struct TaskletRunner<'r, T, O, E, const N: usize> {
+    tasklets: [Option<Tasklet<'r, T, O, E>>; N],
+    next:     usize,
+}
+
+impl<'r, T, O, E, const N: usize> TaskletRunner<'r, T, O, E, N>
+    where T: TimeoutTimer
+{
+    /// Creates an empty runner
+    pub fn new() -> Self {
+        TaskletRunner {
+            tasklets: core::array::from_fn(|_| None),
+            next:     0,
+        }
+    }
+
+    /// Adds an operation, limited by the given timer
+    ///
+    /// The timer must already be running. Returns the id that the operation
+    /// is reported under, or an error, if the runner is full.
+    pub fn spawn(&mut self,
+        timer: T,
+        op:    &'r mut dyn TimedOperation<Output = O, Error = E>,
+    )
+        -> Result<TaskId, Full>
+    {
+        let i = self.tasklets.iter()
+            .position(|tasklet| tasklet.is_none())
+            .ok_or(Full)?;
+
+        self.tasklets[i] = Some(Tasklet {
+            timer,
+            op,
+        });
+
+        Ok(TaskId(i))
+    }
+
+    /// Removes an operation without finishing it
+    ///
+    /// Returns its timer, or `None`, if there's no operation with that id.
+    pub fn cancel(&mut self, id: TaskId) -> Option<T> {
+        self.tasklets.get_mut(id.0)?
+            .take()
+            .map(|tasklet| tasklet.timer)
+    }
+
+    /// Steps every operation once
+    ///
+    /// Each operation is stepped when the returned iterator gets to it. Its
+    /// timer is checked first, then it is polled, like the macros do. The
+    /// iterator yields the operations that have finished, along with their
+    /// results, and removes them from the runner.
+    ///
+    /// If the iterator is dropped early, the next call continues where it
+    /// left off, so no operation is starved.
+    pub fn poll(&mut self) -> Completions<'_, 'r, T, O, E, N> {
+        Completions {
+            runner:    self,
+            remaining: N,
+        }
+    }
+
+    /// Returns the number of operations that haven't finished yet
+    pub fn len(&self) -> usize {
+        self.tasklets.iter()
+            .filter(|tasklet| tasklet.is_some())
+            .count()
+    }
+
+    /// Indicates whether all operations have finished
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, O, E, const N: usize> Default for TaskletRunner<'_, T, O, E, N>
+    where T: TimeoutTimer
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Identifies an operation in a [`TaskletRunner`]
+///
+/// Ids of finished operations are reused for operations spawned later.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TaskId(usize);
+
+impl TaskId {
+    /// Returns the index of the slot that holds the operation
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+
+/// The operations that finished during a pass of [`TaskletRunner::poll`]
+pub struct Completions<'a, 'r, T, O, E, const N: usize> {
+    runner:    &'a mut TaskletRunner<'r, T, O, E, N>,
+    remaining: usize,
+}
+
+impl<T, O, E, const N: usize> Iterator for Completions<'_, '_, T, O, E, N>
+    where T: TimeoutTimer
+{
+    type Item = (TaskId, Result<O, TimeoutError<E>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+
+            let i = self.runner.next;
+            self.runner.next = (i + 1) % N;
+
+            let slot = &mut self.runner.tasklets[i];
+            let result = match slot {
+                Some(tasklet) => tasklet.step(),
+                None          => continue,
+            };
+
+            if let Some(result) = result {
+                *slot = None;
+                return Some((TaskId(i), result));
+            }
+        }
+
+        None
+    }
+}
+
+
+/// Returned by [`TaskletRunner::spawn`], if there's no room for another
+/// operation
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Full;
+
+
+struct Tasklet<'r, T, O, E> {
+    timer: T,
+    op:    &'r mut dyn TimedOperation<Output = O, Error = E>,
+}
+
+impl<T, O, E> Tasklet<'_, T, O, E>
+    where T: TimeoutTimer
+{
+    fn step(&mut self) -> Option<Result<O, TimeoutError<E>>> {
+        if self.timer.is_expired() {
+            return Some(Err(TimeoutError::Timeout));
+        }
+
+        match self.op.poll() {
+            Ok(output) =>
+                Some(Ok(output)),
+            Err(nb::Error::WouldBlock) =>
+                None,
+            Err(nb::Error::Other(error)) =>
+                Some(Err(TimeoutError::Other(error))),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Bringing up USB devices, with a timeout for each phase
+//!
+//! A USB device stack like `usb-device` has to be polled regularly, while
+//! waiting for anything to happen: for the host to enumerate the device, for
+//! a class to be configured, or for data to arrive on an endpoint. Each of
+//! these phases needs a timeout of its own. [`UsbPoller`] keeps the poll
+//! function and the timer together, so every phase becomes one call to
+//! [`UsbPoller::phase`].
+
+
+use core::convert::Infallible;
+
+use crate::{
+    bound::PollBound,
+    StartTimer,
+    TimeoutError,
+};
+
+
+/// Polls a USB device while waiting, limiting each phase by a timeout
+///
+/// `poll` is called with the device, which is whatever the poll function
+/// needs mutable access to. With `usb-device`, that is typically a struct or
+/// tuple, holding the `UsbDevice` and the classes, and `poll` calls
+/// `UsbDevice::poll` with the classes. It returns whether there were any
+/// events, like `UsbDevice::poll` does.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     io::usb::UsbPoller,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = u32;
+/// #     fn start<T>(&mut self, _: T) { self.0 = 100; }
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # #[derive(PartialEq)]
+/// # enum State { Default, Configured }
+/// #
+/// # struct UsbDevice { polls: u32 }
+/// #
+/// # impl UsbDevice {
+/// #     fn poll(&mut self, _: &mut [&mut SerialPort]) -> bool {
+/// #         self.polls += 1;
+/// #         true
+/// #     }
+/// #     fn state(&self) -> State {
+/// #         if self.polls >= 3 { State::Configured } else { State::Default }
+/// #     }
+/// # }
+/// #
+/// # struct SerialPort;
+/// #
+/// # impl SerialPort {
+/// #     fn read(&mut self, _: &mut [u8]) -> Result<usize, ()> { Err(()) }
+/// # }
+/// #
+/// # let timer = Timer(0);
+///
+/// let mut device = (UsbDevice { polls: 0 }, SerialPort);
+///
+/// let mut usb = UsbPoller::new(
+///     timer,
+///     |(usb_dev, serial): &mut (UsbDevice, SerialPort)| {
+///         usb_dev.poll(&mut [serial])
+///     },
+/// );
+///
+/// // Give the host 1 second to enumerate the device.
+/// let result = usb.phase(&mut device, 1_000_000, |(usb_dev, _)| {
+///     (usb_dev.state() == State::Configured).then_some(())
+/// });
+/// assert_eq!(result, Ok(()));
+///
+/// // The host doesn't send anything within 100 ms.
+/// let mut buf = [0; 64];
+/// let result = usb.phase(&mut device, 100_000, |(_, serial)| {
+///     serial.read(&mut buf).ok()
+/// });
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// ```
+#[derive(Debug)]
+pub struct UsbPoller<T, P> {
+    timer: T,
+    poll:  P,
+}
+
+impl<T, P> UsbPoller<T, P>
+    where T: StartTimer<Error = Infallible>
+{
+    /// Creates an instance that polls using `poll`
+    pub fn new(timer: T, poll: P) -> Self {
+        UsbPoller {
+            timer,
+            poll,
+        }
+    }
+
+    /// Polls the device, until `ready` returns `Some`, or the timeout expires
+    ///
+    /// The timer is started with `duration` at the beginning of the phase.
+    /// `ready` is called after every poll, regardless of whether there were
+    /// any events, and receives the device too. Between polls, the timer's
+    /// [`idle`] method gets a chance to yield.
+    ///
+    /// [`idle`]: crate::TimeoutTimer::idle
+    pub fn phase<D, R, V>(
+        &mut self,
+        device:    &mut D,
+        duration:  T::Duration,
+        mut ready: R,
+    )
+        -> Result<V, TimeoutError>
+        where
+            P: FnMut(&mut D) -> bool,
+            R: FnMut(&mut D) -> Option<V>,
+    {
+        match self.timer.try_start(duration) {
+            Ok(()) => (),
+            Err(error) => match error {},
+        }
+
+        let mut bound = PollBound::new();
+
+        loop {
+            (self.poll)(device);
+
+            if let Some(value) = ready(device) {
+                return Ok(value);
+            }
+            if self.timer.is_expired() || bound.exhausted() {
+                bound.expired();
+                return Err(TimeoutError::Timeout);
+            }
+
+            self.timer.idle();
+        }
+    }
+
+    /// Provides access to the timer
+    pub fn timer(&mut self) -> &mut T {
+        &mut self.timer
+    }
+
+    /// Returns the timer and the poll function
+    pub fn free(self) -> (T, P) {
+        (self.timer, self.poll)
+    }
+}
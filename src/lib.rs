@@ -132,6 +132,185 @@
 //! # struct DoingStuff;
 //! ```
 //!
+//! # embedded-hal Versions
+//!
+//! The core of this crate (the macros, [`TimeoutTimer`], [`Delay`], and the
+//! clocks and timers built on them) doesn't depend on any version of
+//! `embedded-hal`. Support for a specific version is provided by adapters that
+//! implement these traits for the types of that version, enabled by a cargo
+//! feature:
+//!
+//! - `eh0` (enabled by default): `CountDown` timers, `DelayUs<u32>` delays,
+//!   and the serial and SPI helpers for embedded-hal 0.2.
+//!
+//! There is no adapter for embedded-hal 1.0 yet, so its delays have to
+//! implement [`Delay`] themselves. It has no timer traits, though. Its HALs
+//! usually provide a free-running counter instead, and implementing [`Clock`]
+//! for such a counter makes it usable with the macros, through [`Deadline`]:
+//!
+//! ``` rust
+//! use embedded_timeout_macros::{
+//!     block_timeout,
+//!     Clock,
+//!     Deadline,
+//!     TimeoutError,
+//! };
+//! #
+//! # mod hal {
+//! #     pub struct Timer(pub u32);
+//! #
+//! #     impl Timer {
+//! #         pub fn counter(&self) -> u32 { self.0 }
+//! #         pub fn tick(&mut self) { self.0 += 1 }
+//! #     }
+//! # }
+//!
+//! /// Wraps the free-running timer of a HAL
+//! struct Counter(hal::Timer);
+//!
+//! impl Clock for Counter {
+//!     type Ticks = u32;
+//!
+//!     fn now(&mut self) -> u32 {
+//!         # self.0.tick();
+//!         self.0.counter()
+//!     }
+//! }
+//!
+//! let mut deadline = Deadline::start(Counter(hal::Timer(0)), 1000);
+//!
+//! let result: Result<(), TimeoutError<()>> = block_timeout!(
+//!     &mut deadline,
+//!     Err(nb::Error::WouldBlock)
+//! );
+//! assert_eq!(result, Err(TimeoutError::Timeout));
+//! ```
+//!
+//! Drivers that need to support several versions at once can be written
+//! against the traits of this crate, leaving it to the application to pick an
+//! adapter. To use a HAL that isn't covered by an adapter, implement
+//! [`TimeoutTimer`] and [`Delay`] for its types.
+//!
+//! ``` rust
+//! use embedded_timeout_macros::{
+//!     block_timeout,
+//!     Clock,
+//!     Deadline,
+//!     Delay,
+//!     TimeoutError,
+//!     TimeoutTimer,
+//! };
+//!
+//! /// A driver that doesn't depend on any version of embedded-hal
+//! struct Sensor<T, D> {
+//!     timer: T,
+//!     delay: D,
+//! }
+//!
+//! impl<T, D> Sensor<T, D>
+//!     where
+//!         T: TimeoutTimer,
+//!         D: Delay,
+//! {
+//!     fn measure(&mut self) -> Result<u16, TimeoutError<()>> {
+//!         // Give the sensor time to power up.
+//!         self.delay.delay_us(500);
+//!         block_timeout!(&mut self.timer, Ok(412))
+//!     }
+//! }
+//! #
+//! # struct HalTimer;
+//! #
+//! # impl embedded_hal::timer::CountDown for HalTimer {
+//! #     type Time = ();
+//! #     fn start<T>(&mut self, _: T) {}
+//! #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+//! #         Err(nb::Error::WouldBlock)
+//! #     }
+//! # }
+//! #
+//! # struct HalDelay;
+//! #
+//! # impl embedded_hal::blocking::delay::DelayUs<u32> for HalDelay {
+//! #     fn delay_us(&mut self, _: u32) {}
+//! # }
+//!
+//! #
+//! # struct Counter(u32);
+//! #
+//! # impl Clock for Counter {
+//! #     type Ticks = u32;
+//! #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+//! # }
+//! #
+//! # struct BusyWait;
+//!
+//! // An application using embedded-hal 0.2 passes its types directly.
+//! let mut sensor = Sensor {
+//!     timer: HalTimer,
+//!     delay: HalDelay,
+//! };
+//! assert_eq!(sensor.measure(), Ok(412));
+//!
+//! // An application using a HAL that isn't covered by an adapter implements
+//! // the traits of this crate for its types, or uses a `Deadline`.
+//! impl Delay for BusyWait {
+//!     fn delay_us(&mut self, us: u32) {
+//!         // Wait for `us` microseconds, using whatever the HAL provides.
+//! #       let _ = us;
+//!     }
+//! }
+//!
+//! let mut sensor = Sensor {
+//!     timer: Deadline::start(Counter(0), 1000),
+//!     delay: BusyWait,
+//! };
+//! assert_eq!(sensor.measure(), Ok(412));
+//! ```
+//!
+//! # Optional Modules
+//!
+//! Beyond the macros and the traits they are built on, this crate is split into
+//! modules that can be disabled, if they're not needed. Each is controlled by a
+//! cargo feature of the same name, all of which are enabled by default:
+//!
+//! - [`timer`]: Clocks, alarms, timer wheels, poll strategies, and other timer
+//!   adapters
+//! - [`io`]: Helpers for serial ports, buses, and network stacks, and for
+//!   polling several operations at once
+//! - [`retry`]: Retrying failed operations, with configurable backoff
+//!
+//! Some modules are only needed in specific situations, or don't work on all
+//! targets. They are controlled by cargo features too, mostly of the same name,
+//! but need to be enabled explicitly:
+//!
+//! - [`ambient`]: Passing a deadline down to nested calls implicitly
+//! - [`asynch`] (feature `async`): Timeouts for futures, for async firmware
+//! - [`cancel`]: Cancellation and deadlines shared between cores
+//! - [`config`]: Timeouts that can be changed at runtime
+//! - [`signal`]: Passing values from interrupt handlers to the main code
+//! - [`sample`]: Combining the values produced before a timeout
+//! - [`sim`]: Deterministic simulation of operations and timers, for tests
+//! - [`stats`]: Completion time statistics and worst-case execution times per
+//!   call site
+//! - [`telemetry`]: Compact records of timeouts, for sending them off the
+//!   device
+//!
+//! # Debugging
+//!
+//! When a peripheral is halted at a breakpoint, every timeout that is waiting
+//! for it expires, which makes it hard to step through the code. To prevent
+//! that, build with the `EMBEDDED_TIMEOUT_MACROS_NO_TIMEOUTS` environment
+//! variable set (to any value). The macros then never check their timers, and
+//! wait forever, like `nb::block!` does. The functions in the other modules of
+//! this crate are not affected, except for [`retry::await_ready`], which
+//! ignores its deadline, but still waits for the warm-up. The iteration limit
+//! of the `bounded` feature isn't affected either. Never ship a build like
+//! that. Checking [`NO_TIMEOUTS`] in a release build can make sure of it.
+//!
+//! This is not a cargo feature, as enabling it in any crate would silently
+//! change the behavior of all crates that use this one.
+//!
 //! [`embedded-hal`]: https://crates.io/crates/embedded-hal
 //! [`nb`]: https://crates.io/crates/nb
 
@@ -141,18 +320,121 @@
 #![deny(missing_docs)]
 
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+mod macros;
+
+pub mod codes;
+pub mod dynamic;
+
+#[cfg(feature = "ambient")]
+pub mod ambient;
+
+// Requires a newer version of Rust than the rest of the crate.
+#[cfg(feature = "async")]
+#[allow(clippy::incompatible_msrv)]
+pub mod asynch;
+
+#[cfg(feature = "cancel")]
+pub mod cancel;
+
+#[cfg(feature = "config")]
+pub mod config;
+
+#[cfg(feature = "io")]
+pub mod io;
+
+#[cfg(feature = "retry")]
+pub mod retry;
+
+#[cfg(feature = "signal")]
+pub mod signal;
+
+#[cfg(feature = "sample")]
+pub mod sample;
+
+#[cfg(feature = "sim")]
+pub mod sim;
+
+#[cfg(feature = "stats")]
+pub mod stats;
+
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+
+#[cfg(feature = "timer")]
+pub mod timer;
+
+mod bound;
+mod clock;
+mod deadline;
+mod delay;
+mod idle;
+mod traits;
+
+
+use core::convert::Infallible;
+
+
+#[cfg(feature = "eh0")]
 pub use embedded_hal;
 pub use nb;
 
+pub use self::{
+    bound::NO_TIMEOUTS,
+    clock::{
+        Clock,
+        Ticks,
+        ticks_from_duration,
+    },
+    deadline::Deadline,
+    delay::Delay,
+    traits::{
+        Context,
+        Elapsed,
+        FnTimer,
+        FromDuration,
+        IntoTimeoutOp,
+        Progress,
+        StartTimer,
+        TimedOperation,
+        TimeoutResultExt,
+        TimeoutTimer,
+        TimerOperation,
+    },
+};
 
-/// Blocks on a non-blocking operation until a timer times out
-///
-/// Expects two arguments:
+#[cfg(feature = "bounded")]
+pub use self::bound::MAX_POLLS;
+
+#[cfg(feature = "idle-yield")]
+pub use self::idle::set_yield_hook;
+
+#[cfg(feature = "timer")]
+pub use self::timer::{
+    Alarm,
+    Counter64Clock,
+    CounterClock,
+    Yielding,
+};
+
+#[cfg(all(feature = "std", feature = "timer"))]
+pub use self::timer::StdClock;
+
+
+/// An error that can either be a timeout or another error
 ///
-/// - A timer that implements `embedded_hal::timer::CountDown`
-/// - An expression that evaluates to `nb::Result<T, E>`
+/// Returned by the [`block_timeout`] macro.
 ///
-/// Evaluates the expression and returns `Result<T, TimeoutError<E>>`.
+/// The error type defaults to `Infallible`, so operations that can only fail
+/// by timing out don't require spelling it out. Many HALs based on
+/// embedded-hal 0.2 use `void::Void` for that purpose instead. With the `void`
+/// feature enabled (which `eh0` does), `TimeoutError<Void>` can be converted
+/// into `TimeoutError<Infallible>` using `From`.
 ///
 /// # Example
 ///
@@ -162,192 +444,327 @@ pub use nb;
 ///     TimeoutError,
 /// };
 /// #
-/// # struct Timer;
+/// # struct Timer(u32);
 /// #
 /// # impl embedded_hal::timer::CountDown for Timer {
 /// #     type Time = ();
 /// #     fn start<T>(&mut self, _: T) {}
-/// #     fn wait(&mut self) -> nb::Result<(), void::Void> { Ok(()) }
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
 /// # }
 /// #
-/// # let mut timer = Timer;
+/// # struct Pin;
+/// #
+/// # impl Pin {
+/// #     fn wait_for_edge(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer(3);
+/// # let mut pin = Pin;
 ///
-/// let result: Result<(), TimeoutError<()>> = block_timeout!(
-///     &mut timer,
-///     {
-///         // The macro will keep evaluation this expression repeatedly until
-///         // it returns `Ok` or until the timer times out.
-///         //
-///         // We can do anything that returns `nb::Result` here. For this
-///         // simple example, we just return `Ok`.
-///         Ok(())
-///     }
-/// );
+/// // The HAL uses `Void` to indicate that waiting for the edge can't fail.
+/// let result: Result<(), TimeoutError> =
+///     block_timeout!(&mut timer, pin.wait_for_edge())
+///         .map_err(TimeoutError::from);
 ///
-/// match result {
-///     Ok(()) => {
-///         // success
-///     }
-///     Err(TimeoutError::Timeout) => {
-///         // the operation timed out
-///     }
-///     Err(TimeoutError::Other(error)) => {
-///         // the operation returned another error
-///     }
-/// }
+/// assert_eq!(result, Err(TimeoutError::Timeout));
 /// ```
-#[macro_export]
-macro_rules! block_timeout {
-    ($timer:expr, $op:expr) => {
-        {
-            use $crate::embedded_hal::prelude::*;
-
-            // Make sure the timer has the right type. If it hasn't, the user
-            // should at least get a good error message.
-            fn check_type<T>(_: &mut T)
-                where T: $crate::embedded_hal::timer::CountDown {}
-            check_type($timer);
-
-            loop {
-                match $timer.wait() {
-                    Ok(()) =>
-                        break Err($crate::TimeoutError::Timeout),
-                    Err($crate::nb::Error::WouldBlock) =>
-                        (),
-                    Err(_) =>
-                        unreachable!(),
-                }
-
-                match $op {
-                    Ok(result) =>
-                        break Ok(result),
-                    Err($crate::nb::Error::WouldBlock) =>
-                        (),
-                    Err($crate::nb::Error::Other(error)) =>
-                        break Err($crate::TimeoutError::Other(error)),
-                }
-            }
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeoutError<T = Infallible> {
+    /// The operation timed out
+    Timeout,
+
+    /// Another error occured
+    Other(T),
+}
+
+impl<T> TimeoutError<T> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            TimeoutError::Timeout  => codes::TIMEOUT,
+            TimeoutError::Other(_) => codes::OTHER,
         }
     }
 }
 
-/// Repeats an operation until a timer times out
-///
-/// Expects four arguments:
-///
-/// - A timer that implements `embedded_hal::timer::CountDown`
-/// - An expression that evaluates to `Result<T, E>` (the operation)
-/// - A pseudo-closure that will be called every time the operation succeeds
-///   This pseudo-closure is expected to take an argument of type `T`. The
-///   return value is ignored.
-/// - A pseudo-closure that will be called every time the operation fails
-///   This pseudo-closure is expected to take an argument of type `E`. The
-///   return value is ignored.
-///
-/// `repeat_timeout!` will keep repeating the operation until the timer runs
-/// out, no matter whether it suceeds or fails.
-///
-/// It uses a `loop` to do that, which is `break`s from when the timer runs out.
-/// Any of the expressions passed into the macro, the main expression, as well
-/// as the two pseudo-closures, can employ `break` and `continue` to manipulate
-/// that loop.
-///
-/// # Example
+impl<T> From<Infallible> for TimeoutError<T> {
+    fn from(error: Infallible) -> Self {
+        match error {}
+    }
+}
+
+#[cfg(feature = "void")]
+impl<T> From<void::Void> for TimeoutError<T> {
+    fn from(error: void::Void) -> Self {
+        match error {}
+    }
+}
+
+#[cfg(feature = "void")]
+impl From<TimeoutError<void::Void>> for TimeoutError<Infallible> {
+    fn from(error: TimeoutError<void::Void>) -> Self {
+        match error {
+            TimeoutError::Timeout      => TimeoutError::Timeout,
+            TimeoutError::Other(error) => match error {},
+        }
+    }
+}
+
+
+/// An error that can be a failure to start the timer, a timeout, or another
+/// error
 ///
-/// ``` rust
-/// use embedded_timeout_macros::{
-///     repeat_timeout,
-///     TimeoutError,
-/// };
-/// #
-/// # struct Timer;
-/// #
-/// # impl embedded_hal::timer::CountDown for Timer {
-/// #     type Time = ();
-/// #     fn start<T>(&mut self, _: T) {}
-/// #     fn wait(&mut self) -> nb::Result<(), void::Void> { Ok(()) }
-/// # }
-/// #
-/// # let mut timer = Timer;
+/// Returned by those forms of the macros that start the timer themselves. See
+/// [`StartTimer`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StartTimeoutError<S = Infallible, T = Infallible> {
+    /// The timer could not be started
+    Start(S),
+
+    /// The operation timed out
+    Timeout,
+
+    /// Another error occured
+    Other(T),
+}
+
+impl<S, T> StartTimeoutError<S, T> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            StartTimeoutError::Start(_) => codes::START_FAILED,
+            StartTimeoutError::Timeout  => codes::START_TIMEOUT,
+            StartTimeoutError::Other(_) => codes::START_OTHER,
+        }
+    }
+}
+
+impl<S, T> From<TimeoutError<T>> for StartTimeoutError<S, T> {
+    fn from(error: TimeoutError<T>) -> Self {
+        match error {
+            TimeoutError::Timeout      => StartTimeoutError::Timeout,
+            TimeoutError::Other(error) => StartTimeoutError::Other(error),
+        }
+    }
+}
+
+impl<T> From<StartTimeoutError<Infallible, T>> for TimeoutError<T> {
+    fn from(error: StartTimeoutError<Infallible, T>) -> Self {
+        match error {
+            StartTimeoutError::Start(error) => match error {},
+            StartTimeoutError::Timeout      => TimeoutError::Timeout,
+            StartTimeoutError::Other(error) => TimeoutError::Other(error),
+        }
+    }
+}
+
+
+/// An error that occured while starting a timer with a `Duration`
 ///
-/// repeat_timeout!(
-///     &mut timer,
-///     {
-///         // The macro will keep evaluating this expression repeatedly until
-///         // the timer times out.
-///         //
-///         // We can do anything that returns `Result` here. For this simple
-///         // example, we just return `Ok`.
-///         Ok(())
+/// Returned by the `after` form of [`block_timeout`], wrapped in
+/// [`StartTimeoutError::Start`]. Durations that the timer can't represent are
+/// reported as an error, instead of being truncated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DurationError<S = Infallible> {
+    /// The duration can't be represented by the timer
+    OutOfRange,
+
+    /// The timer could not be started
+    Start(S),
+}
+
+impl<S> DurationError<S> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            DurationError::OutOfRange => codes::DURATION_OUT_OF_RANGE,
+            DurationError::Start(_)   => codes::DURATION_START,
+        }
+    }
+}
+
+
+/// An error returned by [`clock_timeout`]
 ///
-///         // We could also return an error.
-///         // Err("This is an error")
-///     },
-///     // Here's a pseudo-closure with an argument in parentheses, which we can
-///     // name freely, followed by an expression whose return value is ignored.
-///     (result) {
-///         // The macro will evaluate this expression, if the main expression
-///         // above returns `Ok`. `result`, which we've named in the
-///         // parentheses above, will be whatever the contents of the `Ok` are.
-///         let result: () = result;
-///     };
-///     (error) {
-///         // will be called by the macro, if the expression returns `Err`
-///         let error: &'static str = error;
-///     };
-/// );
-/// ```
-#[macro_export]
-macro_rules! repeat_timeout {
-    (
-        $timer:expr,
-        $op:expr,
-        ($result:ident) $on_success:expr;
-        ($error:ident) $on_error:expr;
-    ) => {
-        {
-            use $crate::embedded_hal::prelude::*;
-
-            // Make sure the timer has the right type. If it hasn't, the user
-            // should at least get a good error message.
-            fn check_type<T>(_: &mut T)
-                where T: $crate::embedded_hal::timer::CountDown {}
-            check_type($timer);
-
-            loop {
-                match $timer.wait() {
-                    Ok(()) =>
-                        break,
-                    Err($crate::nb::Error::WouldBlock) =>
-                        (),
-                    Err(_) =>
-                        unreachable!(),
-                }
-
-                match $op {
-                    Ok(result) => {
-                        let $result = result;
-                        $on_success;
-                    }
-                    Err(error) => {
-                        let $error = error;
-                        $on_error;
-                    }
-                }
-            }
+/// Like [`TimeoutError`], but with an additional variant for when the clock
+/// couldn't be read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClockTimeoutError<C, T = Infallible> {
+    /// The clock could not be read
+    Clock(C),
+
+    /// The operation timed out
+    Timeout,
+
+    /// Another error occured
+    Other(T),
+}
+
+impl<C, T> ClockTimeoutError<C, T> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            ClockTimeoutError::Clock(_) => codes::CLOCK_FAILED,
+            ClockTimeoutError::Timeout  => codes::CLOCK_TIMEOUT,
+            ClockTimeoutError::Other(_) => codes::CLOCK_OTHER,
         }
     }
 }
 
+impl<C, T> From<TimeoutError<T>> for ClockTimeoutError<C, T> {
+    fn from(error: TimeoutError<T>) -> Self {
+        match error {
+            TimeoutError::Timeout      => ClockTimeoutError::Timeout,
+            TimeoutError::Other(error) => ClockTimeoutError::Other(error),
+        }
+    }
+}
 
-/// An error that can either be a timeout or another error
+
+/// An error returned by [`block_timeout_bounded`]
 ///
-/// Returned by the [`block_timeout`] macro.
-#[derive(Debug)]
-pub enum TimeoutError<T> {
+/// Like [`TimeoutError`], but with an additional variant for when the maximum
+/// number of polls has been reached.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BoundedError<T = Infallible> {
     /// The operation timed out
     Timeout,
 
+    /// The operation was polled the maximum number of times
+    ///
+    /// Carries the maximum.
+    LimitReached(u32),
+
     /// Another error occured
     Other(T),
 }
+
+impl<T> BoundedError<T> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            BoundedError::Timeout         => codes::BOUNDED_TIMEOUT,
+            BoundedError::LimitReached(_) => codes::BOUNDED_LIMIT,
+            BoundedError::Other(_)        => codes::BOUNDED_OTHER,
+        }
+    }
+}
+
+impl<T> From<TimeoutError<T>> for BoundedError<T> {
+    fn from(error: TimeoutError<T>) -> Self {
+        match error {
+            TimeoutError::Timeout      => BoundedError::Timeout,
+            TimeoutError::Other(error) => BoundedError::Other(error),
+        }
+    }
+}
+
+
+/// An error returned by [`block_timeout_abort`]
+///
+/// Every variant but `Other` means that the operation timed out. They differ
+/// in how the abort that followed went.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AbortError<T = Infallible, A = Infallible> {
+    /// The operation timed out, and was aborted
+    Aborted,
+
+    /// The operation timed out, and the abort failed
+    AbortFailed(A),
+
+    /// The operation timed out, and so did the abort
+    AbortTimeout,
+
+    /// The operation failed, and wasn't aborted
+    Other(T),
+}
+
+impl<T, A> AbortError<T, A> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            AbortError::Aborted        => codes::ABORT_ABORTED,
+            AbortError::AbortFailed(_) => codes::ABORT_FAILED,
+            AbortError::AbortTimeout   => codes::ABORT_TIMEOUT,
+            AbortError::Other(_)       => codes::ABORT_OTHER,
+        }
+    }
+
+    /// Indicates whether the operation timed out
+    pub fn is_timeout(&self) -> bool {
+        !matches!(self, AbortError::Other(_))
+    }
+}
+
+
+/// The outcome of a wait that can be cancelled
+///
+/// Returned by [`block_cancellable`]. Unlike [`TimeoutError`], this has a
+/// separate variant for cancellation, so call sites have to handle it
+/// explicitly, and the type documents that the wait can be cancelled.
+///
+/// [`block_cancellable`]: crate::block_cancellable
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WaitOutcome<T, E = Infallible> {
+    /// The operation finished
+    Done(T),
+
+    /// The timer expired before the operation finished
+    TimedOut,
+
+    /// The wait was cancelled before the operation finished
+    Cancelled,
+
+    /// The operation returned an error
+    Other(E),
+}
+
+impl<T, E> WaitOutcome<T, E> {
+    /// Returns the result of the operation, if it finished
+    pub fn done(self) -> Option<T> {
+        match self {
+            WaitOutcome::Done(value) => Some(value),
+            _                        => None,
+        }
+    }
+
+    /// Indicates whether the wait was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, WaitOutcome::Cancelled)
+    }
+}
+
+
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::{
+        bound::{
+            is_expired,
+            PollBound,
+        },
+        idle::idle,
+    };
+
+    #[cfg(feature = "eh0")]
+    pub use embedded_hal::prelude;
+
+    #[cfg(not(feature = "eh0"))]
+    pub mod prelude {}
+}
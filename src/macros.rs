@@ -0,0 +1,2139 @@
+//! The macros that this crate is built around
+
+
+/// Blocks on a non-blocking operation until a timer times out
+///
+/// Expects two arguments:
+///
+/// - A timer that implements [`TimeoutTimer`], like an
+///   `embedded_hal::timer::CountDown` timer or a [`Deadline`]
+/// - An expression that evaluates to `nb::Result<T, E>`
+///
+/// Evaluates the expression and returns `Result<T, TimeoutError<E>>`.
+///
+/// The expression can also be written as a pseudo-closure, `|context| expr`.
+/// In that case, `context` is bound to a [`Context`], which tells the
+/// expression how many attempts have been made so far, and how much time has
+/// elapsed. This can be used to adapt the operation, as the wait drags on. The
+/// timer must implement [`Elapsed`] for this form, which timers that don't
+/// know when they were started, like `CountDown` timers, don't. Please note
+/// that this is not a real closure, so `return`, `break`, and `continue` still
+/// work as they would outside of the macro.
+///
+/// A duration can be passed as an additional argument, between the timer and
+/// the expression. In that case, the timer must implement [`StartTimer`], and
+/// is started with that duration before the operation is first evaluated.
+/// Since starting a timer can fail, this form evaluates to
+/// `Result<T, StartTimeoutError<S, E>>` instead. The duration can be any
+/// expression of the timer's [`StartTimer::Duration`] type. It is passed on
+/// as-is, so it doesn't have to be a number. For a `CountDown` timer, it is
+/// the timer's `Time` type, which some HALs define as an enum of prescaler
+/// settings.
+///
+/// To express the duration independently of the timer, write `after` in front
+/// of it. Then it can be anything that converts into `core::time::Duration`,
+/// and the timer must implement [`FromDuration`], which converts it into the
+/// timer's own duration type. A duration that the timer can't represent
+/// results in `StartTimeoutError::Start(DurationError::OutOfRange)`, and the
+/// timer isn't started. Errors from starting the timer are wrapped in
+/// `DurationError::Start`.
+///
+/// After a timeout, the timer is left expired, so using it again times out
+/// right away, unless it is restarted first. To have the macro do that, write
+/// `rearm` in front of the duration. Then the timer isn't started before
+/// waiting, but restarted with the duration after a timeout, so it is ready
+/// for the next wait. After success or an error, it keeps running, so the
+/// waits that follow share what's left. This form evaluates to
+/// `Result<T, StartTimeoutError<S, E>>` too, with `StartTimeoutError::Start`
+/// meaning that the wait timed out, and restarting the timer failed.
+///
+/// Errors end the wait by default. To retry after an error instead, add
+/// `on_retry (error) <expr>;` after the expression. The pseudo-closure is
+/// evaluated once per failed attempt, with the error bound to `error`, but not
+/// when the expression returns `WouldBlock`. This is the place to re-send a
+/// command, or to reset a peripheral, before polling again. Waiting continues
+/// until the expression succeeds, or the timer times out. Use
+/// [`bail_timeout`] within the pseudo-closure, to give up early.
+///
+/// If the timer has already expired when the macro first checks it, the
+/// operation is never evaluated, and the macro evaluates to a timeout right
+/// away. This usually means that the timer wasn't started, or that it has
+/// expired during a previous use. With the `check-start` feature enabled,
+/// this panics in builds with debug assertions instead, as do the other wait
+/// loops in this crate.
+///
+/// To pass an existing operation instead of an expression, use
+/// [`block_timeout_op`].
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// let result: Result<(), TimeoutError<()>> = block_timeout!(
+///     &mut timer,
+///     {
+///         // The macro will keep evaluation this expression repeatedly until
+///         // it returns `Ok` or until the timer times out.
+///         //
+///         // We can do anything that returns `nb::Result` here. For this
+///         // simple example, we just return `Ok`.
+///         Ok(())
+///     }
+/// );
+///
+/// match result {
+///     Ok(()) => {
+///         // success
+///     }
+///     Err(TimeoutError::Timeout) => {
+///         // the operation timed out
+///     }
+///     Err(TimeoutError::Other(error)) => {
+///         // the operation returned another error
+///     }
+/// }
+/// ```
+///
+/// Using the pseudo-closure form:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     Deadline,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+/// #
+/// # let clock = Clock(0);
+///
+/// let mut deadline = Deadline::start(clock, 1000);
+///
+/// let result: Result<u32, TimeoutError<()>> = block_timeout!(
+///     &mut deadline,
+///     |context| {
+///         if context.attempt < 100 {
+///             // We could lower the bus speed here, or try something else.
+///             Err(nb::Error::WouldBlock)
+///         }
+///         else {
+///             Ok(context.attempt)
+///         }
+///     }
+/// );
+///
+/// assert_eq!(result, Ok(100));
+/// ```
+///
+/// Passing a duration:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     StartTimeoutError,
+///     StartTimer,
+///     TimeoutTimer,
+/// };
+///
+/// struct Timer(u32);
+///
+/// impl TimeoutTimer for Timer {
+///     fn is_expired(&mut self) -> bool {
+///         false
+///     }
+/// }
+///
+/// impl StartTimer for Timer {
+///     type Duration = u32;
+///     type Error    = &'static str;
+///
+///     fn try_start(&mut self, duration: u32) -> Result<(), Self::Error> {
+///         if duration > 1000 {
+///             return Err("duration out of range");
+///         }
+///         self.0 = duration;
+///         Ok(())
+///     }
+/// }
+///
+/// let mut timer = Timer(0);
+///
+/// let result: Result<(), StartTimeoutError<_, ()>> = block_timeout!(
+///     &mut timer,
+///     5000,
+///     Ok(())
+/// );
+///
+/// assert_eq!(result, Err(StartTimeoutError::Start("duration out of range")));
+/// ```
+///
+/// Passing a duration that isn't a number:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     StartTimeoutError,
+/// };
+///
+/// /// The `Time` type of a HAL timer, which only supports a few timeouts
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// enum Prescaler {
+///     Div16,
+///     Div256,
+/// }
+///
+/// struct HalTimer(Option<Prescaler>);
+///
+/// impl embedded_hal::timer::CountDown for HalTimer {
+///     type Time = Prescaler;
+///
+///     fn start<T>(&mut self, count: T) where T: Into<Prescaler> {
+///         self.0 = Some(count.into());
+///     }
+///     fn wait(&mut self) -> nb::Result<(), void::Void> {
+///         Err(nb::Error::WouldBlock)
+///     }
+/// }
+///
+/// let mut timer = HalTimer(None);
+///
+/// let result: Result<(), StartTimeoutError<_, ()>> = block_timeout!(
+///     &mut timer,
+///     Prescaler::Div256,
+///     Ok(())
+/// );
+///
+/// assert_eq!(result, Ok(()));
+/// assert_eq!(timer.0, Some(Prescaler::Div256));
+/// ```
+///
+/// Passing a `Duration`:
+///
+/// ``` rust
+/// use core::time::Duration;
+///
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     CounterClock,
+///     Deadline,
+///     DurationError,
+///     StartTimeoutError,
+/// };
+/// #
+/// # let read_counter_register = || 0u16;
+///
+/// // A 16-bit counter running at 32.768 kHz
+/// let clock = CounterClock::with_bits(16, 32_768, read_counter_register);
+/// let mut deadline = Deadline::start(clock, 0);
+///
+/// let result: Result<(), StartTimeoutError<_, ()>> = block_timeout!(
+///     &mut deadline,
+///     after Duration::from_millis(100),
+///     Ok(())
+/// );
+/// assert_eq!(result, Ok(()));
+/// assert_eq!(deadline.duration(), 3277);
+///
+/// // Doesn't fit into 16 bits at this frequency
+/// let result: Result<(), StartTimeoutError<_, ()>> = block_timeout!(
+///     &mut deadline,
+///     after Duration::from_secs(5),
+///     Ok(())
+/// );
+/// assert_eq!(
+///     result,
+///     Err(StartTimeoutError::Start(DurationError::OutOfRange))
+/// );
+/// ```
+///
+/// Restarting the timer after a timeout:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     Deadline,
+///     StartTimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+///
+/// let mut deadline = Deadline::start(Clock(0), 10);
+///
+/// for _ in 0..3 {
+///     // The sensor never answers.
+///     let result: Result<(), StartTimeoutError<_, ()>> = block_timeout!(
+///         &mut deadline,
+///         rearm 10,
+///         Err(nb::Error::WouldBlock)
+///     );
+///
+///     // Every wait times out after 10 ticks, not just the first.
+///     assert_eq!(result, Err(StartTimeoutError::Timeout));
+/// }
+/// ```
+///
+/// Re-sending a command after an error:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// let mut sent = 1;
+/// let mut polls = 0;
+///
+/// let result: Result<u32, TimeoutError<&str>> = block_timeout!(
+///     &mut timer,
+///     {
+///         polls += 1;
+///         match (sent, polls % 2) {
+///             (3, _) => Ok(sent),
+///             (_, 0) => Err(nb::Error::Other("NAK")),
+///             (_, _) => Err(nb::Error::WouldBlock),
+///         }
+///     },
+///     on_retry (_error) {
+///         // Runs after every failed attempt, but not after `WouldBlock`.
+///         sent += 1;
+///     };
+/// );
+///
+/// assert_eq!(result, Ok(3));
+/// assert_eq!(polls, 5);
+/// ```
+///
+/// [`bail_timeout`]: crate::bail_timeout
+/// [`block_timeout_op`]: crate::block_timeout_op
+/// [`Context`]: crate::Context
+/// [`Deadline`]: crate::Deadline
+/// [`Elapsed`]: crate::Elapsed
+/// [`FromDuration`]: crate::FromDuration
+/// [`StartTimer`]: crate::StartTimer
+/// [`StartTimer::Duration`]: crate::StartTimer::Duration
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[macro_export]
+macro_rules! block_timeout {
+    ($timer:expr, |$context:ident| $op:expr) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            // Make sure the timer has the right type. If it hasn't, the user
+            // should at least get a good error message.
+            fn check_type<T>(_: &mut T)
+                where T: $crate::TimeoutTimer + $crate::Elapsed + ?Sized {}
+            check_type($timer);
+
+            let mut attempt: u32 = 0;
+
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                if $crate::__private::is_expired($timer)
+                    || bound.exhausted()
+                {
+                    bound.expired();
+                    break Err($crate::TimeoutError::Timeout);
+                }
+
+                attempt = attempt.saturating_add(1);
+                let $context = $crate::Context {
+                    attempt,
+                    elapsed: $crate::Elapsed::elapsed_ticks($timer),
+                };
+
+                match $op {
+                    Ok(result) =>
+                        break Ok(result),
+                    Err($crate::nb::Error::WouldBlock) =>
+                        $crate::TimeoutTimer::idle($timer),
+                    Err($crate::nb::Error::Other(error)) =>
+                        break Err($crate::TimeoutError::Other(error)),
+                }
+            }
+        }
+    };
+    ($timer:expr, after $duration:expr, $op:expr) => {
+        {
+            let duration: ::core::time::Duration =
+                ::core::convert::Into::into($duration);
+
+            match $crate::FromDuration::convert_duration(&*$timer, duration) {
+                Some(duration) =>
+                    match $crate::block_timeout!($timer, duration, $op) {
+                        Ok(result) =>
+                            Ok(result),
+                        Err($crate::StartTimeoutError::Start(error)) =>
+                            Err($crate::StartTimeoutError::Start(
+                                $crate::DurationError::Start(error)
+                            )),
+                        Err($crate::StartTimeoutError::Timeout) =>
+                            Err($crate::StartTimeoutError::Timeout),
+                        Err($crate::StartTimeoutError::Other(error)) =>
+                            Err($crate::StartTimeoutError::Other(error)),
+                    },
+                None =>
+                    Err($crate::StartTimeoutError::Start(
+                        $crate::DurationError::OutOfRange
+                    )),
+            }
+        }
+    };
+    ($timer:expr, rearm $duration:expr, $op:expr) => {
+        match $crate::block_timeout!($timer, $op) {
+            Ok(result) =>
+                Ok(result),
+            Err($crate::TimeoutError::Other(error)) =>
+                Err($crate::StartTimeoutError::Other(error)),
+            Err($crate::TimeoutError::Timeout) =>
+                match $crate::StartTimer::try_start($timer, $duration) {
+                    Ok(()) =>
+                        Err($crate::StartTimeoutError::Timeout),
+                    Err(error) =>
+                        Err($crate::StartTimeoutError::Start(error)),
+                },
+        }
+    };
+    ($timer:expr, $op:expr, on_retry ($error:ident) $on_retry:expr $(;)?) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            // Make sure the timer has the right type. If it hasn't, the user
+            // should at least get a good error message.
+            fn check_type<T>(_: &mut T)
+                where T: $crate::TimeoutTimer + ?Sized {}
+            check_type($timer);
+
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                if $crate::__private::is_expired($timer)
+                    || bound.exhausted()
+                {
+                    bound.expired();
+                    break Err($crate::TimeoutError::Timeout);
+                }
+
+                match $op {
+                    Ok(result) =>
+                        break Ok(result),
+                    Err($crate::nb::Error::WouldBlock) =>
+                        $crate::TimeoutTimer::idle($timer),
+                    Err($crate::nb::Error::Other(error)) => {
+                        let $error = error;
+                        $on_retry;
+                    }
+                }
+            }
+        }
+    };
+    ($timer:expr, $duration:expr, $op:expr) => {
+        {
+            // Evaluate the duration first, so it can borrow the timer.
+            let duration = $duration;
+
+            match $crate::StartTimer::try_start($timer, duration) {
+                Ok(()) =>
+                    match $crate::block_timeout!($timer, $op) {
+                        Ok(result) =>
+                            Ok(result),
+                        Err(error) =>
+                            Err($crate::StartTimeoutError::from(error)),
+                    },
+                Err(error) =>
+                    Err($crate::StartTimeoutError::Start(error)),
+            }
+        }
+    };
+    ($timer:expr, $op:expr) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            // Make sure the timer has the right type. If it hasn't, the user
+            // should at least get a good error message.
+            fn check_type<T>(_: &mut T)
+                where T: $crate::TimeoutTimer + ?Sized {}
+            check_type($timer);
+
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                if $crate::__private::is_expired($timer)
+                    || bound.exhausted()
+                {
+                    bound.expired();
+                    break Err($crate::TimeoutError::Timeout);
+                }
+
+                match $op {
+                    Ok(result) =>
+                        break Ok(result),
+                    Err($crate::nb::Error::WouldBlock) =>
+                        $crate::TimeoutTimer::idle($timer),
+                    Err($crate::nb::Error::Other(error)) =>
+                        break Err($crate::TimeoutError::Other(error)),
+                }
+            }
+        }
+    }
+}
+
+/// Blocks on an existing operation until a timer times out
+///
+/// Expects two arguments:
+///
+/// - A timer that implements [`TimeoutTimer`]
+/// - An operation that implements [`TimedOperation`]
+///
+/// Works like [`block_timeout`], except that it takes an operation instead of
+/// an expression. This can be anything that implements [`TimedOperation`],
+/// including functions and closures that return `nb::Result<T, E>`, and
+/// mutable references to them, or anything that implements [`IntoTimeoutOp`].
+/// The operation is polled once per iteration. This allows defining an
+/// operation once, and reusing it in several places.
+///
+/// If the operation needs to access the timer, write `with_timer` in front of
+/// it. Then it must implement [`TimerOperation`] instead, and is passed the
+/// timer on every poll. This is useful for operations that need to restart
+/// the timer, when they reach a new phase.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_op,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// fn read_status() -> nb::Result<u8, ()> {
+///     Ok(0x80)
+/// }
+///
+/// let mut polls = 0;
+/// let mut wait_for_ready = || {
+///     polls += 1;
+///     if polls % 3 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// };
+///
+/// let status: Result<u8, TimeoutError<()>> =
+///     block_timeout_op!(&mut timer, read_status);
+/// assert_eq!(status, Ok(0x80));
+///
+/// // The same operation can be used repeatedly.
+/// let first: Result<(), TimeoutError<()>> =
+///     block_timeout_op!(&mut timer, &mut wait_for_ready);
+/// let second: Result<(), TimeoutError<()>> =
+///     block_timeout_op!(&mut timer, &mut wait_for_ready);
+/// # drop(wait_for_ready);
+///
+/// assert_eq!((first, second), (Ok(()), Ok(())));
+/// assert_eq!(polls, 6);
+/// ```
+///
+/// Restarting the timer from within the operation:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_op,
+///     Deadline,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+///
+/// let mut timer = Deadline::start(Clock(0), 10);
+/// let mut switched = false;
+/// let mut polls = 0;
+///
+/// let result: Result<(), TimeoutError<()>> = block_timeout_op!(
+///     &mut timer,
+///     with_timer |timer: &mut Deadline<Clock>| {
+///         polls += 1;
+///         if !switched {
+///             // Switch to a higher baud rate. The device needs more time to
+///             // answer after that.
+///             switched = true;
+///             timer.restart_with(100);
+///         }
+///         if polls < 50 { Err(nb::Error::WouldBlock) } else { Ok(()) }
+///     }
+/// );
+///
+/// assert_eq!(result, Ok(()));
+/// ```
+///
+/// [`IntoTimeoutOp`]: crate::IntoTimeoutOp
+/// [`TimedOperation`]: crate::TimedOperation
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+/// [`TimerOperation`]: crate::TimerOperation
+#[macro_export]
+macro_rules! block_timeout_op {
+    ($timer:expr, with_timer $op:expr) => {
+        {
+            let mut op = $op;
+            $crate::block_timeout!(
+                $timer,
+                $crate::TimerOperation::poll(&mut op, $timer)
+            )
+        }
+    };
+    ($timer:expr, $op:expr) => {
+        {
+            let mut op = $crate::IntoTimeoutOp::into_timeout_op($op);
+            $crate::block_timeout!(
+                $timer,
+                $crate::TimedOperation::poll(&mut op)
+            )
+        }
+    }
+}
+
+/// Blocks on a non-blocking operation, without a timeout
+///
+/// Expects a single argument, an expression that evaluates to
+/// `nb::Result<T, E>`.
+///
+/// Works like `nb::block!`, but evaluates to `Result<T, TimeoutError<E>>`,
+/// like [`block_timeout`] does. It never times out, so the result is never
+/// `TimeoutError::Timeout`, unless the `bounded` feature is enabled, and the
+/// maximum number of polls is reached. This makes it possible to switch code
+/// between waits with and without a timeout, without changing how the result
+/// is handled. Like in [`block_timeout`], [`bail_timeout`] can be used within
+/// the expression.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_forever,
+///     block_timeout,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # let mut timer = Timer;
+/// # let bounded = true;
+///
+/// let mut polls = 0;
+/// let mut read = || -> nb::Result<u32, ()> {
+///     polls += 1;
+///     if polls < 10 { Err(nb::Error::WouldBlock) } else { Ok(polls) }
+/// };
+///
+/// let result: Result<u32, TimeoutError<()>> = if bounded {
+///     block_timeout!(&mut timer, read())
+/// }
+/// else {
+///     block_forever!(read())
+/// };
+///
+/// assert_eq!(result, Ok(10));
+/// ```
+///
+/// [`bail_timeout`]: crate::bail_timeout
+#[macro_export]
+macro_rules! block_forever {
+    ($op:expr) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                if bound.exhausted() {
+                    break Err($crate::TimeoutError::Timeout);
+                }
+
+                match $op {
+                    Ok(result) =>
+                        break Ok(result),
+                    Err($crate::nb::Error::WouldBlock) =>
+                        $crate::__private::idle(),
+                    Err($crate::nb::Error::Other(error)) =>
+                        break Err($crate::TimeoutError::Other(error)),
+                }
+            }
+        }
+    }
+}
+
+/// Ends the enclosing [`block_timeout`] immediately, with the given error
+///
+/// Can be used anywhere within the operation passed to [`block_timeout`], to
+/// stop waiting without polling the operation again. [`block_timeout`] then
+/// evaluates to `Err(TimeoutError::Other(error))`. The other blocking macros
+/// are supported too, and evaluate to the equivalent error of their own error
+/// type. [`block_timeout_bounded`], for example, evaluates to
+/// `Err(BoundedError::Other(error))`.
+///
+/// This is useful for operations that are written as a block of statements,
+/// where returning an error from the middle of the block would be awkward
+/// otherwise.
+///
+/// Please note that `bail_timeout!` works by breaking out of the loop of
+/// [`block_timeout`]. It must not be used within a loop that is part of the
+/// operation, as it would only break out of that loop.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     bail_timeout,
+///     block_timeout,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer;
+/// # let read_status = || 0xffu8;
+///
+/// let result: Result<(), TimeoutError<&'static str>> = block_timeout!(
+///     &mut timer,
+///     {
+///         let status = read_status();
+///         if status == 0xff {
+///             bail_timeout!("device not present");
+///         }
+///
+///         Err(nb::Error::WouldBlock)
+///     }
+/// );
+///
+/// assert_eq!(result, Err(TimeoutError::Other("device not present")));
+/// ```
+///
+/// [`block_timeout_bounded`]: crate::block_timeout_bounded
+#[macro_export]
+macro_rules! bail_timeout {
+    ($error:expr) => {
+        // Converting the error makes this work with all macros whose error
+        // type can be converted from `TimeoutError`.
+        break Err(
+            ::core::convert::From::from($crate::TimeoutError::Other($error))
+        )
+    };
+}
+
+/// Implements `From<TimeoutError<E>>` for an error type
+///
+/// Driver error types often have a variant for timeouts, and one that wraps
+/// the errors of the underlying peripheral. Given the type and the names of
+/// these two variants, this macro generates the conversion from
+/// [`TimeoutError`], so the `?` operator can be used on the results of the
+/// blocking macros.
+///
+/// The type comes first, followed by the two variant names in braces. The
+/// variant for other errors is written with the type of the error it wraps.
+/// For generic error types, the generic parameters are listed in angle
+/// brackets before the type.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     impl_from_timeout_error,
+///     TimeoutTimer,
+/// };
+///
+/// #[derive(Debug, PartialEq)]
+/// enum DriverError<E> {
+///     Timeout,
+///     Bus(E),
+///     InvalidResponse,
+/// }
+///
+/// impl_from_timeout_error!(<E> DriverError<E> { Timeout, Bus(E) });
+///
+/// fn read_id<T>(timer: &mut T) -> Result<u8, DriverError<&'static str>>
+///     where T: TimeoutTimer
+/// {
+///     let id = block_timeout!(timer, Err(nb::Error::Other("NAK")))?;
+///     if id == 0 {
+///         return Err(DriverError::InvalidResponse);
+///     }
+///     Ok(id)
+/// }
+/// #
+/// # struct Timer;
+/// #
+/// # impl TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+///
+/// assert_eq!(read_id(&mut Timer), Err(DriverError::Bus("NAK")));
+/// ```
+///
+/// [`TimeoutError`]: crate::TimeoutError
+#[macro_export]
+macro_rules! impl_from_timeout_error {
+    (
+        <$($param:ident),* $(,)?> $ty:ty {
+            $timeout:ident,
+            $other:ident($error:ty) $(,)?
+        }
+    ) => {
+        impl<$($param),*> ::core::convert::From<$crate::TimeoutError<$error>>
+            for $ty
+        {
+            fn from(error: $crate::TimeoutError<$error>) -> Self {
+                match error {
+                    $crate::TimeoutError::Timeout      => Self::$timeout,
+                    $crate::TimeoutError::Other(error) => Self::$other(error),
+                }
+            }
+        }
+    };
+    (
+        $ty:ty {
+            $timeout:ident,
+            $other:ident($error:ty) $(,)?
+        }
+    ) => {
+        $crate::impl_from_timeout_error!(<> $ty { $timeout, $other($error) });
+    };
+}
+
+/// Converts a `Duration` into ticks at compile time, checking their range
+///
+/// Expects three arguments:
+///
+/// - The tick type of the timer or clock, like `u16`
+/// - The frequency of the timer or clock, in Hz
+/// - An expression that evaluates to `core::time::Duration`
+///
+/// All three must be known at compile time. Evaluates to the number of ticks,
+/// as a value of the tick type, rounded up like [`ticks_from_duration`] does.
+///
+/// If the number of ticks doesn't fit into the tick type, compilation fails,
+/// instead of the duration being truncated. A 5 second timeout on a 16-bit
+/// timer running at 32768 Hz, for example, would otherwise turn into less
+/// than a second.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::time::Duration;
+///
+/// use embedded_timeout_macros::ticks;
+///
+/// const TIMEOUT: u16 = ticks!(u16, 32_768, Duration::from_millis(1500));
+///
+/// assert_eq!(TIMEOUT, 49_152);
+/// ```
+///
+/// A duration that doesn't fit is a compile error:
+///
+/// ``` compile_fail
+/// use core::time::Duration;
+///
+/// use embedded_timeout_macros::ticks;
+///
+/// let timeout = ticks!(u16, 32_768, Duration::from_secs(5));
+/// ```
+///
+/// [`ticks_from_duration`]: crate::ticks_from_duration
+#[macro_export]
+macro_rules! ticks {
+    ($ty:ty, $frequency:expr, $duration:expr $(,)?) => {{
+        const TICKS: $ty = {
+            let ticks = match $crate::ticks_from_duration(
+                $duration,
+                $frequency,
+            ) {
+                ::core::option::Option::Some(ticks) => ticks,
+                ::core::option::Option::None        => u64::MAX,
+            };
+
+            ::core::assert!(
+                ticks <= <$ty as $crate::Ticks>::MAX as u64,
+                "Duration doesn't fit into the tick type at this frequency",
+            );
+
+            ticks as $ty
+        };
+
+        TICKS
+    }};
+}
+
+
+/// Blocks on a non-blocking operation until an absolute point in time
+///
+/// Expects three arguments:
+///
+/// - A mutable reference to a [`Clock`]
+/// - The value of the clock at which to give up
+/// - An expression that evaluates to `nb::Result<T, E>`
+///
+/// Works like [`block_timeout`], but instead of a timer that measures a
+/// duration, it takes an absolute point in time. This is useful for periodic
+/// work: If the next point in time is computed by adding the period to the
+/// previous one, the schedule doesn't drift, no matter how long each
+/// iteration takes. Evaluates to `Result<T, TimeoutError<E>>`.
+///
+/// Clock values are compared in a wrap-safe way, the same way [`Alarm`] does,
+/// so the point in time must be less than [`Ticks::HALF`] in the future.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_until,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 7; self.0 }
+/// # }
+/// #
+/// # let mut clock = Clock(0);
+/// # let mut sample_ready = || Err::<u16, _>(nb::Error::<()>::WouldBlock);
+///
+/// const PERIOD: u32 = 100;
+///
+/// let mut next = PERIOD;
+///
+/// for _ in 0..3 {
+///     let result = block_until!(&mut clock, next, sample_ready());
+///     assert_eq!(result, Err(TimeoutError::Timeout));
+///
+///     // The next period starts where the last one ended, however long it
+///     // actually took to get here.
+///     next = next.wrapping_add(PERIOD);
+/// }
+/// ```
+///
+/// [`Alarm`]: crate::Alarm
+/// [`Clock`]: crate::Clock
+/// [`Ticks::HALF`]: crate::Ticks::HALF
+#[macro_export]
+macro_rules! block_until {
+    ($clock:expr, $at:expr, $op:expr) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            // Make sure the clock has the right type. If it hasn't, the user
+            // should at least get a good error message.
+            fn check_type<C>(_: &mut C)
+                where C: $crate::Clock + ?Sized {}
+            check_type($clock);
+
+            let at = $at;
+
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                let now = $crate::Clock::now($clock);
+                let reached =
+                    $crate::Ticks::wrapping_sub(now, at) < $crate::Ticks::HALF;
+                if (!$crate::NO_TIMEOUTS && reached) || bound.exhausted() {
+                    bound.expired();
+                    break Err($crate::TimeoutError::Timeout);
+                }
+
+                match $op {
+                    Ok(result) =>
+                        break Ok(result),
+                    Err($crate::nb::Error::WouldBlock) =>
+                        $crate::__private::idle(),
+                    Err($crate::nb::Error::Other(error)) =>
+                        break Err($crate::TimeoutError::Other(error)),
+                }
+            }
+        }
+    }
+}
+
+/// Blocks on a non-blocking operation, using a clock that can fail to be read
+///
+/// Expects three arguments:
+///
+/// - An expression that reads the clock, and evaluates to
+///   `Result<I, C>`, where `I` is the clock's instant type
+/// - A duration that can be added to an instant
+/// - An expression that evaluates to `nb::Result<T, E>`
+///
+/// Some clocks can fail to be read, like the clocks of `embedded-time`, whose
+/// `try_now` returns a `Result`. This macro reads the clock once to compute
+/// the deadline, then again before every evaluation of the operation, and
+/// compares the two. The instant type must implement `PartialOrd`, and adding
+/// the duration to it must yield another instant.
+///
+/// Evaluates to `Result<T, ClockTimeoutError<C, E>>`. If reading the clock
+/// fails, waiting stops, and the error is returned as
+/// `ClockTimeoutError::Clock`. Like [`block_until`], this doesn't need a
+/// [`TimeoutTimer`], so the crate-wide default idle strategy is used between
+/// polls.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     clock_timeout,
+///     ClockTimeoutError,
+/// };
+///
+/// /// A clock whose reads can fail, like an `embedded_time::Clock`
+/// struct Clock(u32);
+///
+/// impl Clock {
+///     fn try_now(&mut self) -> Result<u32, &'static str> {
+///         self.0 += 10;
+///         Ok(self.0)
+///     }
+/// }
+///
+/// let mut clock = Clock(0);
+///
+/// let result: Result<(), ClockTimeoutError<_, ()>> = clock_timeout!(
+///     clock.try_now(),
+///     100,
+///     Err(nb::Error::WouldBlock)
+/// );
+///
+/// assert_eq!(result, Err(ClockTimeoutError::Timeout));
+/// assert_eq!(clock.0, 110);
+/// ```
+///
+/// [`block_until`]: crate::block_until
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[macro_export]
+macro_rules! clock_timeout {
+    ($now:expr, $duration:expr, $op:expr) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            match $now {
+                Ok(start) => {
+                    let deadline = start + $duration;
+
+                    let mut bound = $crate::__private::PollBound::new();
+
+                    loop {
+                        let now = match $now {
+                            Ok(now) =>
+                                now,
+                            Err(error) =>
+                                break Err($crate::ClockTimeoutError::Clock(
+                                    error
+                                )),
+                        };
+                        if (!$crate::NO_TIMEOUTS && now >= deadline)
+                            || bound.exhausted()
+                        {
+                            bound.expired();
+                            break Err($crate::ClockTimeoutError::Timeout);
+                        }
+
+                        match $op {
+                            Ok(result) =>
+                                break Ok(result),
+                            Err($crate::nb::Error::WouldBlock) =>
+                                $crate::__private::idle(),
+                            Err($crate::nb::Error::Other(error)) =>
+                                break Err($crate::ClockTimeoutError::Other(
+                                    error
+                                )),
+                        }
+                    }
+                }
+                Err(error) =>
+                    Err($crate::ClockTimeoutError::Clock(error)),
+            }
+        }
+    }
+}
+
+/// Waits until a register read satisfies a condition, or a timer times out
+///
+/// Expects a timer that implements [`TimeoutTimer`], and either a condition,
+/// or the value of a register, along with a mask and the expected value of
+/// the masked bits. The condition (or the register value) is evaluated anew in
+/// every iteration, so it can read a register directly, like the register
+/// APIs generated by `svd2rust` do.
+///
+/// Evaluates to `Result<(), TimeoutError>`. This replaces the unbounded
+/// `while` loops that are common in clock, PLL, and flash initialization code.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::cell::Cell;
+///
+/// use embedded_timeout_macros::{
+///     wait_for_reg,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// // Stands in for a status register, where the PLL sets bit 25 once it's
+/// // locked.
+/// let cr = Cell::new(0u32);
+/// let read_cr = || {
+///     cr.set(cr.get() + 0x0020_0000);
+///     cr.get()
+/// };
+///
+/// // With `svd2rust`, this could be `rcc.cr.read().pllrdy().bit_is_set()`.
+/// let result = wait_for_reg!(&mut timer, read_cr() & (1 << 25) != 0);
+/// assert_eq!(result, Ok(()));
+///
+/// // The same, using a mask and the expected value of the masked bits
+/// let result = wait_for_reg!(&mut timer, read_cr(), 0b11 << 25, 0b11 << 25);
+/// assert_eq!(result, Ok(()));
+/// # let _: Result<(), TimeoutError> = result;
+/// ```
+///
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[macro_export]
+macro_rules! wait_for_reg {
+    ($timer:expr, $value:expr, $mask:expr, $expected:expr $(,)?) => {
+        $crate::wait_for_reg!($timer, ($value) & ($mask) == ($expected))
+    };
+    ($timer:expr, $condition:expr $(,)?) => {
+        {
+            let result: Result<(), $crate::TimeoutError> =
+                $crate::block_timeout!(
+                    $timer,
+                    if $condition {
+                        Ok(())
+                    }
+                    else {
+                        Err($crate::nb::Error::WouldBlock)
+                    }
+                );
+            result
+        }
+    };
+}
+
+/// Waits while a condition remains true, or until a timer times out
+///
+/// The complement of [`wait_for_reg`]: Expects a timer that implements
+/// [`TimeoutTimer`], and a condition that is evaluated anew in every
+/// iteration. Waits as long as the condition is true, for example while a
+/// BUSY flag is set. This avoids having to negate the condition, which is
+/// easily overlooked.
+///
+/// Evaluates to `Result<(), TimeoutError>`.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::cell::Cell;
+///
+/// use embedded_timeout_macros::wait_while;
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// const BUSY: u8 = 0x01;
+///
+/// let status = Cell::new(8u8);
+/// let read_status = || {
+///     status.set(status.get() - 1);
+///     status.get()
+/// };
+///
+/// let result = wait_while!(&mut timer, read_status() & BUSY != 0);
+/// assert_eq!(result, Ok(()));
+/// assert_eq!(status.get(), 6);
+/// ```
+///
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+/// [`wait_for_reg`]: crate::wait_for_reg
+#[macro_export]
+macro_rules! wait_while {
+    ($timer:expr, $condition:expr $(,)?) => {
+        $crate::wait_for_reg!($timer, !($condition))
+    };
+}
+
+/// Blocks on a non-blocking operation until a timer times out, or the wait is
+/// cancelled
+///
+/// Expects three arguments:
+///
+/// - A timer that implements [`TimeoutTimer`]
+/// - A cancellation condition, an expression that evaluates to `bool`
+/// - An expression that evaluates to `nb::Result<T, E>`
+///
+/// Works like [`block_timeout`], but the cancellation condition is evaluated
+/// once per iteration, before the timer is checked. As soon as it is `true`,
+/// waiting stops. The condition could check a flag set by an interrupt
+/// handler, or whether a button has been pressed, for example.
+///
+/// Evaluates to [`WaitOutcome<T, E>`], which has a separate variant for
+/// cancellation.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::sync::atomic::{
+///     AtomicBool,
+///     Ordering,
+/// };
+///
+/// use embedded_timeout_macros::{
+///     block_cancellable,
+///     WaitOutcome,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// static ABORT: AtomicBool = AtomicBool::new(false);
+///
+/// let mut polls = 0;
+/// let outcome: WaitOutcome<(), ()> = block_cancellable!(
+///     &mut timer,
+///     ABORT.load(Ordering::Acquire),
+///     {
+///         polls += 1;
+///         if polls == 3 {
+///             // Stands in for an interrupt handler.
+///             ABORT.store(true, Ordering::Release);
+///         }
+///         Err(nb::Error::WouldBlock)
+///     }
+/// );
+///
+/// assert_eq!(outcome, WaitOutcome::Cancelled);
+/// assert_eq!(polls, 3);
+/// ```
+///
+/// [`block_timeout`]: crate::block_timeout
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+/// [`WaitOutcome<T, E>`]: crate::WaitOutcome
+#[macro_export]
+macro_rules! block_cancellable {
+    ($timer:expr, $cancel:expr, $op:expr) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            // Make sure the timer has the right type. If it hasn't, the user
+            // should at least get a good error message.
+            fn check_type<T>(_: &mut T)
+                where T: $crate::TimeoutTimer + ?Sized {}
+            check_type($timer);
+
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                if $cancel {
+                    break $crate::WaitOutcome::Cancelled;
+                }
+                if $crate::__private::is_expired($timer)
+                    || bound.exhausted()
+                {
+                    bound.expired();
+                    break $crate::WaitOutcome::TimedOut;
+                }
+
+                match $op {
+                    Ok(result) =>
+                        break $crate::WaitOutcome::Done(result),
+                    Err($crate::nb::Error::WouldBlock) =>
+                        $crate::TimeoutTimer::idle($timer),
+                    Err($crate::nb::Error::Other(error)) =>
+                        break $crate::WaitOutcome::Other(error),
+                }
+            }
+        }
+    }
+}
+
+/// Blocks on a non-blocking operation until a deadline expires, reporting the
+/// remaining time on success
+///
+/// Expects two arguments:
+///
+/// - A mutable reference to a [`Deadline`]
+/// - An expression that evaluates to `nb::Result<T, E>`
+///
+/// Works like [`block_timeout`], except that on success, the result comes
+/// with the number of ticks that are left until the deadline expires. This can
+/// be used to pass the remaining budget on to the next operation. Evaluates to
+/// `Result<(T, Ticks), TimeoutError<E>>`.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_remaining,
+///     Deadline,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+/// #
+/// # let mut clock = Clock(0);
+///
+/// let mut deadline = Deadline::start(&mut clock, 100);
+///
+/// let result: Result<_, TimeoutError<()>> = block_timeout_remaining!(
+///     &mut deadline,
+///     Ok(())
+/// );
+///
+/// let ((), remaining) = result.unwrap();
+///
+/// // Give the next operation whatever is left of the budget.
+/// let mut deadline = Deadline::start(&mut clock, remaining);
+/// # let _ = deadline.is_expired();
+/// ```
+///
+/// [`Deadline`]: crate::Deadline
+#[macro_export]
+macro_rules! block_timeout_remaining {
+    ($deadline:expr, $op:expr) => {
+        match $crate::block_timeout!($deadline, $op) {
+            Ok(result) =>
+                Ok((result, $crate::Deadline::remaining($deadline))),
+            Err(error) =>
+                Err(error),
+        }
+    }
+}
+
+/// Blocks on a non-blocking operation until a deadline expires, reporting the
+/// elapsed time on success
+///
+/// Expects two arguments:
+///
+/// - A mutable reference to a [`Deadline`]
+/// - An expression that evaluates to `nb::Result<T, E>`
+///
+/// Works like [`block_timeout`], except that on success, the result comes
+/// with the number of ticks that have elapsed since the deadline was started.
+/// This is useful for logging operations that succeeded, but took longer than
+/// expected. Evaluates to `Result<(T, Ticks), TimeoutError<E>>`.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_elapsed,
+///     Deadline,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+/// #
+/// # let clock = Clock(0);
+///
+/// let mut deadline = Deadline::start(clock, 100);
+///
+/// let result: Result<_, TimeoutError<()>> = block_timeout_elapsed!(
+///     &mut deadline,
+///     Ok(())
+/// );
+///
+/// let ((), elapsed) = result.unwrap();
+/// if elapsed > 50 {
+///     // slow, but successful
+/// }
+/// ```
+///
+/// [`Deadline`]: crate::Deadline
+#[macro_export]
+macro_rules! block_timeout_elapsed {
+    ($deadline:expr, $op:expr) => {
+        match $crate::block_timeout!($deadline, $op) {
+            Ok(result) =>
+                Ok((result, $crate::Deadline::elapsed($deadline))),
+            Err(error) =>
+                Err(error),
+        }
+    }
+}
+
+/// Blocks on a non-blocking operation until a deadline expires, recording its
+/// worst-case execution time
+///
+/// Expects four arguments:
+///
+/// - A mutable reference to a [`SiteWcet`]
+/// - The label of the call site, as a `&'static str`
+/// - A mutable reference to a [`Deadline`]
+/// - An expression that evaluates to `nb::Result<T, E>`
+///
+/// Works like [`block_timeout`], and evaluates to the same result. In
+/// addition, the number of polls, and the ticks that have elapsed since the
+/// deadline was started, are recorded under the label. If there's no room for
+/// the label, nothing is recorded.
+///
+/// Only available, if the `stats` feature is enabled.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_wcet,
+///     stats::SiteWcet,
+///     Deadline,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+///
+/// let mut wcet = SiteWcet::<8>::new();
+///
+/// for _ in 0..3 {
+///     let mut deadline = Deadline::start(Clock(0), 100);
+///     let mut polls = 0;
+///
+///     let result: Result<(), TimeoutError<()>> = block_timeout_wcet!(
+///         &mut wcet,
+///         "spi flash busy",
+///         &mut deadline,
+///         {
+///             polls += 1;
+///             if polls < 4 { Err(nb::Error::WouldBlock) } else { Ok(()) }
+///         }
+///     );
+///     assert_eq!(result, Ok(()));
+/// }
+///
+/// let site = wcet.get("spi flash busy").unwrap();
+/// assert_eq!(site.count(), 3);
+/// assert_eq!(site.max_polls(), 4);
+/// ```
+///
+/// [`Deadline`]: crate::Deadline
+/// [`SiteWcet`]: crate::stats::SiteWcet
+#[cfg(feature = "stats")]
+#[macro_export]
+macro_rules! block_timeout_wcet {
+    ($wcet:expr, $label:expr, $deadline:expr, $op:expr) => {
+        {
+            let mut polls: u32 = 0;
+
+            let result = $crate::block_timeout!($deadline, {
+                polls = polls.saturating_add(1);
+                $op
+            });
+
+            // Not having room for the label only means the site isn't
+            // tracked, which shouldn't affect the operation.
+            let _ = match result {
+                Err($crate::TimeoutError::Timeout) =>
+                    $crate::stats::SiteWcet::record_timeout($wcet, $label),
+                _ =>
+                    $crate::stats::SiteWcet::record(
+                        $wcet,
+                        $label,
+                        polls,
+                        $crate::Ticks::into_u64(
+                            $crate::Deadline::elapsed($deadline)
+                        ),
+                    ),
+            };
+
+            result
+        }
+    }
+}
+
+/// Blocks on a non-blocking operation, with a fixed maximum number of polls
+///
+/// Expects three arguments:
+///
+/// - The maximum number of times the operation is polled, as a `u32` constant
+/// - A timer that implements [`TimeoutTimer`]
+/// - An expression that evaluates to `nb::Result<T, E>`
+///
+/// Works like [`block_timeout`], except that the wait loop has a bound that is
+/// known at compile time. This guarantees that the loop terminates, even if
+/// the timer hardware fails and never reports expiry, which safety reviews
+/// often require. The maximum must be a constant expression, so it can't
+/// accidentally depend on runtime state.
+///
+/// Evaluates to `Result<T, BoundedError<E>>`. If the maximum number of polls
+/// is reached, [`BoundedError::LimitReached`] is returned, carrying the
+/// maximum. Like in [`block_timeout`], [`bail_timeout`] can be used within the
+/// operation, which then results in `BoundedError::Other`.
+///
+/// To put a bound on all wait loops of this crate, enable the `bounded`
+/// feature. See `MAX_POLLS` for details.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_bounded,
+///     BoundedError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut broken_timer = Timer;
+///
+/// const MAX_POLLS: u32 = 10_000;
+///
+/// // The timer never expires, but the loop terminates anyway.
+/// let result: Result<(), BoundedError<()>> = block_timeout_bounded!(
+///     MAX_POLLS,
+///     &mut broken_timer,
+///     Err(nb::Error::WouldBlock)
+/// );
+///
+/// assert_eq!(result, Err(BoundedError::LimitReached(10_000)));
+/// ```
+///
+/// Using [`bail_timeout`] within the operation:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     bail_timeout,
+///     block_timeout_bounded,
+///     BoundedError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer;
+/// # let read_status = || 0xffu8;
+///
+/// let result: Result<(), BoundedError<&'static str>> = block_timeout_bounded!(
+///     100,
+///     &mut timer,
+///     {
+///         if read_status() == 0xff {
+///             bail_timeout!("device not present");
+///         }
+///
+///         Err(nb::Error::WouldBlock)
+///     }
+/// );
+///
+/// assert_eq!(result, Err(BoundedError::Other("device not present")));
+/// ```
+///
+/// [`bail_timeout`]: crate::bail_timeout
+/// [`BoundedError::LimitReached`]: crate::BoundedError::LimitReached
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[macro_export]
+macro_rules! block_timeout_bounded {
+    ($max:expr, $timer:expr, $op:expr) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            // Make sure the timer has the right type. If it hasn't, the user
+            // should at least get a good error message.
+            fn check_type<T>(_: &mut T)
+                where T: $crate::TimeoutTimer + ?Sized {}
+            check_type($timer);
+
+            // Using a constant makes sure the bound is known at compile time.
+            const MAX: u32 = $max;
+
+            let mut polls: u32 = 0;
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                if polls >= MAX {
+                    break Err($crate::BoundedError::LimitReached(MAX));
+                }
+                polls += 1;
+
+                if $crate::__private::is_expired($timer) {
+                    bound.expired();
+                    break Err($crate::BoundedError::Timeout);
+                }
+                bound.polled();
+
+                match $op {
+                    Ok(result) =>
+                        break Ok(result),
+                    Err($crate::nb::Error::WouldBlock) =>
+                        $crate::TimeoutTimer::idle($timer),
+                    Err($crate::nb::Error::Other(error)) =>
+                        break Err($crate::BoundedError::Other(error)),
+                }
+            }
+        }
+    }
+}
+
+/// Blocks on a non-blocking operation, and aborts it, if it times out
+///
+/// Expects three arguments:
+///
+/// - A timer that implements [`TimeoutTimer`]
+/// - An expression that evaluates to `nb::Result<T, E>` (the operation)
+/// - `abort (<timer>, <budget>) <expr>`, where `<timer>` implements
+///   [`StartTimer`], and `<expr>` evaluates to `nb::Result<U, A>` (the abort)
+///
+/// Works like [`block_timeout`], until the operation times out. Then the abort
+/// timer is started with the budget, and the abort is waited for, until it
+/// finishes or that timer expires too. This is the place to send a STOP
+/// condition, or to flush a FIFO, which often have to be waited for
+/// themselves, but shouldn't hold things up for long.
+///
+/// The abort timer can be the same timer as the first one, if that implements
+/// [`StartTimer`]. Starting the abort timer must not be able to fail, so its
+/// [`StartTimer::Error`] must be `Infallible`, which is the case for
+/// [`Deadline`] and `CountDown` timers.
+///
+/// Evaluates to `Result<T, AbortError<E, A>>`. The variants of [`AbortError`]
+/// tell whether the abort succeeded, failed, or timed out too. Errors of the
+/// operation are returned as [`AbortError::Other`], without aborting.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_abort,
+///     AbortError,
+///     Deadline,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+///
+/// let mut deadline = Deadline::start(Clock(0), 100);
+/// let mut stop_sent = false;
+///
+/// let result: Result<u8, AbortError<(), ()>> = block_timeout_abort!(
+///     &mut deadline,
+///     // The device never answers.
+///     Err(nb::Error::WouldBlock),
+///     abort (&mut deadline, 10) {
+///         stop_sent = true;
+///         Ok(())
+///     }
+/// );
+///
+/// assert_eq!(result, Err(AbortError::Aborted));
+/// assert!(stop_sent);
+/// ```
+///
+/// [`AbortError`]: crate::AbortError
+/// [`AbortError::Other`]: crate::AbortError::Other
+/// [`Deadline`]: crate::Deadline
+/// [`StartTimer`]: crate::StartTimer
+/// [`StartTimer::Error`]: crate::StartTimer::Error
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[macro_export]
+macro_rules! block_timeout_abort {
+    (
+        $timer:expr,
+        $op:expr,
+        abort ($abort_timer:expr, $budget:expr) $abort:expr $(,)?
+    ) => {
+        match $crate::block_timeout!($timer, $op) {
+            Ok(value) =>
+                Ok(value),
+            Err($crate::TimeoutError::Other(error)) =>
+                Err($crate::AbortError::Other(error)),
+            Err($crate::TimeoutError::Timeout) => {
+                let started: Result<(), core::convert::Infallible> =
+                    $crate::StartTimer::try_start($abort_timer, $budget);
+                if let Err(error) = started {
+                    match error {}
+                }
+
+                match $crate::block_timeout!($abort_timer, $abort) {
+                    Ok(_) =>
+                        Err($crate::AbortError::Aborted),
+                    Err($crate::TimeoutError::Other(error)) =>
+                        Err($crate::AbortError::AbortFailed(error)),
+                    Err($crate::TimeoutError::Timeout) =>
+                        Err($crate::AbortError::AbortTimeout),
+                }
+            }
+        }
+    }
+}
+
+/// Repeats an operation until a timer times out
+///
+/// Expects four arguments:
+///
+/// - A timer that implements [`TimeoutTimer`], like an
+///   `embedded_hal::timer::CountDown` timer or a [`Deadline`]
+/// - An expression that evaluates to `Result<T, E>` (the operation)
+/// - A pseudo-closure that will be called every time the operation succeeds
+///   This pseudo-closure is expected to take an argument of type `T`. The
+///   return value is ignored.
+/// - A pseudo-closure that will be called every time the operation fails
+///   This pseudo-closure is expected to take an argument of type `E`. The
+///   return value is ignored.
+///
+/// `repeat_timeout!` will keep repeating the operation until the timer runs
+/// out, no matter whether it suceeds or fails.
+///
+/// It uses a `loop` to do that, which is `break`s from when the timer runs out.
+/// Any of the expressions passed into the macro, the main expression, as well
+/// as the two pseudo-closures, can employ `break` and `continue` to manipulate
+/// that loop.
+///
+/// By default, the operation is repeated as fast as possible. To limit how
+/// often it is attempted, add `pace <expr>;` after the pseudo-closures, where
+/// `<expr>` implements [`Pace`]. See the [`pace`] module for the available
+/// options.
+///
+/// As a structured alternative to `break`, write `flow` in front of both
+/// pseudo-closures. Then they must evaluate to `ControlFlow<B, ()>`.
+/// `ControlFlow::Break(value)` ends the loop, and `ControlFlow::Continue(())`
+/// keeps it going. In this form, the macro evaluates to
+/// `ControlFlow::Break(value)`, if one of the pseudo-closures returned that,
+/// or to `ControlFlow::Continue(())`, if the timer ran out. This makes it
+/// possible to move the handlers into functions.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     repeat_timeout,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer(3);
+///
+/// repeat_timeout!(
+///     &mut timer,
+///     {
+///         // The macro will keep evaluating this expression repeatedly until
+///         // the timer times out.
+///         //
+///         // We can do anything that returns `Result` here. For this simple
+///         // example, we just return `Ok`.
+///         Ok(())
+///
+///         // We could also return an error.
+///         // Err("This is an error")
+///     },
+///     // Here's a pseudo-closure with an argument in parentheses, which we can
+///     // name freely, followed by an expression whose return value is ignored.
+///     (result) {
+///         // The macro will evaluate this expression, if the main expression
+///         // above returns `Ok`. `result`, which we've named in the
+///         // parentheses above, will be whatever the contents of the `Ok` are.
+///         let result: () = result;
+///     };
+///     (error) {
+///         // will be called by the macro, if the expression returns `Err`
+///         let error: &'static str = error;
+///     };
+/// );
+/// ```
+///
+/// Pacing the operation, using a periodic timer:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     repeat_timeout,
+///     timer::pace::Periodic,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 -= 1;
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # struct Ticker(bool);
+/// #
+/// # impl embedded_hal::timer::CountDown for Ticker {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 = !self.0;
+/// #         if self.0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer(6);
+/// # let every_10_ms = Ticker(true);
+///
+/// let mut samples = 0;
+///
+/// repeat_timeout!(
+///     &mut timer,
+///     Ok::<_, ()>(()),
+///     (_sample) { samples += 1; };
+///     (_error) {};
+///     pace Periodic(every_10_ms);
+/// );
+/// # assert_eq!(samples, 3);
+/// ```
+///
+/// Ending the loop using `ControlFlow`:
+///
+/// ``` rust
+/// use core::ops::ControlFlow;
+///
+/// use embedded_timeout_macros::repeat_timeout;
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         self.0 == 0
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer(100);
+///
+/// fn check_sample(sample: u16) -> ControlFlow<u16> {
+///     if sample > 1000 {
+///         ControlFlow::Break(sample)
+///     }
+///     else {
+///         ControlFlow::Continue(())
+///     }
+/// }
+///
+/// let mut samples = [12, 980, 1003, 17].iter().copied();
+///
+/// let flow = repeat_timeout!(
+///     &mut timer,
+///     samples.next().ok_or("no sample"),
+///     flow (sample) check_sample(sample);
+///     flow (_error) ControlFlow::Continue(());
+/// );
+///
+/// assert_eq!(flow, ControlFlow::Break(1003));
+/// ```
+///
+/// [`Deadline`]: crate::Deadline
+/// [`Pace`]: crate::timer::pace::Pace
+/// [`pace`]: crate::timer::pace
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[macro_export]
+macro_rules! repeat_timeout {
+    (
+        $timer:expr,
+        $op:expr,
+        flow ($result:ident) $on_success:expr;
+        flow ($error:ident) $on_error:expr;
+        $(pace $pace:expr;)?
+    ) => {
+        {
+            let mut flow = core::ops::ControlFlow::Continue(());
+
+            $crate::repeat_timeout!(
+                $timer,
+                $op,
+                ($result) {
+                    if let core::ops::ControlFlow::Break(value) = $on_success {
+                        flow = core::ops::ControlFlow::Break(value);
+                        break;
+                    }
+                };
+                ($error) {
+                    if let core::ops::ControlFlow::Break(value) = $on_error {
+                        flow = core::ops::ControlFlow::Break(value);
+                        break;
+                    }
+                };
+                $(pace $pace;)?
+            );
+
+            flow
+        }
+    };
+    (
+        $timer:expr,
+        $op:expr,
+        ($result:ident) $on_success:expr;
+        ($error:ident) $on_error:expr;
+        pace $pace:expr;
+    ) => {
+        {
+            let mut pace = $pace;
+            let mut first = true;
+
+            $crate::repeat_timeout!(
+                $timer,
+                {
+                    // Pace before every attempt but the first, so the timer
+                    // is checked once more right after pacing.
+                    if !first
+                        && !$crate::timer::pace::Pace::pace(&mut pace, $timer)
+                    {
+                        break;
+                    }
+                    first = false;
+
+                    $op
+                },
+                ($result) $on_success;
+                ($error) $on_error;
+            )
+        }
+    };
+    (
+        $timer:expr,
+        $op:expr,
+        ($result:ident) $on_success:expr;
+        ($error:ident) $on_error:expr;
+    ) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            // Make sure the timer has the right type. If it hasn't, the user
+            // should at least get a good error message.
+            fn check_type<T>(_: &mut T)
+                where T: $crate::TimeoutTimer + ?Sized {}
+            check_type($timer);
+
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                if $crate::__private::is_expired($timer)
+                    || bound.exhausted()
+                {
+                    bound.expired();
+                    break;
+                }
+
+                match $op {
+                    Ok(result) => {
+                        let $result = result;
+                        $on_success;
+                    }
+                    Err(error) => {
+                        let $error = error;
+                        $on_error;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Samples an operation until a timer times out, combining the values
+///
+/// Expects three arguments:
+///
+/// - A timer that implements [`TimeoutTimer`]
+/// - An expression that evaluates to `nb::Result<T, E>`
+/// - A [`Reducer`] for values of type `T`
+///
+/// Unlike [`block_timeout`], this doesn't stop at the first value. It keeps
+/// evaluating the expression until the timer expires, and pushes every value
+/// into the reducer. Evaluates to `Result<R::Output, TimeoutError<E>>`, where
+/// `R` is the type of the reducer: `Ok` with the combined result, or
+/// `Err(TimeoutError::Timeout)`, if the expression didn't produce a single
+/// value. If the expression returns an error, sampling stops, and the macro
+/// evaluates to `Err(TimeoutError::Other(error))`.
+///
+/// Only available, if the `sample` feature is enabled. See the [`sample`]
+/// module for an example.
+///
+/// [`block_timeout`]: crate::block_timeout
+/// [`Reducer`]: crate::sample::Reducer
+/// [`sample`]: crate::sample
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[cfg(feature = "sample")]
+#[macro_export]
+macro_rules! sample_timeout {
+    ($timer:expr, $op:expr, $reducer:expr $(,)?) => {
+        {
+            // The operation might rely on the `embedded-hal` traits being in
+            // scope, so keep importing them, even though we don't need them.
+            #[allow(unused_imports)]
+            use $crate::__private::prelude::*;
+
+            // Make sure the timer has the right type. If it hasn't, the user
+            // should at least get a good error message.
+            fn check_type<T>(_: &mut T)
+                where T: $crate::TimeoutTimer + ?Sized {}
+            check_type($timer);
+
+            let mut reducer = $reducer;
+            let mut bound = $crate::__private::PollBound::new();
+
+            loop {
+                if $crate::__private::is_expired($timer)
+                    || bound.exhausted()
+                {
+                    bound.expired();
+                    break $crate::sample::Reducer::finish(reducer)
+                        .ok_or($crate::TimeoutError::Timeout);
+                }
+
+                match $op {
+                    Ok(value) =>
+                        $crate::sample::Reducer::push(&mut reducer, value),
+                    Err($crate::nb::Error::WouldBlock) =>
+                        $crate::TimeoutTimer::idle($timer),
+                    Err($crate::nb::Error::Other(error)) =>
+                        break Err($crate::TimeoutError::Other(error)),
+                }
+            }
+        }
+    };
+}
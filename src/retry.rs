@@ -0,0 +1,1574 @@
+//! Retrying failed operations, with configurable backoff
+//!
+//! [`retry_with_backoff`] has an async counterpart in the `asynch` module.
+//! Both are generated from the same source, so they make the same decisions
+//! about when to retry and when to give up. That's the only function in this
+//! module with an async flavor. [`Retrier`] and [`await_ready`] only exist as
+//! blocking code, with loops of their own, as they do more than retrying: the
+//! [`Retrier`] notifies an [`Observer`] and can save its state between
+//! attempts, and [`await_ready`] waits for a warm-up first.
+//!
+//! This module is only available, if the `retry` feature is enabled (which it
+//! is by default).
+
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
+use core::{
+    convert::Infallible,
+    time::Duration,
+};
+
+use crate::{
+    block_timeout,
+    bound::{
+        is_expired,
+        PollBound,
+    },
+    codes,
+    ticks_from_duration,
+    Clock,
+    Delay,
+    IntoTimeoutOp,
+    Ticks,
+    TimedOperation,
+    TimeoutError,
+    TimeoutTimer,
+};
+
+
+/// Generates the body of both flavors of `retry_with_backoff`
+///
+/// `attempt` runs a single attempt, and evaluates to
+/// `Result<O, TimeoutError<E>>`. `delay` waits for `$delay_us`, and evaluates
+/// to `Result<(), TimeoutError>`. The async flavor awaits within both.
+macro_rules! retry_with_backoff_body {
+    (
+        $backoff:expr,
+        $classify:expr,
+        $max_attempts:expr,
+        attempt $attempt:expr,
+        delay ($delay_us:ident) $delay:expr
+    ) => {
+        {
+            $crate::retry::Backoff::reset($backoff);
+
+            let mut attempt = 1;
+
+            loop {
+                let error = match $attempt {
+                    Ok(output) =>
+                        return Ok(output),
+                    Err($crate::TimeoutError::Other(error)) =>
+                        error,
+                    Err($crate::TimeoutError::Timeout) =>
+                        return Err($crate::retry::RetryError::Timeout),
+                };
+
+                let verdict = $crate::retry::Classify::classify(
+                    $classify,
+                    &error,
+                );
+                if verdict == $crate::retry::Verdict::Fail {
+                    return Err($crate::retry::RetryError::Fatal(error));
+                }
+                if let Some(max_attempts) = $max_attempts {
+                    if attempt >= max_attempts {
+                        return Err($crate::retry::RetryError::Exhausted(error));
+                    }
+                }
+
+                let $delay_us = $crate::retry::Backoff::next_delay(
+                    $backoff,
+                    attempt,
+                );
+                if $delay.is_err() {
+                    return Err($crate::retry::RetryError::Timeout);
+                }
+
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub(crate) use retry_with_backoff_body;
+
+
+/// Retries an operation until it succeeds, gives up, or a timer times out
+///
+/// A `Retrier` ties together all the pieces that are needed to retry an
+/// operation:
+///
+/// - A [`TimeoutTimer`] that limits the total duration
+/// - A delay provider that is used to wait between attempts
+/// - A [`Backoff`] strategy that decides how long to wait between attempts
+/// - A [`Classify`] implementation that decides which errors are worth
+///   retrying
+/// - An [`Observer`] that is notified about what's going on
+/// - An optional maximum number of attempts, and a label identifying the
+///   operation to the observer
+///
+/// Create one using [`Retrier::new`], configure it using the builder methods,
+/// then call [`Retrier::run`] as often as you like.
+///
+/// Please note that the timer is not restarted by `Retrier`. If you want to
+/// reuse a `Retrier`, you need to restart the timer before each run, using
+/// [`Retrier::timer`].
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::retry::{
+///     Exponential,
+///     RetryError,
+///     Retrier,
+///     Verdict,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # struct Delay;
+/// #
+/// # impl embedded_hal::blocking::delay::DelayUs<u32> for Delay {
+/// #     fn delay_us(&mut self, _: u32) {}
+/// # }
+/// #
+/// # let timer = Timer;
+/// # let delay = Delay;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Error {
+///     Nack,
+///     BusFault,
+/// }
+///
+/// let mut retrier = Retrier::new(timer, delay)
+///     .backoff(Exponential::new(100, 10_000))
+///     .classify(|error: &Error| match error {
+///         Error::Nack     => Verdict::Retry,
+///         Error::BusFault => Verdict::Fail,
+///     })
+///     .max_attempts(3)
+///     .label("sensor read");
+///
+/// let mut attempts = 0;
+/// let result: Result<(), _> = retrier.run(|| {
+///     attempts += 1;
+///     Err(nb::Error::Other(Error::Nack))
+/// });
+///
+/// assert_eq!(result, Err(RetryError::Exhausted(Error::Nack)));
+/// assert_eq!(attempts, 3);
+/// ```
+pub struct Retrier<T, D, B, C, O> {
+    timer:        T,
+    delay:        D,
+    backoff:      B,
+    classify:     C,
+    observer:     O,
+    max_attempts: Option<u32>,
+    label:        &'static str,
+}
+
+/// A [`Retrier`] whose backoff strategy, classifier, and observer are boxed
+///
+/// This makes it possible to choose them at runtime, and to store retriers
+/// with different configurations in the same place. Use [`Retrier::boxed`] to
+/// create one.
+///
+/// Only available, if the `alloc` feature is enabled.
+#[cfg(feature = "alloc")]
+pub type BoxedRetrier<T, D, E> = Retrier<
+    T,
+    D,
+    Box<dyn Backoff>,
+    Box<dyn Classify<E>>,
+    Box<dyn Observer<E>>,
+>;
+
+impl<T, D> Retrier<T, D, NoBackoff, RetryAll, NoObserver>
+    where
+        T: TimeoutTimer,
+        D: Delay,
+{
+    /// Creates a new `Retrier`
+    ///
+    /// By default, the `Retrier` doesn't wait between attempts, retries all
+    /// errors, notifies nobody, and doesn't limit the number of attempts.
+    pub fn new(timer: T, delay: D) -> Self {
+        Retrier {
+            timer,
+            delay,
+            backoff:      NoBackoff,
+            classify:     RetryAll,
+            observer:     NoObserver,
+            max_attempts: None,
+            label:        "",
+        }
+    }
+}
+
+impl<T, D, B, C, O> Retrier<T, D, B, C, O> {
+    /// Sets the backoff strategy
+    pub fn backoff<B2>(self, backoff: B2) -> Retrier<T, D, B2, C, O>
+        where B2: Backoff
+    {
+        Retrier {
+            timer:        self.timer,
+            delay:        self.delay,
+            backoff,
+            classify:     self.classify,
+            observer:     self.observer,
+            max_attempts: self.max_attempts,
+            label:        self.label,
+        }
+    }
+
+    /// Sets the error classifier
+    ///
+    /// Any closure that takes a reference to the error and returns a
+    /// [`Verdict`] can be used as a classifier.
+    pub fn classify<C2>(self, classify: C2) -> Retrier<T, D, B, C2, O> {
+        Retrier {
+            timer:        self.timer,
+            delay:        self.delay,
+            backoff:      self.backoff,
+            classify,
+            observer:     self.observer,
+            max_attempts: self.max_attempts,
+            label:        self.label,
+        }
+    }
+
+    /// Sets the observer
+    pub fn observer<O2>(self, observer: O2) -> Retrier<T, D, B, C, O2> {
+        Retrier {
+            timer:        self.timer,
+            delay:        self.delay,
+            backoff:      self.backoff,
+            classify:     self.classify,
+            observer,
+            max_attempts: self.max_attempts,
+            label:        self.label,
+        }
+    }
+
+    /// Limits the number of attempts
+    ///
+    /// An attempt ends when the operation returns an error. `WouldBlock`
+    /// doesn't count as the end of an attempt.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Sets the label that identifies the operation to the observer
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// Boxes the backoff strategy, classifier, and observer
+    ///
+    /// Only available, if the `alloc` feature is enabled.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// use embedded_timeout_macros::retry::{
+    ///     BoxedRetrier,
+    ///     Constant,
+    ///     Retrier,
+    /// };
+    /// #
+    /// # struct Timer;
+    /// #
+    /// # impl embedded_hal::timer::CountDown for Timer {
+    /// #     type Time = ();
+    /// #     fn start<T>(&mut self, _: T) {}
+    /// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+    /// #         Err(nb::Error::WouldBlock)
+    /// #     }
+    /// # }
+    /// #
+    /// # struct Delay;
+    /// #
+    /// # impl embedded_hal::blocking::delay::DelayUs<u32> for Delay {
+    /// #     fn delay_us(&mut self, _: u32) {}
+    /// # }
+    /// # let slow_bus = true;
+    ///
+    /// let mut retrier: BoxedRetrier<_, _, ()> = if slow_bus {
+    ///     Retrier::new(Timer, Delay).backoff(Constant(1000)).boxed()
+    /// }
+    /// else {
+    ///     Retrier::new(Timer, Delay).boxed()
+    /// };
+    /// # let _ = retrier.run(|| Ok::<(), nb::Error<()>>(()));
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn boxed<E>(self) -> BoxedRetrier<T, D, E>
+        where
+            B: Backoff + 'static,
+            C: Classify<E> + 'static,
+            O: Observer<E> + 'static,
+    {
+        Retrier {
+            timer:        self.timer,
+            delay:        self.delay,
+            backoff:      Box::new(self.backoff),
+            classify:     Box::new(self.classify),
+            observer:     Box::new(self.observer),
+            max_attempts: self.max_attempts,
+            label:        self.label,
+        }
+    }
+
+    /// Provides access to the timer
+    ///
+    /// Use this to restart the timer before reusing the `Retrier`.
+    pub fn timer(&mut self) -> &mut T {
+        &mut self.timer
+    }
+
+    /// Runs the operation until it succeeds, gives up, or the timer times out
+    ///
+    /// The operation is polled until it returns `Ok` or an error. Errors are
+    /// passed to the classifier, and if they're worth retrying, the `Retrier`
+    /// waits for the delay determined by the backoff strategy, before starting
+    /// the next attempt.
+    ///
+    /// The timer is only checked between polls, not while waiting for the
+    /// backoff delay.
+    ///
+    /// Any [`TimedOperation`] can be used as the operation, including closures
+    /// that return `nb::Result`, as can anything that implements
+    /// [`IntoTimeoutOp`].
+    pub fn run<Op>(&mut self, op: Op)
+        -> Result<Op::Output, RetryError<Op::Error>>
+        where
+            T:  TimeoutTimer,
+            D:  Delay,
+            B:  Backoff,
+            C:  Classify<Op::Error>,
+            O:  Observer<Op::Error>,
+            Op: IntoTimeoutOp,
+    {
+        self.run_inner(None, |_| {}, op.into_timeout_op())
+    }
+
+    /// Runs the operation, persisting the retry state in the given storage
+    ///
+    /// Works like [`Retrier::run`], but before each backoff delay, the current
+    /// [`RetryState`] is written to `store`. If the storage survives a reset
+    /// (like backup RAM does), a device that loses power while retrying picks
+    /// up where it left off, instead of starting the backoff schedule from
+    /// scratch: The interrupted delay is waited for again, and the attempt
+    /// count continues from the stored value, including towards the maximum
+    /// number of attempts.
+    ///
+    /// The storage is cleared, once the run is over, no matter how it ended.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// use embedded_timeout_macros::retry::{
+    ///     Exponential,
+    ///     RetryError,
+    ///     RetryState,
+    ///     Retrier,
+    ///     StateStore,
+    /// };
+    /// #
+    /// # struct Timer;
+    /// #
+    /// # impl embedded_hal::timer::CountDown for Timer {
+    /// #     type Time = ();
+    /// #     fn start<T>(&mut self, _: T) {}
+    /// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+    /// #         Err(nb::Error::WouldBlock)
+    /// #     }
+    /// # }
+    /// #
+    /// # struct Delay;
+    /// #
+    /// # impl embedded_hal::blocking::delay::DelayUs<u32> for Delay {
+    /// #     fn delay_us(&mut self, _: u32) {}
+    /// # }
+    ///
+    /// // In a real application, this would be placed in backup RAM, using
+    /// // `#[link_section]`.
+    /// let mut backup_ram = [0u8; RetryState::SIZE];
+    ///
+    /// // We've been through 3 attempts before losing power.
+    /// backup_ram.store(Some(RetryState { attempt: 3, delay_us: 800 }));
+    ///
+    /// let mut retrier = Retrier::new(Timer, Delay)
+    ///     .backoff(Exponential::new(100, 10_000))
+    ///     .max_attempts(5);
+    ///
+    /// let mut attempts = 0;
+    /// let result: Result<(), _> = retrier.run_persistent(&mut backup_ram, || {
+    ///     attempts += 1;
+    ///     Err(nb::Error::Other("nack"))
+    /// });
+    ///
+    /// assert_eq!(result, Err(RetryError::Exhausted("nack")));
+    /// assert_eq!(attempts, 2);
+    /// assert_eq!(backup_ram.load(), None);
+    /// ```
+    pub fn run_persistent<S, Op>(&mut self, store: &mut S, op: Op)
+        -> Result<Op::Output, RetryError<Op::Error>>
+        where
+            T:  TimeoutTimer,
+            D:  Delay,
+            B:  Backoff,
+            C:  Classify<Op::Error>,
+            O:  Observer<Op::Error>,
+            S:  StateStore,
+            Op: IntoTimeoutOp,
+    {
+        let resume = store.load();
+        let result = self.run_inner(
+            resume,
+            |state| store.store(state),
+            op.into_timeout_op(),
+        );
+        store.store(None);
+        result
+    }
+
+    fn run_inner<Op, F>(
+        &mut self,
+        resume:   Option<RetryState>,
+        mut save: F,
+        mut op:   Op,
+    )
+        -> Result<Op::Output, RetryError<Op::Error>>
+        where
+            T:  TimeoutTimer,
+            D:  Delay,
+            B:  Backoff,
+            C:  Classify<Op::Error>,
+            O:  Observer<Op::Error>,
+            F:  FnMut(Option<RetryState>),
+            Op: TimedOperation,
+    {
+        self.backoff.reset();
+
+        let mut attempt = 1;
+        if let Some(state) = resume {
+            Delay::delay_us(&mut self.delay, state.delay_us);
+            attempt = state.attempt.saturating_add(1);
+        }
+
+        self.observer.on_attempt(self.label, attempt);
+
+        let mut bound = PollBound::new();
+
+        loop {
+            if self.timer.is_expired() || bound.exhausted() {
+                bound.expired();
+                self.observer.on_timeout(self.label, attempt);
+                return Err(RetryError::Timeout);
+            }
+
+            let error = match op.poll() {
+                Ok(value) => {
+                    self.observer.on_success(self.label, attempt);
+                    return Ok(value);
+                }
+                Err(nb::Error::WouldBlock) => {
+                    self.timer.idle();
+                    continue;
+                }
+                Err(nb::Error::Other(error)) =>
+                    error,
+            };
+
+            self.observer.on_error(self.label, attempt, &error);
+
+            if self.classify.classify(&error) == Verdict::Fail {
+                return Err(RetryError::Fatal(error));
+            }
+            if let Some(max_attempts) = self.max_attempts {
+                if attempt >= max_attempts {
+                    return Err(RetryError::Exhausted(error));
+                }
+            }
+
+            let delay_us = self.backoff.next_delay(attempt);
+            save(Some(RetryState { attempt, delay_us }));
+            Delay::delay_us(&mut self.delay, delay_us);
+
+            attempt += 1;
+            self.observer.on_attempt(self.label, attempt);
+        }
+    }
+}
+
+
+/// The state of a [`Retrier`] between two attempts
+///
+/// Written to a [`StateStore`] by [`Retrier::run_persistent`], before each
+/// backoff delay.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryState {
+    /// The number of the attempt that just failed, starting at 1
+    pub attempt: u32,
+
+    /// The delay before the next attempt, in microseconds
+    pub delay_us: u32,
+}
+
+impl RetryState {
+    /// The size of the serialized state in bytes
+    pub const SIZE: usize = 12;
+
+    const MAGIC: u32 = 0x5254_5259;
+
+    /// Serializes the state
+    ///
+    /// The serialized state includes a check value, so a storage slot that
+    /// contains garbage after a cold start isn't mistaken for valid state.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+
+        bytes[0..4].copy_from_slice(&self.attempt.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.delay_us.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.check().to_le_bytes());
+
+        bytes
+    }
+
+    /// Deserializes the state
+    ///
+    /// Returns `None`, if the bytes don't contain valid state.
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Option<Self> {
+        let word = |i: usize| {
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[i..i + 4]);
+            u32::from_le_bytes(word)
+        };
+
+        let state = RetryState {
+            attempt:  word(0),
+            delay_us: word(4),
+        };
+
+        if state.attempt == 0 || word(8) != state.check() {
+            return None;
+        }
+
+        Some(state)
+    }
+
+    fn check(&self) -> u32 {
+        Self::MAGIC ^ self.attempt ^ self.delay_us.rotate_left(16)
+    }
+}
+
+
+/// Storage for [`RetryState`] that can survive a reset
+///
+/// Implemented for byte arrays of the right size, which can be placed in
+/// backup RAM or another memory that isn't cleared on reset. Implement it
+/// directly to store the state elsewhere, for example in an RTC's backup
+/// registers.
+pub trait StateStore {
+    /// Loads the stored state
+    ///
+    /// Returns `None`, if no valid state is stored.
+    fn load(&mut self) -> Option<RetryState>;
+
+    /// Stores the state, or clears the storage, if `None` is passed
+    fn store(&mut self, state: Option<RetryState>);
+}
+
+impl<S> StateStore for &mut S
+    where S: StateStore + ?Sized
+{
+    fn load(&mut self) -> Option<RetryState> {
+        (**self).load()
+    }
+
+    fn store(&mut self, state: Option<RetryState>) {
+        (**self).store(state)
+    }
+}
+
+impl StateStore for [u8; RetryState::SIZE] {
+    fn load(&mut self) -> Option<RetryState> {
+        RetryState::from_bytes(self)
+    }
+
+    fn store(&mut self, state: Option<RetryState>) {
+        *self = match state {
+            Some(state) => state.to_bytes(),
+            None        => [0; RetryState::SIZE],
+        };
+    }
+}
+
+
+/// Recovers from timeouts, by resetting things and restarting the operation
+///
+/// When an operation times out, `Supervisor` calls a recovery routine, then
+/// restarts the operation. This is repeated, until the operation succeeds or
+/// fails, or the maximum number of restarts is reached.
+///
+/// The recovery routine receives the timer and the number of the restart,
+/// starting at 1. It is responsible for resetting whatever needs resetting
+/// (for example a peripheral or a bus), and for restarting the timer.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     retry::Supervisor,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(bool);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) { self.0 = false; }
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         if self.0 { return Ok(()); }
+/// #         self.0 = true;
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer(false);
+/// # let mut bus_is_hung = true;
+/// use embedded_hal::timer::CountDown;
+///
+/// let mut resets = 0;
+/// let mut supervisor = Supervisor::new(3, |timer: &mut Timer, _restart| {
+///     // Reset the bus here, then restart the timer.
+///     resets += 1;
+///     timer.start(());
+/// });
+///
+/// let result: Result<(), TimeoutError<()>> = supervisor.run(&mut timer, || {
+///     if bus_is_hung {
+///         bus_is_hung = false;
+///         return Err(nb::Error::WouldBlock);
+///     }
+///     Ok(())
+/// });
+/// # drop(supervisor);
+///
+/// assert!(result.is_ok());
+/// assert_eq!(resets, 1);
+/// ```
+pub struct Supervisor<R> {
+    max_restarts: u32,
+    recover:      R,
+}
+
+impl<R> Supervisor<R> {
+    /// Creates a new `Supervisor`
+    ///
+    /// The operation is restarted up to `max_restarts` times, with `recover`
+    /// being called before each restart.
+    pub fn new(max_restarts: u32, recover: R) -> Self {
+        Supervisor {
+            max_restarts,
+            recover,
+        }
+    }
+
+    /// Runs the operation, restarting it if it times out
+    ///
+    /// Returns `TimeoutError::Timeout`, if the operation still times out after
+    /// the last restart. Errors returned by the operation are passed on
+    /// immediately, without restarting the operation.
+    pub fn run<T, Op>(&mut self, timer: &mut T, op: Op)
+        -> Result<Op::Output, TimeoutError<Op::Error>>
+        where
+            T:  TimeoutTimer,
+            R:  FnMut(&mut T, u32),
+            Op: IntoTimeoutOp,
+    {
+        let mut op = op.into_timeout_op();
+        let mut restart = 0;
+
+        loop {
+            match block_timeout!(&mut *timer, op.poll()) {
+                Err(TimeoutError::Timeout) if restart < self.max_restarts => {
+                    restart += 1;
+                    (self.recover)(timer, restart);
+                }
+                result =>
+                    return result,
+            }
+        }
+    }
+}
+
+
+/// Waits for a sensor to warm up, then polls it until it is ready
+///
+/// Many sensors (gas sensors, for example) need a minimum warm-up time after
+/// power-on, before it even makes sense to ask them whether they're ready. This
+/// function first waits until `warm_up` has expired, then polls `ready` until
+/// it returns something other than `WouldBlock`. Between polls, it waits for
+/// the delay returned by `backoff`. While waiting for the warm-up, it idles
+/// using [`TimeoutTimer::idle`] of `warm_up`.
+///
+/// `deadline` limits the whole process. Both timers must already be running.
+/// If the deadline passes during the warm-up, [`ReadyError::NotWarm`] is
+/// returned. If it passes after that, [`ReadyError::NotReady`] is returned.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::retry::{
+///     await_ready,
+///     Constant,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         if self.0 == 0 { return Ok(()) }
+/// #         self.0 -= 1;
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # struct Delay;
+/// #
+/// # impl embedded_hal::blocking::delay::DelayUs<u32> for Delay {
+/// #     fn delay_us(&mut self, _: u32) {}
+/// # }
+/// #
+/// # struct Sensor { polls: u32 }
+/// #
+/// # impl Sensor {
+/// #     fn read_if_ready(&mut self) -> nb::Result<u16, ()> {
+/// #         self.polls += 1;
+/// #         if self.polls < 3 { return Err(nb::Error::WouldBlock) }
+/// #         Ok(412)
+/// #     }
+/// # }
+/// #
+/// # let mut warm_up = Timer(5);
+/// # let mut deadline = Timer(100);
+/// # let mut delay = Delay;
+/// # let mut sensor = Sensor { polls: 0 };
+///
+/// let result = await_ready(
+///     &mut warm_up,
+///     &mut deadline,
+///     &mut delay,
+///     &mut Constant(10_000),
+///     || sensor.read_if_ready(),
+/// );
+///
+/// assert_eq!(result, Ok(412));
+/// ```
+pub fn await_ready<W, T, D, B, Op>(
+    warm_up:   &mut W,
+    deadline:  &mut T,
+    delay:     &mut D,
+    backoff:   &mut B,
+    ready:     Op,
+)
+    -> Result<Op::Output, ReadyError<Op::Error>>
+    where
+        W:  TimeoutTimer,
+        T:  TimeoutTimer,
+        D:  Delay,
+        B:  Backoff,
+        Op: IntoTimeoutOp,
+{
+    let mut ready = ready.into_timeout_op();
+    let mut bound = PollBound::new();
+
+    while !warm_up.is_expired() {
+        if is_expired(deadline) || bound.exhausted() {
+            bound.expired();
+            return Err(ReadyError::NotWarm);
+        }
+
+        warm_up.idle();
+    }
+
+    backoff.reset();
+    let mut attempt = 0;
+
+    loop {
+        match ready.poll() {
+            Ok(value) =>
+                return Ok(value),
+            Err(nb::Error::Other(error)) =>
+                return Err(ReadyError::Other(error)),
+            Err(nb::Error::WouldBlock) =>
+                (),
+        }
+
+        if is_expired(deadline) || bound.exhausted() {
+            bound.expired();
+            return Err(ReadyError::NotReady);
+        }
+
+        attempt += 1;
+        let delay_us = backoff.next_delay(attempt);
+        if delay_us > 0 {
+            Delay::delay_us(delay, delay_us);
+        }
+    }
+}
+
+
+/// Retries an operation until it succeeds, gives up, or the timer expires
+///
+/// A lighter alternative to [`Retrier`], without an observer, that takes all
+/// its parts by reference. `op` is polled until it returns `Ok` or an error.
+/// If it fails, `classify` decides whether the error is worth retrying, and
+/// `backoff` how long to wait before the next attempt. The number of attempts
+/// can be limited using `max_attempts`.
+///
+/// The timer limits the total duration, and must already be running. It is
+/// only checked between polls, not while waiting for the backoff delay.
+///
+/// This is the blocking counterpart of `asynch::retry_with_backoff`, which
+/// makes the same decisions.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::retry::{
+///     retry_with_backoff,
+///     Exponential,
+///     RetryAll,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+///
+/// struct Delay(u32);
+///
+/// impl embedded_timeout_macros::Delay for Delay {
+///     fn delay_us(&mut self, us: u32) {
+///         self.0 += us;
+///     }
+/// }
+///
+/// let mut delay = Delay(0);
+/// let mut attempts = 0;
+///
+/// let result = retry_with_backoff(
+///     &mut Timer,
+///     &mut delay,
+///     &mut Exponential::new(100, 10_000),
+///     &mut RetryAll,
+///     None,
+///     || {
+///         attempts += 1;
+///         if attempts < 3 {
+///             Err(nb::Error::Other("NAK"))
+///         }
+///         else {
+///             Ok(attempts)
+///         }
+///     },
+/// );
+///
+/// assert_eq!(result, Ok(3));
+/// assert_eq!(delay.0, 300);
+/// ```
+pub fn retry_with_backoff<T, D, B, C, Op>(
+    timer:        &mut T,
+    delay:        &mut D,
+    backoff:      &mut B,
+    classify:     &mut C,
+    max_attempts: Option<u32>,
+    op:           Op,
+)
+    -> Result<Op::Output, RetryError<Op::Error>>
+    where
+        T:  TimeoutTimer,
+        D:  Delay,
+        B:  Backoff,
+        C:  Classify<Op::Error>,
+        Op: IntoTimeoutOp,
+{
+    let mut op = op.into_timeout_op();
+
+    retry_with_backoff_body!(
+        backoff,
+        classify,
+        max_attempts,
+        attempt block_timeout!(timer, op.poll()),
+        delay (delay_us) {
+            Delay::delay_us(delay, delay_us);
+            Ok::<(), TimeoutError>(())
+        }
+    )
+}
+
+
+/// Initializes an SD or MMC card, retrying until it is ready
+///
+/// Drives the usual initialization sequence: Reset the card using CMD0, then
+/// repeat ACMD41 (or CMD1, for MMC) until the card is no longer busy. If
+/// either command fails, the sequence starts over with another reset, as long
+/// as `classify` considers the error worth retrying, and `max_attempts` (which
+/// counts resets) hasn't been reached.
+///
+/// All pauses, between resets as well as between two polls of a busy card,
+/// follow the schedule determined by `backoff`. `deadline` limits the whole
+/// process, which the specifications limit to one second. It must already be
+/// running.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::retry::{
+///     init_card,
+///     CardInit,
+///     Constant,
+///     RetryAll,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # struct Delay;
+/// #
+/// # impl embedded_timeout_macros::Delay for Delay {
+/// #     fn delay_us(&mut self, _: u32) {}
+/// # }
+///
+/// /// A card that doesn't answer the first CMD0, then is busy for a while
+/// struct Card {
+///     resets: u32,
+///     polls:  u32,
+/// }
+///
+/// impl CardInit for Card {
+///     type Output = ();
+///     type Error  = &'static str;
+///
+///     fn reset(&mut self) -> Result<(), Self::Error> {
+///         self.resets += 1;
+///         if self.resets < 2 { Err("no response") } else { Ok(()) }
+///     }
+///
+///     fn activate(&mut self) -> nb::Result<Self::Output, Self::Error> {
+///         self.polls += 1;
+///         if self.polls < 5 { Err(nb::Error::WouldBlock) } else { Ok(()) }
+///     }
+/// }
+///
+/// let mut card = Card { resets: 0, polls: 0 };
+///
+/// let result = init_card(
+///     &mut Timer,
+///     &mut Delay,
+///     &mut Constant(10_000),
+///     &mut RetryAll,
+///     Some(3),
+///     &mut card,
+/// );
+///
+/// assert_eq!(result, Ok(()));
+/// assert_eq!(card.resets, 2);
+/// ```
+pub fn init_card<T, D, B, C, I>(
+    deadline:     &mut T,
+    delay:        &mut D,
+    backoff:      &mut B,
+    classify:     &mut C,
+    max_attempts: Option<u32>,
+    card:         &mut I,
+)
+    -> Result<I::Output, RetryError<I::Error>>
+    where
+        T: TimeoutTimer,
+        D: Delay,
+        B: Backoff,
+        C: Classify<I::Error>,
+        I: CardInit,
+{
+    backoff.reset();
+
+    let mut attempt = 0;
+    let mut pause = 0;
+    let mut bound = PollBound::new();
+
+    loop {
+        attempt += 1;
+
+        let error = match card.reset() {
+            Ok(()) => loop {
+                match card.activate() {
+                    Ok(output) =>
+                        return Ok(output),
+                    Err(nb::Error::Other(error)) =>
+                        break error,
+                    Err(nb::Error::WouldBlock) =>
+                        (),
+                }
+
+                if deadline.is_expired() || bound.exhausted() {
+                    return Err(RetryError::Timeout);
+                }
+
+                pause += 1;
+                Delay::delay_us(delay, backoff.next_delay(pause));
+            },
+            Err(error) =>
+                error,
+        };
+
+        if classify.classify(&error) == Verdict::Fail {
+            return Err(RetryError::Fatal(error));
+        }
+        if let Some(max_attempts) = max_attempts {
+            if attempt >= max_attempts {
+                return Err(RetryError::Exhausted(error));
+            }
+        }
+        if deadline.is_expired() || bound.exhausted() {
+            return Err(RetryError::Timeout);
+        }
+
+        pause += 1;
+        Delay::delay_us(delay, backoff.next_delay(pause));
+    }
+}
+
+/// The card-specific steps of [`init_card`]
+///
+/// Implemented by SD/MMC drivers, or by the application, on top of the
+/// command layer of a driver.
+pub trait CardInit {
+    /// The result of a successful initialization, like the type of the card
+    type Output;
+
+    /// The error that sending a command can result in
+    type Error;
+
+    /// Resets the card using CMD0
+    ///
+    /// Should return an error, if the card doesn't enter the idle state. For
+    /// SD cards, this is also the place to send CMD8.
+    fn reset(&mut self) -> Result<(), Self::Error>;
+
+    /// Sends ACMD41 (or CMD1 for MMC) once
+    ///
+    /// Returns `WouldBlock`, while the card reports that it is still busy.
+    fn activate(&mut self) -> nb::Result<Self::Output, Self::Error>;
+}
+
+
+/// Failed operations, parked until they are due to be retried
+///
+/// Network and radio stacks can't block the main loop, while waiting to retry
+/// a packet that wasn't acknowledged. Instead, they park the operation in a
+/// `RetryQueue`, along with the time after which to retry it, and how long it
+/// stays relevant. The main loop calls [`RetryQueue::service`] on every pass,
+/// which retries the operations that are due, and reports those that are done
+/// with.
+///
+/// When a retry fails, `classify` decides whether the error is worth retrying,
+/// and `backoff` how long to wait before the next attempt. The delays that the
+/// backoff strategy returns are in microseconds, and converted into ticks of
+/// the clock, using its frequency.
+///
+/// Holds up to `N` operations, all of the same type. Any [`TimedOperation`]
+/// can be used, including closures that return `nb::Result`.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::retry::{
+///     Constant,
+///     Event,
+///     RetryAll,
+///     RetryQueue,
+/// };
+/// #
+/// # #[derive(Clone, Copy)]
+/// # struct Clock<'r>(&'r core::cell::Cell<u32>);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock<'_> {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0.get() }
+/// # }
+/// #
+/// # let time = core::cell::Cell::new(0);
+/// # let clock = Clock(&time);
+///
+/// /// Sends a packet, which fails twice, before it is acknowledged
+/// struct Send {
+///     seq:      u8,
+///     failures: u32,
+/// }
+///
+/// impl embedded_timeout_macros::TimedOperation for Send {
+///     type Output = ();
+///     type Error  = &'static str;
+///
+///     fn poll(&mut self) -> nb::Result<(), &'static str> {
+///         if self.failures < 2 {
+///             self.failures += 1;
+///             return Err(nb::Error::Other("no ACK"));
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// // A 1 kHz clock, and 10 ms between retries
+/// let mut queue = RetryQueue::<_, _, _, Send, 8>::new(
+///     clock,
+///     1000,
+///     Constant(10_000),
+///     RetryAll,
+/// );
+///
+/// // The first attempt failed, outside of the queue.
+/// queue.park(Send { seq: 1, failures: 1 }, 10, 1000).ok().unwrap();
+///
+/// let mut acked = None;
+/// while !queue.is_empty() {
+///     time.set(time.get() + 1);
+///
+///     queue.service(|event| {
+///         if let Event::Done(send, ()) = event {
+///             acked = Some((send.seq, time.get()));
+///         }
+///     });
+/// }
+///
+/// assert_eq!(acked, Some((1, 20)));
+/// ```
+pub struct RetryQueue<C: Clock, B, K, Op, const N: usize> {
+    clock:     C,
+    frequency: u32,
+    backoff:   B,
+    classify:  K,
+    items:     [Option<Parked<C::Ticks, Op>>; N],
+}
+
+impl<C, B, K, Op, const N: usize> RetryQueue<C, B, K, Op, N>
+    where
+        C:  Clock,
+        B:  Backoff,
+        K:  Classify<Op::Error>,
+        Op: TimedOperation,
+{
+    /// Creates an empty queue
+    ///
+    /// `frequency` is the frequency of the clock, in Hz.
+    pub fn new(clock: C, frequency: u32, backoff: B, classify: K) -> Self {
+        RetryQueue {
+            clock,
+            frequency,
+            backoff,
+            classify,
+            items: core::array::from_fn(|_| None),
+        }
+    }
+
+    /// Parks an operation that has failed once
+    ///
+    /// The operation is retried once `retry_after` ticks have passed, and
+    /// given up on once `lifetime` ticks have passed, both counted from now.
+    /// Returns the operation, if the queue is full.
+    pub fn park(&mut self, op: Op, retry_after: C::Ticks, lifetime: C::Ticks)
+        -> Result<(), Full<Op>>
+    {
+        let i = match self.items.iter().position(|item| item.is_none()) {
+            Some(i) => i,
+            None    => return Err(Full(op)),
+        };
+
+        let now = self.clock.now();
+
+        self.items[i] = Some(Parked {
+            op,
+            attempt: 1,
+            created: now,
+            lifetime,
+            since:   now,
+            delay:   retry_after,
+        });
+
+        Ok(())
+    }
+
+    /// Retries the operations that are due, and reports those done with
+    ///
+    /// Every operation that is due is polled once. If it returns `WouldBlock`,
+    /// it stays due, and is polled again on the next call. Calls `on_event`
+    /// for every operation that has succeeded, failed with an error that isn't
+    /// worth retrying, or outlived its lifetime, and removes it.
+    pub fn service<F>(&mut self, mut on_event: F)
+        where F: FnMut(Event<Op>)
+    {
+        for slot in self.items.iter_mut() {
+            let item = match slot {
+                Some(item) => item,
+                None       => continue,
+            };
+
+            let now = self.clock.now();
+
+            if now.wrapping_sub(item.created) >= item.lifetime {
+                if let Some(item) = slot.take() {
+                    on_event(Event::Expired(item.op));
+                }
+                continue;
+            }
+            if now.wrapping_sub(item.since) < item.delay {
+                continue;
+            }
+
+            let error = match item.op.poll() {
+                Ok(output) => {
+                    if let Some(item) = slot.take() {
+                        on_event(Event::Done(item.op, output));
+                    }
+                    continue;
+                }
+                Err(nb::Error::WouldBlock) =>
+                    continue,
+                Err(nb::Error::Other(error)) =>
+                    error,
+            };
+
+            if self.classify.classify(&error) == Verdict::Fail {
+                if let Some(item) = slot.take() {
+                    on_event(Event::Failed(item.op, error));
+                }
+                continue;
+            }
+
+            item.attempt = item.attempt.saturating_add(1);
+
+            let delay_us = self.backoff.next_delay(item.attempt);
+            let delay = ticks_from_duration(
+                Duration::from_micros(delay_us as u64),
+                self.frequency,
+            );
+
+            item.since = now;
+            item.delay = delay
+                .map_or(C::Ticks::MAX, C::Ticks::saturating_from_u64);
+        }
+    }
+
+    /// Returns the number of parked operations
+    pub fn len(&self) -> usize {
+        self.items.iter()
+            .filter(|item| item.is_some())
+            .count()
+    }
+
+    /// Indicates whether the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of ticks until the next operation is due
+    ///
+    /// Returns zero, if an operation is already due, and `None`, if the queue
+    /// is empty. Low-power applications can use this to decide how long to
+    /// sleep.
+    pub fn ticks_until_due(&mut self) -> Option<C::Ticks> {
+        let now = self.clock.now();
+
+        self.items.iter()
+            .flatten()
+            .map(|item| item.delay.saturating_sub(now.wrapping_sub(item.since)))
+            .min()
+    }
+
+    /// Provides access to the clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+
+/// Reported by [`RetryQueue::service`], when it is done with an operation
+#[derive(Debug)]
+pub enum Event<Op: TimedOperation> {
+    /// The operation succeeded
+    Done(Op, Op::Output),
+
+    /// The operation failed, with an error that isn't worth retrying
+    Failed(Op, Op::Error),
+
+    /// The operation was parked for longer than its lifetime
+    Expired(Op),
+}
+
+
+/// Returned by [`RetryQueue::park`], if there's no room for the operation
+///
+/// Carries the operation that couldn't be parked.
+#[derive(Debug)]
+pub struct Full<Op>(pub Op);
+
+
+struct Parked<T, Op> {
+    op:       Op,
+    attempt:  u32,
+    created:  T,
+    lifetime: T,
+    since:    T,
+    delay:    T,
+}
+
+
+/// Decides how long to wait between attempts
+pub trait Backoff {
+    /// Returns the delay after the given attempt failed, in microseconds
+    ///
+    /// Attempts are counted starting at 1.
+    fn next_delay(&mut self, attempt: u32) -> u32;
+
+    /// Resets the strategy to its initial state
+    ///
+    /// Called at the beginning of each run. The default implementation does
+    /// nothing.
+    fn reset(&mut self) {}
+}
+
+#[cfg(feature = "alloc")]
+impl<B> Backoff for Box<B>
+    where B: Backoff + ?Sized
+{
+    fn next_delay(&mut self, attempt: u32) -> u32 {
+        (**self).next_delay(attempt)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
+/// Doesn't wait between attempts
+#[derive(Clone, Copy, Debug)]
+pub struct NoBackoff;
+
+impl Backoff for NoBackoff {
+    fn next_delay(&mut self, _: u32) -> u32 {
+        0
+    }
+}
+
+/// Waits for the same number of microseconds between all attempts
+#[derive(Clone, Copy, Debug)]
+pub struct Constant(pub u32);
+
+impl Backoff for Constant {
+    fn next_delay(&mut self, _: u32) -> u32 {
+        self.0
+    }
+}
+
+/// Doubles the delay after every attempt, up to a maximum
+#[derive(Clone, Copy, Debug)]
+pub struct Exponential {
+    initial: u32,
+    max:     u32,
+}
+
+impl Exponential {
+    /// Creates an exponential backoff strategy
+    ///
+    /// `initial` is the delay after the first attempt, `max` is the delay that
+    /// will never be exceeded. Both are in microseconds. An attempt number of
+    /// zero is treated like the first attempt.
+    pub fn new(initial: u32, max: u32) -> Self {
+        Exponential {
+            initial,
+            max,
+        }
+    }
+}
+
+impl Backoff for Exponential {
+    fn next_delay(&mut self, attempt: u32) -> u32 {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+
+/// Decides whether an error is worth retrying
+///
+/// Implemented for all closures that take a reference to the error and return
+/// a [`Verdict`].
+pub trait Classify<E> {
+    /// Classifies the error
+    fn classify(&mut self, error: &E) -> Verdict;
+}
+
+impl<E, F> Classify<E> for F
+    where F: FnMut(&E) -> Verdict
+{
+    fn classify(&mut self, error: &E) -> Verdict {
+        self(error)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<E> Classify<E> for Box<dyn Classify<E>> {
+    fn classify(&mut self, error: &E) -> Verdict {
+        (**self).classify(error)
+    }
+}
+
+/// Retries all errors
+#[derive(Clone, Copy, Debug)]
+pub struct RetryAll;
+
+impl<E> Classify<E> for RetryAll {
+    fn classify(&mut self, _: &E) -> Verdict {
+        Verdict::Retry
+    }
+}
+
+/// The result of classifying an error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    /// The error is transient, and the operation should be retried
+    Retry,
+
+    /// The error is permanent, and retrying would be pointless
+    Fail,
+}
+
+
+/// Is notified about what a [`Retrier`] is doing
+///
+/// All methods have default implementations that do nothing, so you only need
+/// to implement the ones you're interested in. All methods receive the label of
+/// the `Retrier`, and the number of the current attempt, starting at 1.
+pub trait Observer<E> {
+    /// Called when an attempt starts
+    fn on_attempt(&mut self, _label: &'static str, _attempt: u32) {}
+
+    /// Called when an attempt ends with an error
+    fn on_error(&mut self, _label: &'static str, _attempt: u32, _error: &E) {}
+
+    /// Called when the operation succeeds
+    fn on_success(&mut self, _label: &'static str, _attempt: u32) {}
+
+    /// Called when the timer times out
+    fn on_timeout(&mut self, _label: &'static str, _attempt: u32) {}
+}
+
+#[cfg(feature = "alloc")]
+impl<E, O> Observer<E> for Box<O>
+    where O: Observer<E> + ?Sized
+{
+    fn on_attempt(&mut self, label: &'static str, attempt: u32) {
+        (**self).on_attempt(label, attempt)
+    }
+
+    fn on_error(&mut self, label: &'static str, attempt: u32, error: &E) {
+        (**self).on_error(label, attempt, error)
+    }
+
+    fn on_success(&mut self, label: &'static str, attempt: u32) {
+        (**self).on_success(label, attempt)
+    }
+
+    fn on_timeout(&mut self, label: &'static str, attempt: u32) {
+        (**self).on_timeout(label, attempt)
+    }
+}
+
+/// An observer that ignores everything
+#[derive(Clone, Copy, Debug)]
+pub struct NoObserver;
+
+impl<E> Observer<E> for NoObserver {}
+
+
+/// An error returned by [`Retrier::run`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RetryError<E = Infallible> {
+    /// The timer timed out
+    Timeout,
+
+    /// The maximum number of attempts was reached
+    ///
+    /// Carries the error returned by the last attempt.
+    Exhausted(E),
+
+    /// The operation returned an error that isn't worth retrying
+    Fatal(E),
+}
+
+impl<E> RetryError<E> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            RetryError::Timeout      => codes::RETRY_TIMEOUT,
+            RetryError::Exhausted(_) => codes::RETRY_EXHAUSTED,
+            RetryError::Fatal(_)     => codes::RETRY_FATAL,
+        }
+    }
+}
+
+
+/// An error returned by [`await_ready`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReadyError<E = Infallible> {
+    /// The deadline passed before the warm-up time was over
+    NotWarm,
+
+    /// The deadline passed after the warm-up, before the operation was ready
+    NotReady,
+
+    /// The operation returned an error
+    Other(E),
+}
+
+impl<E> ReadyError<E> {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            ReadyError::NotWarm  => codes::READY_NOT_WARM,
+            ReadyError::NotReady => codes::READY_NOT_READY,
+            ReadyError::Other(_) => codes::READY_OTHER,
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Combining the values that an operation produces before a timeout
+//!
+//! Noisy sources, like ADCs or sensors, are often sampled for as long as a
+//! timeout allows, but a single sample doesn't mean much. [`sample_timeout!`]
+//! keeps polling such a source until its timer expires, and feeds every value
+//! into a [`Reducer`], which combines them into a single result.
+//!
+//! This module provides reducers for the common cases, [`Min`], [`Max`],
+//! [`Median`], and [`Last`], as well as [`Fold`], which combines the values
+//! using a closure. For anything else, implement [`Reducer`].
+//!
+//! This module is only available, if the `sample` feature is enabled.
+//!
+//! # Example
+//!
+//! ``` rust
+//! use embedded_timeout_macros::{
+//!     sample::Median,
+//!     sample_timeout,
+//! };
+//! #
+//! # struct Timer(u32);
+//! #
+//! # impl embedded_timeout_macros::TimeoutTimer for Timer {
+//! #     fn is_expired(&mut self) -> bool {
+//! #         self.0 = self.0.saturating_sub(1);
+//! #         self.0 == 0
+//! #     }
+//! # }
+//! #
+//! # let mut timer = Timer(6);
+//!
+//! let mut readings = [512u16, 498, 4095, 505, 0].iter().copied();
+//! let mut read_adc = || readings.next().ok_or(nb::Error::<()>::WouldBlock);
+//!
+//! // The outliers don't affect the result.
+//! let level = sample_timeout!(&mut timer, read_adc(), Median::<_, 8>::new());
+//!
+//! assert_eq!(level, Ok(505));
+//! ```
+//!
+//! [`sample_timeout!`]: crate::sample_timeout
+
+
+/// Combines a sequence of values into a single result
+///
+/// Used by [`sample_timeout!`], which pushes every value the operation
+/// produces, then finishes the reducer once the timer has expired.
+///
+/// [`sample_timeout!`]: crate::sample_timeout
+pub trait Reducer<T> {
+    /// The combined result
+    type Output;
+
+    /// Adds a value
+    fn push(&mut self, value: T);
+
+    /// Returns the combined result
+    ///
+    /// Returns `None`, if no values have been pushed.
+    fn finish(self) -> Option<Self::Output>;
+}
+
+
+/// Keeps the smallest value
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Min<T>(Option<T>);
+
+impl<T> Min<T> {
+    /// Creates the reducer
+    pub const fn new() -> Self {
+        Min(None)
+    }
+}
+
+impl<T> Default for Min<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Reducer<T> for Min<T>
+    where T: Ord
+{
+    type Output = T;
+
+    fn push(&mut self, value: T) {
+        self.0 = match self.0.take() {
+            Some(min) => Some(min.min(value)),
+            None      => Some(value),
+        };
+    }
+
+    fn finish(self) -> Option<T> {
+        self.0
+    }
+}
+
+
+/// Keeps the largest value
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Max<T>(Option<T>);
+
+impl<T> Max<T> {
+    /// Creates the reducer
+    pub const fn new() -> Self {
+        Max(None)
+    }
+}
+
+impl<T> Default for Max<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Reducer<T> for Max<T>
+    where T: Ord
+{
+    type Output = T;
+
+    fn push(&mut self, value: T) {
+        self.0 = match self.0.take() {
+            Some(max) => Some(max.max(value)),
+            None      => Some(value),
+        };
+    }
+
+    fn finish(self) -> Option<T> {
+        self.0
+    }
+}
+
+
+/// Keeps the most recent value
+///
+/// This is what waiting for a single value amounts to, and is mostly useful
+/// where a reducer is expected, but no aggregation is wanted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Last<T>(Option<T>);
+
+impl<T> Last<T> {
+    /// Creates the reducer
+    pub const fn new() -> Self {
+        Last(None)
+    }
+}
+
+impl<T> Default for Last<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Reducer<T> for Last<T> {
+    type Output = T;
+
+    fn push(&mut self, value: T) {
+        self.0 = Some(value);
+    }
+
+    fn finish(self) -> Option<T> {
+        self.0
+    }
+}
+
+
+/// Computes the median of the most recent `N` values
+///
+/// Older values are dropped, once `N` values have been pushed, so the result
+/// reflects the end of the wait. For an even number of values, the lower of
+/// the two middle values is returned.
+#[derive(Clone, Copy, Debug)]
+pub struct Median<T, const N: usize> {
+    window: [Option<T>; N],
+    next:   usize,
+}
+
+impl<T, const N: usize> Median<T, N>
+    where T: Copy
+{
+    /// Creates the reducer
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `N` is zero.
+    pub fn new() -> Self {
+        assert!(N > 0, "The window must hold at least one value");
+
+        Median {
+            window: [None; N],
+            next:   0,
+        }
+    }
+}
+
+impl<T, const N: usize> Default for Median<T, N>
+    where T: Copy
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Reducer<T> for Median<T, N>
+    where T: Copy + Ord
+{
+    type Output = T;
+
+    fn push(&mut self, value: T) {
+        self.window[self.next] = Some(value);
+        self.next = (self.next + 1) % N;
+    }
+
+    fn finish(mut self) -> Option<T> {
+        // `None` sorts before all values, so the values end up at the end.
+        self.window.sort_unstable();
+
+        let empty = self.window.iter()
+            .take_while(|value| value.is_none())
+            .count();
+        let len = N - empty;
+        if len == 0 {
+            return None;
+        }
+
+        self.window[empty + (len - 1) / 2]
+    }
+}
+
+
+/// Combines the values using a closure
+///
+/// Starts with an initial value, and passes it to the closure along with each
+/// value, replacing it with whatever the closure returns.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::sample::{
+///     Fold,
+///     Reducer,
+/// };
+///
+/// // Sum and count, for computing the mean
+/// let mut mean = Fold::new((0u32, 0u32), |(sum, n), value: u16| {
+///     (sum + value as u32, n + 1)
+/// });
+///
+/// for &value in &[10, 20, 60] {
+///     mean.push(value);
+/// }
+///
+/// let (sum, n) = mean.finish().unwrap();
+/// assert_eq!(sum / n, 30);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Fold<A, F> {
+    acc:    Option<A>,
+    pushed: bool,
+    f:      F,
+}
+
+impl<A, F> Fold<A, F> {
+    /// Creates the reducer from an initial value and a closure
+    pub fn new(init: A, f: F) -> Self {
+        Fold {
+            acc:    Some(init),
+            pushed: false,
+            f,
+        }
+    }
+}
+
+impl<T, A, F> Reducer<T> for Fold<A, F>
+    where F: FnMut(A, T) -> A
+{
+    type Output = A;
+
+    fn push(&mut self, value: T) {
+        // The accumulator is only taken out for the duration of the call.
+        if let Some(acc) = self.acc.take() {
+            self.acc = Some((self.f)(acc, value));
+        }
+        self.pushed = true;
+    }
+
+    fn finish(self) -> Option<A> {
+        if self.pushed {
+            self.acc
+        }
+        else {
+            None
+        }
+    }
+}
@@ -0,0 +1,412 @@
+//! Waiting for an interrupt handler to signal completion
+//!
+//! Interrupt-driven drivers often need to wait for an interrupt handler to
+//! report that something has happened, maybe along with a result. Getting the
+//! memory orderings of that right is subtle, so [`Signal`] takes care of them.
+//!
+//! [`EventSource`] abstracts over event-like primitives, like `Signal`, RTOS
+//! semaphores or event groups, so they can be waited for using [`wait`] and
+//! [`wait_count`].
+//!
+//! This module is only available, if the `signal` feature is enabled. It
+//! requires atomic loads and stores, which some targets, like MSP430, don't
+//! support.
+
+
+use core::{
+    cell::UnsafeCell,
+    convert::Infallible,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use crate::{
+    block_timeout,
+    TimeoutError,
+    TimeoutTimer,
+};
+
+
+/// An event-like primitive that can be waited for
+///
+/// This can be implemented for RTOS semaphores, event groups, or similar
+/// primitives, to plug them into the timeout helpers of this module.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::{
+///     cell::Cell,
+///     convert::Infallible,
+/// };
+///
+/// use embedded_timeout_macros::{
+///     signal::{
+///         wait,
+///         EventSource,
+///     },
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer(10);
+///
+/// /// A counting semaphore
+/// struct Semaphore(Cell<u8>);
+///
+/// impl EventSource for &'_ Semaphore {
+///     type Event = ();
+///     type Error = Infallible;
+///
+///     fn try_take(&mut self) -> nb::Result<(), Infallible> {
+///         match self.0.get() {
+///             0     => Err(nb::Error::WouldBlock),
+///             count => Ok(self.0.set(count - 1)),
+///         }
+///     }
+/// }
+///
+/// let semaphore = Semaphore(Cell::new(1));
+///
+/// assert_eq!(wait(&semaphore, &mut timer), Ok(()));
+/// assert_eq!(wait(&semaphore, &mut timer), Err(TimeoutError::Timeout));
+/// ```
+pub trait EventSource {
+    /// The value that is taken, when an event occurs
+    type Event;
+
+    /// The error that can occur while taking an event
+    type Error;
+
+    /// Takes an event, if one has occured
+    ///
+    /// Returns `WouldBlock`, if no event is available right now.
+    fn try_take(&mut self) -> nb::Result<Self::Event, Self::Error>;
+}
+
+impl<S> EventSource for &'_ mut S
+    where S: EventSource + ?Sized
+{
+    type Event = S::Event;
+    type Error = S::Error;
+
+    fn try_take(&mut self) -> nb::Result<Self::Event, Self::Error> {
+        S::try_take(self)
+    }
+}
+
+
+/// Waits for an event, until the timer expires
+pub fn wait<S, T>(mut source: S, timer: &mut T)
+    -> Result<S::Event, TimeoutError<S::Error>>
+    where
+        S: EventSource,
+        T: TimeoutTimer + ?Sized,
+{
+    block_timeout!(timer, source.try_take())
+}
+
+/// Waits until `count` events have been taken, or the timer expires
+///
+/// The timer covers all events, not each one individually. `taken` is
+/// incremented for every event that is taken, and waiting stops once it
+/// reaches `count`. If the timer expires, `taken` tells how many events have
+/// been taken so far, so they can be given back to a counting primitive, or
+/// the wait can be resumed by calling this function again with a restarted
+/// timer.
+///
+/// The values of the events are dropped. Use [`wait`] to get them.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::{
+///     cell::Cell,
+///     convert::Infallible,
+/// };
+///
+/// use embedded_timeout_macros::{
+///     signal::{
+///         wait_count,
+///         EventSource,
+///     },
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer(10);
+/// #
+/// # struct Semaphore(Cell<u8>);
+/// #
+/// # impl EventSource for &'_ Semaphore {
+/// #     type Event = ();
+/// #     type Error = Infallible;
+/// #     fn try_take(&mut self) -> nb::Result<(), Infallible> {
+/// #         match self.0.get() {
+/// #             0     => Err(nb::Error::WouldBlock),
+/// #             count => Ok(self.0.set(count - 1)),
+/// #         }
+/// #     }
+/// # }
+///
+/// let semaphore = Semaphore(Cell::new(2));
+///
+/// let mut taken = 0;
+/// let result = wait_count(&semaphore, &mut timer, &mut taken, 3);
+///
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// assert_eq!(taken, 2);
+/// ```
+pub fn wait_count<S, T>(
+    mut source: S,
+    timer:      &mut T,
+    taken:      &mut u32,
+    count:      u32,
+)
+    -> Result<(), TimeoutError<S::Error>>
+    where
+        S: EventSource,
+        T: TimeoutTimer + ?Sized,
+{
+    while *taken < count {
+        wait(&mut source, timer)?;
+        *taken += 1;
+    }
+
+    Ok(())
+}
+
+
+/// A value that is passed from an interrupt handler to the main code
+///
+/// A `Signal` is split into a [`Producer`], which is usually moved into an
+/// interrupt handler, and a [`Consumer`], which stays with the main code. Since
+/// [`Signal::split`] requires mutable access, there can only ever be one of
+/// each, which is what makes it safe to share the value between them. Only
+/// atomic loads and stores are used, so this works on targets that don't
+/// support atomic read-modify-write operations, like Cortex-M0.
+///
+/// The value is written before the ready flag is set with `Release` ordering,
+/// and read after the flag has been observed with `Acquire` ordering, so the
+/// consumer always sees the complete value. A new value can only be signaled
+/// after the previous one has been taken.
+///
+/// To wait in a low-power mode, wrap the timer in a [`WithStrategy`] with
+/// [`WaitForInterrupt`]. Both the interrupt that signals and the timer must
+/// then be able to wake up the CPU.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     signal::Signal,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// // In a real application, this would be a `&'static mut Signal`, for
+/// // example from `cortex_m::singleton!`, and the producer would be moved
+/// // into the interrupt handler.
+/// let mut signal = Signal::new();
+/// let (mut producer, mut consumer) = signal.split();
+///
+/// // In the ADC interrupt handler
+/// producer.signal(0x123u16).ok();
+///
+/// // In the main code
+/// let result = consumer.wait(&mut timer);
+/// assert_eq!(result, Ok(0x123));
+/// ```
+///
+/// [`WaitForInterrupt`]: crate::timer::pace::WaitForInterrupt
+/// [`WithStrategy`]: crate::timer::pace::WithStrategy
+pub struct Signal<T> {
+    ready: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Signal<T> {
+    /// Creates a signal that hasn't been signaled
+    pub const fn new() -> Self {
+        Signal {
+            ready: AtomicBool::new(false),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Splits the signal into its producer and consumer halves
+    pub fn split(&mut self) -> (Producer<'_, T>, Consumer<'_, T>) {
+        let signal = &*self;
+
+        let producer = Producer {
+            signal,
+            _not_sync: PhantomData,
+        };
+        let consumer = Consumer {
+            signal,
+            _not_sync: PhantomData,
+        };
+
+        (producer, consumer)
+    }
+}
+
+impl<T> Default for Signal<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Signal<T> {
+    fn drop(&mut self) {
+        if *self.ready.get_mut() {
+            // Safety: The flag is set, so the value has been initialized. We
+            // have exclusive access, so nobody else can touch it.
+            unsafe { self.value.get_mut().as_mut_ptr().drop_in_place() };
+        }
+    }
+}
+
+// Safety: The value is only accessed through `Producer` and `Consumer`, of
+// which there can only be one each per `split`. Access between them is
+// synchronized using the ready flag.
+unsafe impl<T> Sync for Signal<T>
+    where T: Send {}
+
+
+/// The sending half of a [`Signal`]
+///
+/// Created by [`Signal::split`]. Can be sent to another context, like an
+/// interrupt handler, but not shared between contexts.
+pub struct Producer<'r, T> {
+    signal: &'r Signal<T>,
+
+    // `signal` requires `&mut self`, so this isn't strictly necessary. It's
+    // here to make sure that `Producer` can never be shared, even if that
+    // changes.
+    _not_sync: PhantomData<*const ()>,
+}
+
+impl<T> Producer<'_, T> {
+    /// Signals the value to the consumer
+    ///
+    /// Returns the value back, if the previous value hasn't been taken yet.
+    pub fn signal(&mut self, value: T) -> Result<(), T> {
+        if self.signal.ready.load(Ordering::Acquire) {
+            return Err(value);
+        }
+
+        // Safety: The consumer only accesses the value while the flag is set,
+        // and we've just observed that it isn't. There's only one producer,
+        // and we have mutable access to it, so nobody else accesses the value
+        // right now.
+        unsafe { (*self.signal.value.get()).as_mut_ptr().write(value) };
+        self.signal.ready.store(true, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Indicates whether the previous value has been taken yet
+    pub fn is_pending(&self) -> bool {
+        self.signal.ready.load(Ordering::Acquire)
+    }
+}
+
+// Safety: The producer only writes the value, which is synchronized with the
+// consumer using the ready flag.
+unsafe impl<T> Send for Producer<'_, T>
+    where T: Send {}
+
+
+/// The receiving half of a [`Signal`]
+///
+/// Created by [`Signal::split`]. Can be sent to another context, but not shared
+/// between contexts.
+pub struct Consumer<'r, T> {
+    signal: &'r Signal<T>,
+
+    // See comment on `Producer`.
+    _not_sync: PhantomData<*const ()>,
+}
+
+impl<T> Consumer<'_, T> {
+    /// Indicates whether a value has been signaled, but not taken yet
+    pub fn is_signaled(&self) -> bool {
+        self.signal.ready.load(Ordering::Acquire)
+    }
+
+    /// Takes the value, if it has been signaled
+    ///
+    /// Returns `WouldBlock`, if no value has been signaled. This can be passed
+    /// directly to the macros in this crate.
+    pub fn try_take(&mut self) -> nb::Result<T, Infallible> {
+        if !self.signal.ready.load(Ordering::Acquire) {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Safety: The flag is set, so the producer has finished writing the
+        // value, and won't touch it until we clear the flag. There's only one
+        // consumer, and we have mutable access to it, so nobody else reads the
+        // value.
+        let value = unsafe { (*self.signal.value.get()).as_ptr().read() };
+        self.signal.ready.store(false, Ordering::Release);
+
+        Ok(value)
+    }
+
+    /// Waits for the value, until the timer expires
+    pub fn wait<Tm>(&mut self, timer: &mut Tm) -> Result<T, TimeoutError>
+        where Tm: TimeoutTimer + ?Sized
+    {
+        wait(self, timer)
+    }
+}
+
+impl<T> EventSource for Consumer<'_, T> {
+    type Event = T;
+    type Error = Infallible;
+
+    fn try_take(&mut self) -> nb::Result<Self::Event, Self::Error> {
+        Consumer::try_take(self)
+    }
+}
+
+// Safety: The consumer only reads the value, which is synchronized with the
+// producer using the ready flag.
+unsafe impl<T> Send for Consumer<'_, T>
+    where T: Send {}
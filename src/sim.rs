@@ -0,0 +1,560 @@
+//! Deterministic simulation of operations and timers, for testing
+//!
+//! Timeout and retry logic is hard to test on real hardware, as it depends on
+//! timing that can't be controlled. This module provides a [`Simulation`] with
+//! a simulated clock, which can be used to create timers and scripted
+//! operations. Every time an operation is polled, the simulated clock advances
+//! by a fixed number of ticks, so the interleaving of operation results and
+//! timer expiry is fully deterministic.
+//!
+//! For code that uses a [`Clock`] directly, [`FakeClock`] provides a clock that
+//! only advances when the test tells it to.
+//!
+//! This module is only available, if the `sim` feature is enabled. It is meant
+//! to be used in tests running on the host.
+//!
+//! # Example
+//!
+//! ``` rust
+//! use embedded_timeout_macros::{
+//!     block_timeout,
+//!     sim::{
+//!         Simulation,
+//!         Step,
+//!     },
+//!     TimeoutError,
+//! };
+//!
+//! // Every poll takes 10 ticks.
+//! let sim = Simulation::new(10);
+//!
+//! let mut timer = sim.timer(100);
+//! let mut op = sim.operation(&[
+//!     Step::Block(3),
+//!     Step::Fail("bus error"),
+//!     Step::Succeed(42),
+//! ]);
+//!
+//! let result = block_timeout!(&mut timer, op.poll());
+//! assert_eq!(result, Err(TimeoutError::Other("bus error")));
+//! assert_eq!(sim.now(), 40);
+//!
+//! let result = block_timeout!(&mut timer, op.poll());
+//! assert_eq!(result, Ok(42));
+//! assert_eq!(op.polls(), 5);
+//! ```
+
+
+use core::cell::Cell;
+
+use embedded_hal::timer::CountDown;
+use void::Void;
+
+use crate::{
+    Clock,
+    Ticks,
+    TimedOperation,
+    TimeoutError,
+};
+
+
+/// A simulated clock, from which timers and operations can be created
+///
+/// See [module documentation] for more information.
+///
+/// [module documentation]: self
+#[derive(Debug)]
+pub struct Simulation {
+    now:            Cell<u32>,
+    ticks_per_poll: u32,
+}
+
+impl Simulation {
+    /// Creates a simulation that starts at zero ticks
+    ///
+    /// The simulated clock advances by `ticks_per_poll`, whenever an operation
+    /// is polled.
+    pub fn new(ticks_per_poll: u32) -> Self {
+        Simulation {
+            now: Cell::new(0),
+            ticks_per_poll,
+        }
+    }
+
+    /// Returns the current value of the simulated clock
+    pub fn now(&self) -> u32 {
+        self.now.get()
+    }
+
+    /// Advances the simulated clock by the given number of ticks
+    pub fn advance(&self, ticks: u32) {
+        self.now.set(self.now.get().wrapping_add(ticks));
+    }
+
+    /// Creates a timer that has been started with the given duration
+    pub fn timer(&self, duration: u32) -> SimTimer<'_> {
+        SimTimer {
+            sim:   self,
+            start: self.now(),
+            duration,
+        }
+    }
+
+    /// Creates an operation that follows the given script
+    pub fn operation<'r, T, E>(&'r self, steps: &'r [Step<T, E>])
+        -> ScriptedOp<'r, T, E>
+    {
+        ScriptedOp {
+            sim:     self,
+            steps,
+            next:    0,
+            blocked: 0,
+            polls:   0,
+        }
+    }
+}
+
+impl Clock for &'_ Simulation {
+    type Ticks = u32;
+
+    fn now(&mut self) -> Self::Ticks {
+        Simulation::now(self)
+    }
+}
+
+
+/// A clock that only advances when told to
+///
+/// Where [`Simulation`] advances its clock automatically, whenever an
+/// operation is polled, `FakeClock` only advances when [`FakeClock::advance`]
+/// or [`FakeClock::set`] are called. It can use any tick type, which makes it
+/// easy to test wraparound edge cases, by starting it close to the maximum
+/// value.
+///
+/// Please note that [`Clock`] is implemented for `&FakeClock`, so the test can
+/// keep control over the clock while a [`Deadline`] or another type is using
+/// it.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     sim::FakeClock,
+///     Deadline,
+/// };
+///
+/// let clock = FakeClock::new(u16::MAX - 5);
+/// let mut deadline = Deadline::start(&clock, 10);
+///
+/// clock.advance(9);
+/// assert!(!deadline.is_expired());
+///
+/// clock.advance(1);
+/// assert!(deadline.is_expired());
+/// assert_eq!(clock.get(), 4);
+/// ```
+///
+/// [`Deadline`]: crate::Deadline
+#[derive(Debug)]
+pub struct FakeClock<T: Ticks> {
+    now: Cell<T>,
+}
+
+impl<T> FakeClock<T>
+    where T: Ticks
+{
+    /// Creates a fake clock that starts at the given value
+    pub fn new(start: T) -> Self {
+        FakeClock {
+            now: Cell::new(start),
+        }
+    }
+
+    /// Returns the current value of the clock
+    pub fn get(&self) -> T {
+        self.now.get()
+    }
+
+    /// Sets the clock to the given value
+    pub fn set(&self, now: T) {
+        self.now.set(now);
+    }
+
+    /// Advances the clock by the given number of ticks, wrapping around
+    pub fn advance(&self, ticks: T) {
+        self.now.set(self.now.get().wrapping_add(ticks));
+    }
+}
+
+impl<T> Clock for FakeClock<T>
+    where T: Ticks
+{
+    type Ticks = T;
+
+    fn now(&mut self) -> Self::Ticks {
+        self.get()
+    }
+}
+
+impl<T> Clock for &'_ FakeClock<T>
+    where T: Ticks
+{
+    type Ticks = T;
+
+    fn now(&mut self) -> Self::Ticks {
+        self.get()
+    }
+}
+
+
+/// A `CountDown` timer that is driven by a [`Simulation`]
+///
+/// The timer counts in ticks of the simulated clock.
+#[derive(Debug)]
+pub struct SimTimer<'r> {
+    sim:      &'r Simulation,
+    start:    u32,
+    duration: u32,
+}
+
+impl CountDown for SimTimer<'_> {
+    type Time = u32;
+
+    fn start<T>(&mut self, count: T)
+        where T: Into<Self::Time>
+    {
+        self.start = self.sim.now();
+        self.duration = count.into();
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        if self.sim.now().wrapping_sub(self.start) >= self.duration {
+            return Ok(());
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+
+/// An operation that follows a script, driven by a [`Simulation`]
+///
+/// Once the end of the script is reached, the operation keeps returning
+/// `WouldBlock`.
+#[derive(Debug)]
+pub struct ScriptedOp<'r, T, E> {
+    sim:     &'r Simulation,
+    steps:   &'r [Step<T, E>],
+    next:    usize,
+    blocked: u32,
+    polls:   u32,
+}
+
+impl<T, E> ScriptedOp<'_, T, E>
+    where
+        T: Clone,
+        E: Clone,
+{
+    /// Polls the operation, advancing the simulated clock
+    pub fn poll(&mut self) -> nb::Result<T, E> {
+        self.polls += 1;
+        self.sim.advance(self.sim.ticks_per_poll);
+
+        let step = match self.steps.get(self.next) {
+            Some(step) => step,
+            None       => return Err(nb::Error::WouldBlock),
+        };
+
+        match step {
+            Step::Block(polls) => {
+                self.blocked += 1;
+                if self.blocked >= *polls {
+                    self.blocked = 0;
+                    self.next += 1;
+                }
+                Err(nb::Error::WouldBlock)
+            }
+            Step::Fail(error) => {
+                self.next += 1;
+                Err(nb::Error::Other(error.clone()))
+            }
+            Step::Succeed(value) => {
+                self.next += 1;
+                Ok(value.clone())
+            }
+        }
+    }
+
+    /// Returns how often the operation has been polled
+    pub fn polls(&self) -> u32 {
+        self.polls
+    }
+
+    /// Indicates whether all steps of the script have been played
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.steps.len()
+    }
+}
+
+impl<T, E> TimedOperation for ScriptedOp<'_, T, E>
+    where
+        T: Clone,
+        E: Clone,
+{
+    type Output = T;
+    type Error  = E;
+
+    fn poll(&mut self) -> nb::Result<Self::Output, Self::Error> {
+        ScriptedOp::poll(self)
+    }
+}
+
+
+/// A step in the script of a [`ScriptedOp`]
+#[derive(Clone, Debug)]
+pub enum Step<T, E> {
+    /// Return `WouldBlock` for the given number of polls (at least one)
+    Block(u32),
+
+    /// Return the given error once
+    Fail(E),
+
+    /// Return the given value once
+    Succeed(T),
+}
+
+
+/// Checks a wait against invariants, in randomly generated scenarios
+///
+/// Scripted operations only cover the scenarios that someone thought of.
+/// `fuzz` generates `runs` random scenarios instead, each with a random number
+/// of ticks per poll, a random timeout, and a [`RandomOp`] that randomly
+/// blocks, fails, or succeeds. Every scenario is passed to `run`, which waits
+/// for the operation, using the timer, and returns the result. Afterwards,
+/// the following invariants are checked:
+///
+/// - The operation was polled at least once.
+/// - Waiting didn't take longer than the timeout, plus the duration of one
+///   poll.
+/// - A timeout was only reported, if the timer had actually expired.
+/// - If the operation succeeded, that success was returned, and the result
+///   is the one the operation returned last.
+///
+/// Returns the first violation. Scenarios are generated from `seed`, so
+/// passing the seed of the violation and a `runs` of 1 reproduces it.
+///
+/// Only available on the host, as part of the `sim` feature.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     sim,
+///     TimeoutError,
+///     TimeoutTimer,
+/// };
+///
+/// let result = sim::fuzz(0x5eed, 1000, |timer, op| {
+///     block_timeout!(timer, op.poll())
+/// });
+/// assert_eq!(result, Ok(()));
+///
+/// // A faulty wait loop, that checks the timer before looking at the result
+/// let result = sim::fuzz(0x5eed, 1000, |timer, op| {
+///     loop {
+///         let result = op.poll();
+///         if timer.is_expired() {
+///             return Err(TimeoutError::Timeout);
+///         }
+///
+///         match result {
+///             Err(nb::Error::WouldBlock) => (),
+///             Err(nb::Error::Other(error)) =>
+///                 return Err(TimeoutError::Other(error)),
+///             Ok(value) =>
+///                 return Ok(value),
+///         }
+///     }
+/// });
+/// assert!(matches!(
+///     result,
+///     Err(sim::Violation { invariant: sim::Invariant::LostSuccess, .. })
+/// ));
+/// ```
+pub fn fuzz<F>(seed: u64, runs: u32, mut run: F) -> Result<(), Violation>
+    where
+        F: FnMut(&mut SimTimer<'_>, &mut RandomOp<'_>)
+            -> Result<u32, TimeoutError<u32>>,
+{
+    for i in 0..runs {
+        let seed = seed.wrapping_add(i as u64);
+        let mut rng = Rng(seed);
+
+        let ticks_per_poll = rng.below(20) as u32 + 1;
+        let duration = rng.below(200) as u32 + 1;
+
+        let sim = Simulation::new(ticks_per_poll);
+        let mut timer = sim.timer(duration);
+        let mut op = RandomOp {
+            sim:          &sim,
+            rng:          Rng(rng.next()),
+            block_weight: rng.below(100) as u8,
+            fail_weight:  rng.below(100) as u8,
+            polls:        0,
+            last:         None,
+            succeeded:    None,
+        };
+
+        let result = run(&mut timer, &mut op);
+        let elapsed = sim.now().wrapping_sub(timer.start);
+
+        let invariant = if op.polls == 0 {
+            Some(Invariant::NoAttempt)
+        }
+        else if elapsed >= duration.saturating_add(ticks_per_poll) {
+            Some(Invariant::Overshoot)
+        }
+        else {
+            match result {
+                Err(TimeoutError::Timeout) if op.succeeded.is_some() =>
+                    Some(Invariant::LostSuccess),
+                Err(TimeoutError::Timeout) if elapsed < timer.duration =>
+                    Some(Invariant::EarlyTimeout),
+                Err(TimeoutError::Timeout) =>
+                    None,
+                Ok(value) if op.last != Some(Ok(value)) =>
+                    Some(Invariant::WrongResult),
+                Err(TimeoutError::Other(error))
+                    if op.last != Some(Err(error)) =>
+                        Some(Invariant::WrongResult),
+                _ =>
+                    None,
+            }
+        };
+
+        if let Some(invariant) = invariant {
+            return Err(Violation {
+                seed,
+                ticks_per_poll,
+                duration,
+                invariant,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+
+/// An operation with random results, created by [`fuzz`]
+///
+/// Each poll advances the simulated clock, like [`ScriptedOp`] does, then
+/// randomly blocks, fails, or succeeds. How likely each of these is, is
+/// chosen randomly per scenario. Errors and values are the number of the
+/// poll, so they can be told apart.
+#[derive(Debug)]
+pub struct RandomOp<'r> {
+    sim:          &'r Simulation,
+    rng:          Rng,
+    block_weight: u8,
+    fail_weight:  u8,
+    polls:        u32,
+    last:         Option<Result<u32, u32>>,
+    succeeded:    Option<u32>,
+}
+
+impl RandomOp<'_> {
+    /// Polls the operation, advancing the simulated clock
+    pub fn poll(&mut self) -> nb::Result<u32, u32> {
+        self.polls += 1;
+        self.sim.advance(self.sim.ticks_per_poll);
+
+        let total = self.block_weight as u64 + self.fail_weight as u64 + 10;
+        let roll = self.rng.below(total);
+
+        if roll < self.block_weight as u64 {
+            return Err(nb::Error::WouldBlock);
+        }
+        if roll < self.block_weight as u64 + self.fail_weight as u64 {
+            self.last = Some(Err(self.polls));
+            return Err(nb::Error::Other(self.polls));
+        }
+
+        self.last = Some(Ok(self.polls));
+        self.succeeded = Some(self.polls);
+        Ok(self.polls)
+    }
+
+    /// Returns how often the operation has been polled
+    pub fn polls(&self) -> u32 {
+        self.polls
+    }
+}
+
+impl TimedOperation for RandomOp<'_> {
+    type Output = u32;
+    type Error  = u32;
+
+    fn poll(&mut self) -> nb::Result<Self::Output, Self::Error> {
+        RandomOp::poll(self)
+    }
+}
+
+
+/// A scenario generated by [`fuzz`], in which an invariant was violated
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Violation {
+    /// The seed that the scenario was generated from
+    pub seed: u64,
+
+    /// The number of ticks that each poll took
+    pub ticks_per_poll: u32,
+
+    /// The duration that the timer was started with
+    pub duration: u32,
+
+    /// The invariant that was violated
+    pub invariant: Invariant,
+}
+
+/// An invariant checked by [`fuzz`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Invariant {
+    /// The operation was never polled
+    NoAttempt,
+
+    /// Waiting took longer than the timeout, plus the duration of one poll
+    Overshoot,
+
+    /// A timeout was reported, before the timer had expired
+    EarlyTimeout,
+
+    /// The operation succeeded, but a timeout was reported
+    LostSuccess,
+
+    /// The result is not what the operation returned last
+    WrongResult,
+}
+
+
+/// A small pseudo-random number generator (SplitMix64)
+///
+/// Good enough to generate scenarios, and doesn't require a dependency.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
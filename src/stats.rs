@@ -0,0 +1,410 @@
+//! Statistics about how long operations take to complete
+//!
+//! Knowing how long peripheral operations actually take is essential for
+//! choosing good timeouts. [`SiteStats`] collects completion times for a
+//! fixed number of labeled call sites, in fixed-size storage, so the
+//! application can retrieve them later, for example to log them or to send
+//! them to a host.
+//!
+//! Completion times can come from anywhere, but [`block_timeout_elapsed`]
+//! provides them directly.
+//!
+//! For worst-case execution time (WCET) analysis, [`SiteWcet`] keeps track of
+//! the largest number of polls and ticks per call site instead.
+//! [`block_timeout_wcet`] records them from the wait loop itself, so timing
+//! budgets can be derived from what was measured on the target.
+//!
+//! This module is only available, if the `stats` feature is enabled.
+//!
+//! [`block_timeout_elapsed`]: crate::block_timeout_elapsed
+//! [`block_timeout_wcet`]: crate::block_timeout_wcet
+
+
+/// Completion time statistics for up to `N` labeled call sites
+///
+/// Times are recorded in ticks of whatever clock was used to measure them. Any
+/// tick type that converts into `u64` is accepted, so clocks with 64-bit ticks
+/// work as well as those with narrower ones. It is up to the application to
+/// use the same clock for the same label.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_elapsed,
+///     stats::SiteStats,
+///     Deadline,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 10; self.0 }
+/// # }
+///
+/// let mut stats = SiteStats::<4>::new();
+///
+/// for _ in 0..3 {
+///     let mut deadline = Deadline::start(Clock(0), 1000);
+///
+///     let result: Result<_, TimeoutError<()>> = block_timeout_elapsed!(
+///         &mut deadline,
+///         Ok(())
+///     );
+///
+///     if let Ok(((), elapsed)) = result {
+///         stats.record("eeprom write", elapsed).unwrap();
+///     }
+/// }
+///
+/// let eeprom = stats.get("eeprom write").unwrap();
+/// assert_eq!(eeprom.count(), 3);
+/// assert_eq!(eeprom.max(), 20);
+/// ```
+#[derive(Debug)]
+pub struct SiteStats<const N: usize> {
+    sites: [Option<(&'static str, Stats)>; N],
+}
+
+impl<const N: usize> SiteStats<N> {
+    /// Creates an empty set of statistics
+    pub fn new() -> Self {
+        SiteStats {
+            sites: [None; N],
+        }
+    }
+
+    /// Records the completion time of an operation at the given call site
+    ///
+    /// Returns an error, if this is a new label, and there's no room left for
+    /// it.
+    pub fn record(&mut self, label: &'static str, ticks: impl Into<u64>)
+        -> Result<(), Full>
+    {
+        site(&mut self.sites, label, Stats::new)?.record(ticks);
+        Ok(())
+    }
+
+    /// Returns the statistics for the given call site
+    pub fn get(&self, label: &str) -> Option<&Stats> {
+        self.iter()
+            .find(|(l, _)| *l == label)
+            .map(|(_, stats)| stats)
+    }
+
+    /// Iterates over all call sites and their statistics
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Stats)> + '_ {
+        self.sites.iter()
+            .filter_map(|site| site.as_ref())
+            .map(|(label, stats)| (*label, stats))
+    }
+
+    /// Removes the statistics of all call sites
+    pub fn clear(&mut self) {
+        self.sites = [None; N];
+    }
+}
+
+impl<const N: usize> Default for SiteStats<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Completion time statistics for a single call site
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Stats {
+    count: u32,
+    min:   u64,
+    max:   u64,
+    last:  u64,
+    total: u64,
+    ewma:  u64,
+}
+
+impl Stats {
+    /// The weight of new completion times in the moving average, as a shift
+    ///
+    /// Each new time is weighted with `1 / 2^EWMA_SHIFT`, which is 1/8.
+    pub const EWMA_SHIFT: u32 = 3;
+
+    /// Creates empty statistics
+    pub fn new() -> Self {
+        Stats {
+            count: 0,
+            min:   u64::MAX,
+            max:   0,
+            last:  0,
+            total: 0,
+            ewma:  0,
+        }
+    }
+
+    /// Records a completion time
+    pub fn record(&mut self, ticks: impl Into<u64>) {
+        let ticks = ticks.into();
+
+        // Stored multiplied by `2^EWMA_SHIFT`, so no precision is lost.
+        self.ewma = match self.count {
+            0 =>
+                ticks.saturating_mul(1 << Self::EWMA_SHIFT),
+            _ =>
+                self.ewma
+                    .saturating_sub(self.ewma >> Self::EWMA_SHIFT)
+                    .saturating_add(ticks),
+        };
+
+        self.count = self.count.saturating_add(1);
+        self.min   = self.min.min(ticks);
+        self.max   = self.max.max(ticks);
+        self.last  = ticks;
+        self.total = self.total.saturating_add(ticks);
+    }
+
+    /// Returns the number of completion times recorded
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the shortest completion time
+    ///
+    /// Returns `u64::MAX`, if nothing has been recorded yet.
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    /// Returns the longest completion time
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Returns the most recent completion time
+    pub fn last(&self) -> u64 {
+        self.last
+    }
+
+    /// Returns the mean completion time, rounded down
+    ///
+    /// Returns zero, if nothing has been recorded yet.
+    pub fn mean(&self) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+
+        self.total / self.count as u64
+    }
+
+    /// Returns the exponentially weighted moving average of completion times
+    ///
+    /// Unlike [`Stats::mean`], this follows changes quickly, as older times
+    /// lose weight with every new one (see [`Stats::EWMA_SHIFT`]). It is
+    /// cheap to maintain, and suitable for code that adapts its timeouts at
+    /// runtime, for example by waiting for four times the recent average.
+    ///
+    /// Returns zero, if nothing has been recorded yet.
+    ///
+    /// # Example
+    ///
+    /// ``` rust
+    /// use embedded_timeout_macros::stats::Stats;
+    ///
+    /// let mut stats = Stats::new();
+    ///
+    /// stats.record(100u32);
+    /// assert_eq!(stats.ewma(), 100);
+    ///
+    /// stats.record(180u32);
+    /// assert_eq!(stats.ewma(), 110);
+    ///
+    /// let timeout = stats.ewma() * 4;
+    /// # assert_eq!(timeout, 440);
+    /// ```
+    pub fn ewma(&self) -> u64 {
+        self.ewma >> Self::EWMA_SHIFT
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Worst-case execution times for up to `N` labeled call sites
+///
+/// Records the largest number of polls, and the largest number of ticks, that
+/// an operation took to complete at each call site. Operations that timed out
+/// are counted separately, and don't affect the maximums, as they didn't
+/// complete. Like with [`SiteStats`], it is up to the application to use the
+/// same clock for the same label.
+///
+/// Use [`block_timeout_wcet`] to record directly from a wait loop.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::stats::SiteWcet;
+///
+/// let mut wcet = SiteWcet::<4>::new();
+///
+/// wcet.record("adc", 3, 120u32).unwrap();
+/// wcet.record("adc", 5, 90u32).unwrap();
+/// wcet.record_timeout("adc").unwrap();
+///
+/// let adc = wcet.get("adc").unwrap();
+/// assert_eq!(adc.count(), 2);
+/// assert_eq!(adc.timeouts(), 1);
+/// assert_eq!(adc.max_polls(), 5);
+/// assert_eq!(adc.max_ticks(), 120);
+/// ```
+///
+/// [`block_timeout_wcet`]: crate::block_timeout_wcet
+#[derive(Debug)]
+pub struct SiteWcet<const N: usize> {
+    sites: [Option<(&'static str, Wcet)>; N],
+}
+
+impl<const N: usize> SiteWcet<N> {
+    /// Creates an empty set of execution times
+    pub fn new() -> Self {
+        SiteWcet {
+            sites: [None; N],
+        }
+    }
+
+    /// Records a completed operation at the given call site
+    ///
+    /// `polls` is the number of times the operation was polled, `ticks` the
+    /// time it took to complete. Returns an error, if this is a new label, and
+    /// there's no room left for it.
+    pub fn record(&mut self,
+        label: &'static str,
+        polls: u32,
+        ticks: impl Into<u64>,
+    )
+        -> Result<(), Full>
+    {
+        site(&mut self.sites, label, Wcet::new)?.record(polls, ticks);
+        Ok(())
+    }
+
+    /// Records an operation at the given call site, that timed out
+    ///
+    /// Returns an error, if this is a new label, and there's no room left for
+    /// it.
+    pub fn record_timeout(&mut self, label: &'static str)
+        -> Result<(), Full>
+    {
+        site(&mut self.sites, label, Wcet::new)?.record_timeout();
+        Ok(())
+    }
+
+    /// Returns the execution times for the given call site
+    pub fn get(&self, label: &str) -> Option<&Wcet> {
+        self.iter()
+            .find(|(l, _)| *l == label)
+            .map(|(_, wcet)| wcet)
+    }
+
+    /// Iterates over all call sites and their execution times
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Wcet)> + '_ {
+        self.sites.iter()
+            .filter_map(|site| site.as_ref())
+            .map(|(label, wcet)| (*label, wcet))
+    }
+
+    /// Removes the execution times of all call sites
+    pub fn clear(&mut self) {
+        self.sites = [None; N];
+    }
+}
+
+impl<const N: usize> Default for SiteWcet<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Worst-case execution times for a single call site
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Wcet {
+    count:     u32,
+    timeouts:  u32,
+    max_polls: u32,
+    max_ticks: u64,
+}
+
+impl Wcet {
+    /// Creates empty execution times
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a completed operation
+    pub fn record(&mut self, polls: u32, ticks: impl Into<u64>) {
+        self.count     = self.count.saturating_add(1);
+        self.max_polls = self.max_polls.max(polls);
+        self.max_ticks = self.max_ticks.max(ticks.into());
+    }
+
+    /// Records an operation that timed out
+    pub fn record_timeout(&mut self) {
+        self.timeouts = self.timeouts.saturating_add(1);
+    }
+
+    /// Returns the number of completed operations recorded
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the number of operations that timed out
+    pub fn timeouts(&self) -> u32 {
+        self.timeouts
+    }
+
+    /// Returns the largest number of polls a completed operation took
+    pub fn max_polls(&self) -> u32 {
+        self.max_polls
+    }
+
+    /// Returns the largest number of ticks a completed operation took
+    pub fn max_ticks(&self) -> u64 {
+        self.max_ticks
+    }
+}
+
+
+/// Returned by [`SiteStats`] and [`SiteWcet`], if there's no room for another
+/// label
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Full;
+
+
+fn site<'s, T, const N: usize>(
+    sites: &'s mut [Option<(&'static str, T)>; N],
+    label: &'static str,
+    new:   impl FnOnce() -> T,
+)
+    -> Result<&'s mut T, Full>
+{
+    let i = match sites.iter().position(|site| {
+        matches!(site, Some((l, _)) if *l == label)
+    }) {
+        Some(i) => i,
+        None    => {
+            let i = sites.iter().position(|site| site.is_none()).ok_or(Full)?;
+            sites[i] = Some((label, new()));
+            i
+        }
+    };
+
+    match &mut sites[i] {
+        Some((_, value)) => Ok(value),
+        None             => unreachable!(),
+    }
+}
@@ -0,0 +1,344 @@
+//! Compact records of timeouts, for sending them off the device
+//!
+//! Devices in the field can't be debugged, but they can report how their
+//! timeouts are doing. A [`Record`] describes a single wait in a few bytes:
+//! where it happened, how it ended, how many attempts it took, and how long.
+//! [`TelemetryLog`] keeps the most recent records in fixed-size storage, until
+//! the application drains them, for example to send them over LoRa.
+//!
+//! Records have a stable wire format, which is the one that `postcard` uses
+//! for a struct with the same fields, so the host can decode them using
+//! `postcard` and `serde`:
+//!
+//! ``` ignore
+//! // On the host
+//! #[derive(serde::Deserialize)]
+//! struct Record {
+//!     site:     u16,
+//!     outcome:  Outcome,
+//!     attempts: u32,
+//!     elapsed:  u32,
+//! }
+//!
+//! #[derive(serde::Deserialize)]
+//! enum Outcome {
+//!     Success,
+//!     Timeout,
+//!     Error,
+//! }
+//! ```
+//!
+//! This crate doesn't depend on `postcard` or `serde` itself. The format is
+//! simple enough to encode directly, using [`Record::encode`], and to decode
+//! using [`Record::decode`].
+//!
+//! This module is only available, if the `telemetry` feature is enabled.
+//!
+//! # Example
+//!
+//! ``` rust
+//! use embedded_timeout_macros::{
+//!     telemetry::{
+//!         Outcome,
+//!         Record,
+//!         TelemetryLog,
+//!     },
+//!     TimeoutError,
+//! };
+//!
+//! const SENSOR: u16 = 7;
+//!
+//! let mut log = TelemetryLog::<16>::new();
+//!
+//! let result: Result<u8, TimeoutError<()>> = Err(TimeoutError::Timeout);
+//! log.push(Record::new(SENSOR, &result, 12, 5000u32));
+//!
+//! let mut buf = [0; Record::MAX_SIZE];
+//! for record in log.drain() {
+//!     let bytes = record.encode(&mut buf);
+//!     // send `bytes`
+//! #   assert_eq!(bytes, [7, 1, 12, 0x88, 0x27]);
+//! #   assert_eq!(Record::decode(bytes), Ok((record, &[][..])));
+//! }
+//!
+//! assert!(log.is_empty());
+//! ```
+
+
+use core::convert::TryFrom;
+
+use crate::{
+    codes,
+    TimeoutError,
+};
+
+
+/// A compact record of a single wait
+///
+/// See the [module documentation] for the wire format.
+///
+/// [module documentation]: self
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Record {
+    /// Identifies the call site; the numbering is up to the application
+    pub site: u16,
+
+    /// How the wait ended
+    pub outcome: Outcome,
+
+    /// The number of times the operation was attempted
+    pub attempts: u32,
+
+    /// The time the wait took, in ticks of whatever clock measured it
+    pub elapsed: u32,
+}
+
+impl Record {
+    /// The maximum number of bytes an encoded record takes up
+    pub const MAX_SIZE: usize = 3 + 1 + 5 + 5;
+
+    /// Creates a record from the result of a wait
+    ///
+    /// `elapsed` saturates at `u32::MAX`, if it doesn't fit.
+    pub fn new<T, E>(
+        site:     u16,
+        result:   &Result<T, TimeoutError<E>>,
+        attempts: u32,
+        elapsed:  impl Into<u64>,
+    )
+        -> Self
+    {
+        let elapsed = elapsed.into().min(u32::MAX as u64) as u32;
+
+        Record {
+            site,
+            outcome: Outcome::of(result),
+            attempts,
+            elapsed,
+        }
+    }
+
+    /// Encodes the record into the buffer
+    ///
+    /// Returns the part of the buffer that holds the encoded record.
+    pub fn encode<'b>(&self, buf: &'b mut [u8; Self::MAX_SIZE]) -> &'b [u8] {
+        let mut len = 0;
+
+        len += encode_varint(self.site as u32, &mut buf[len..]);
+        len += encode_varint(self.outcome as u32, &mut buf[len..]);
+        len += encode_varint(self.attempts, &mut buf[len..]);
+        len += encode_varint(self.elapsed, &mut buf[len..]);
+
+        &buf[..len]
+    }
+
+    /// Decodes a record from the start of the bytes
+    ///
+    /// Returns the record, and the bytes that follow it.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, &[u8]), DecodeError> {
+        let (site, bytes) = decode_varint(bytes)?;
+        let site = u16::try_from(site).map_err(|_| DecodeError::Invalid)?;
+
+        let (outcome, bytes) = decode_varint(bytes)?;
+        let outcome = match outcome {
+            0 => Outcome::Success,
+            1 => Outcome::Timeout,
+            2 => Outcome::Error,
+            _ => return Err(DecodeError::Invalid),
+        };
+
+        let (attempts, bytes) = decode_varint(bytes)?;
+        let (elapsed, bytes)  = decode_varint(bytes)?;
+
+        let record = Record {
+            site,
+            outcome,
+            attempts,
+            elapsed,
+        };
+
+        Ok((record, bytes))
+    }
+}
+
+
+/// How a wait ended
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The operation succeeded
+    Success = 0,
+
+    /// The timer expired
+    Timeout = 1,
+
+    /// The operation failed
+    Error = 2,
+}
+
+impl Outcome {
+    /// Returns the outcome of a wait, based on its result
+    pub fn of<T, E>(result: &Result<T, TimeoutError<E>>) -> Self {
+        match result {
+            Ok(_)                       => Outcome::Success,
+            Err(TimeoutError::Timeout)  => Outcome::Timeout,
+            Err(TimeoutError::Other(_)) => Outcome::Error,
+        }
+    }
+}
+
+
+/// The most recent records, in fixed-size storage
+///
+/// Holds up to `N` records. If a record is pushed while the log is full, the
+/// oldest record is dropped to make room, as recent records are the more
+/// interesting ones. The number of records dropped that way is available from
+/// [`TelemetryLog::lost`], so it can be reported too.
+#[derive(Debug)]
+pub struct TelemetryLog<const N: usize> {
+    records: [Option<Record>; N],
+    head:    usize,
+    len:     usize,
+    lost:    u32,
+}
+
+impl<const N: usize> TelemetryLog<N> {
+    /// Creates an empty log
+    pub const fn new() -> Self {
+        TelemetryLog {
+            records: [None; N],
+            head:    0,
+            len:     0,
+            lost:    0,
+        }
+    }
+
+    /// Adds a record, dropping the oldest one, if the log is full
+    pub fn push(&mut self, record: Record) {
+        if N == 0 {
+            self.lost = self.lost.saturating_add(1);
+            return;
+        }
+
+        let i = (self.head + self.len) % N;
+        self.records[i] = Some(record);
+
+        if self.len < N {
+            self.len += 1;
+        }
+        else {
+            self.head = (self.head + 1) % N;
+            self.lost = self.lost.saturating_add(1);
+        }
+    }
+
+    /// Removes the oldest record and returns it
+    pub fn pop(&mut self) -> Option<Record> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let record = self.records[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+
+        record
+    }
+
+    /// Removes all records, returning an iterator over them, oldest first
+    ///
+    /// Records that the iterator doesn't get to stay in the log.
+    pub fn drain(&mut self) -> impl Iterator<Item = Record> + '_ {
+        core::iter::from_fn(move || self.pop())
+    }
+
+    /// Returns the number of records in the log
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Indicates whether the log is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of records that were dropped, as the log was full
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+
+    /// Resets the number of records that were dropped
+    ///
+    /// Call this after reporting it.
+    pub fn reset_lost(&mut self) {
+        self.lost = 0;
+    }
+}
+
+impl<const N: usize> Default for TelemetryLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// An error returned by [`Record::decode`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The bytes ended before the record did
+    UnexpectedEnd,
+
+    /// The bytes don't encode a valid record
+    Invalid,
+}
+
+impl DecodeError {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            DecodeError::UnexpectedEnd => codes::DECODE_UNEXPECTED_END,
+            DecodeError::Invalid       => codes::DECODE_INVALID,
+        }
+    }
+}
+
+
+fn encode_varint(mut value: u32, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            buf[len] = byte;
+            return len + 1;
+        }
+
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+fn decode_varint(bytes: &[u8]) -> Result<(u32, &[u8]), DecodeError> {
+    let mut value: u32 = 0;
+
+    for (i, &byte) in bytes.iter().enumerate().take(5) {
+        let bits = (byte & 0x7f) as u32;
+        if i == 4 && bits > 0x0f {
+            return Err(DecodeError::Invalid);
+        }
+
+        value |= bits << (7 * i);
+
+        if byte & 0x80 == 0 {
+            return Ok((value, &bytes[i + 1..]));
+        }
+    }
+
+    match bytes.len() {
+        0..=4 => Err(DecodeError::UnexpectedEnd),
+        _     => Err(DecodeError::Invalid),
+    }
+}
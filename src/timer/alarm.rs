@@ -0,0 +1,139 @@
+//! Alarms that go off at absolute points in time
+
+
+use crate::{
+    Clock,
+    Ticks,
+    TimeoutTimer,
+};
+
+
+/// An alarm that goes off at an absolute point in time
+///
+/// Where a [`Deadline`] measures a duration relative to the time it was
+/// started, an `Alarm` is set to an absolute value of a [`Clock`]. This makes a
+/// difference when implementing periodic work: Rearming an alarm by adding the
+/// period to the previous expiry time doesn't accumulate drift, no matter how
+/// late the alarm is being handled.
+///
+/// Absolute clock values are compared in a wrap-safe way. An alarm is
+/// considered to be due, if the current value of the clock is less than
+/// [`Ticks::HALF`] ahead of the expiry time. This means an alarm must not be
+/// set further than that into the future.
+///
+/// `Alarm` implements [`TimeoutTimer`], so it can be passed to all macros in
+/// this crate. An alarm that isn't set never expires.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::Alarm;
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 }
+/// # }
+/// #
+/// # let mut clock = Clock(0);
+///
+/// const PERIOD: u32 = 100;
+///
+/// let mut alarm = Alarm::new(&mut clock);
+/// alarm.set(PERIOD);
+///
+/// // We're late, but that doesn't shift the schedule.
+/// alarm.clock().0 = 130;
+/// assert!(alarm.rearm_if_due(PERIOD));
+/// assert_eq!(alarm.at(), Some(200));
+/// ```
+///
+/// [`Deadline`]: crate::Deadline
+/// [`TimeoutTimer`]: crate::TimeoutTimer
+#[derive(Debug)]
+pub struct Alarm<C: Clock> {
+    clock: C,
+    at:    Option<C::Ticks>,
+}
+
+impl<C> Alarm<C>
+    where C: Clock
+{
+    /// Creates an alarm that isn't set
+    pub fn new(clock: C) -> Self {
+        Alarm {
+            clock,
+            at: None,
+        }
+    }
+
+    /// Sets the alarm to go off at the given value of the clock
+    pub fn set(&mut self, at: C::Ticks) {
+        self.at = Some(at);
+    }
+
+    /// Sets the alarm to go off after the given number of ticks
+    pub fn set_in(&mut self, ticks: C::Ticks) {
+        let now = self.clock.now();
+        self.at = Some(now.wrapping_add(ticks));
+    }
+
+    /// Cancels the alarm
+    pub fn cancel(&mut self) {
+        self.at = None;
+    }
+
+    /// Returns the value of the clock at which the alarm goes off
+    ///
+    /// Returns `None`, if the alarm isn't set.
+    pub fn at(&self) -> Option<C::Ticks> {
+        self.at
+    }
+
+    /// Returns the value of the clock at which the alarm goes off
+    ///
+    /// This is the same as [`Alarm::at`]. It exists for consistency with the
+    /// other types in this crate that can report their next deadline.
+    pub fn next_deadline(&self) -> Option<C::Ticks> {
+        self.at
+    }
+
+    /// Indicates whether the alarm is set and its time has come
+    pub fn is_due(&mut self) -> bool {
+        match self.at {
+            Some(at) => self.clock.now().wrapping_sub(at) < C::Ticks::HALF,
+            None     => false,
+        }
+    }
+
+    /// Rearms the alarm by `period` ticks, if it is due
+    ///
+    /// The new expiry time is computed from the previous one, not from the
+    /// current value of the clock, so periodic work doesn't drift. Returns
+    /// whether the alarm was due.
+    pub fn rearm_if_due(&mut self, period: C::Ticks) -> bool {
+        if !self.is_due() {
+            return false;
+        }
+
+        if let Some(at) = self.at {
+            self.at = Some(at.wrapping_add(period));
+        }
+
+        true
+    }
+
+    /// Provides access to the clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+impl<C> TimeoutTimer for Alarm<C>
+    where C: Clock
+{
+    fn is_expired(&mut self) -> bool {
+        self.is_due()
+    }
+}
@@ -0,0 +1,153 @@
+//! Precise short intervals, for bit-banged protocols
+//!
+//! Protocols like 1-Wire, or the one used by DHT sensors, are implemented by
+//! toggling and sampling a pin at precisely timed intervals of a few
+//! microseconds. `CountDown` timers are too coarse for that, and restarting
+//! them for every interval takes too long. [`BitTimer`] reads a free-running
+//! counter instead, and schedules every interval relative to the end of the
+//! previous one, so errors don't accumulate over the bits of a transfer.
+//!
+//! Like the rest of the [`timer`](crate::timer) module, this module is only
+//! available, if the `timer` feature is enabled.
+
+
+use core::hint::spin_loop;
+
+use crate::{
+    bound::PollBound,
+    Clock,
+    TimeoutError,
+    Ticks,
+};
+
+
+/// Waits for precise intervals, and measures pulses, using a clock
+///
+/// Keeps track of a mark, the point in time that the next interval starts at.
+/// [`BitTimer::wait`] waits until an interval has passed since the mark, then
+/// moves the mark to the end of the interval. [`BitTimer::wait_while`] waits
+/// for a pin to change, measures how long that took, and moves the mark to
+/// the point where the change was noticed.
+///
+/// The clock should run at 1 MHz or more, which is what the counters of most
+/// microcontrollers can do. Use [`CounterClock::micros`] to convert
+/// microseconds into ticks.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     timer::bitbang::BitTimer,
+///     CounterClock,
+///     TimeoutError,
+/// };
+/// #
+/// # let counter = core::cell::Cell::new(0u32);
+/// # let read_counter = || { counter.set(counter.get() + 1); counter.get() };
+/// # let mut line_low = 0;
+/// # let mut sensor_pulls_low = || { line_low += 1; line_low < 30 };
+///
+/// // A counter running at 1 MHz
+/// let clock = CounterClock::new(1_000_000, read_counter);
+/// let mut timer = BitTimer::new(clock);
+///
+/// // Send the start signal, and wait for the sensor's response.
+/// timer.restart();
+/// // pin.set_low();
+/// timer.wait(18_000);
+/// // pin.set_high();
+///
+/// // The response lasts about 80 µs, so give up after 100 µs.
+/// let pulse = timer.wait_while(100, &mut sensor_pulls_low);
+/// assert!(matches!(pulse, Ok(ticks) if ticks < 100));
+///
+/// // The line stays low, so the sensor isn't there.
+/// let pulse = timer.wait_while(100, || true);
+/// assert_eq!(pulse, Err(TimeoutError::Timeout));
+/// ```
+///
+/// [`CounterClock::micros`]: crate::CounterClock::micros
+#[derive(Debug)]
+pub struct BitTimer<C: Clock> {
+    clock: C,
+    mark:  C::Ticks,
+}
+
+impl<C> BitTimer<C>
+    where C: Clock
+{
+    /// Creates an instance, with the mark set to now
+    pub fn new(mut clock: C) -> Self {
+        let mark = clock.now();
+
+        BitTimer {
+            clock,
+            mark,
+        }
+    }
+
+    /// Sets the mark to now
+    ///
+    /// Call this before the first interval of a transfer, if some time has
+    /// passed since the last one.
+    pub fn restart(&mut self) {
+        self.mark = self.clock.now();
+    }
+
+    /// Returns the number of ticks since the mark
+    pub fn elapsed(&mut self) -> C::Ticks {
+        self.clock.now().wrapping_sub(self.mark)
+    }
+
+    /// Waits until `ticks` have passed since the mark, and moves the mark
+    ///
+    /// The mark is moved forward by exactly `ticks`, not to the current time,
+    /// so the next interval starts where this one should have ended. If the
+    /// interval has already passed (because an interrupt delayed the code, for
+    /// example), this returns right away.
+    pub fn wait(&mut self, ticks: C::Ticks) {
+        let mut bound = PollBound::new();
+
+        while self.elapsed() < ticks && !bound.exhausted() {
+            spin_loop();
+        }
+
+        self.mark = self.mark.wrapping_add(ticks);
+    }
+
+    /// Waits while `condition` is true, for at most `max` ticks since the mark
+    ///
+    /// Returns the number of ticks from the mark to the point where the
+    /// condition was noticed to be false, and moves the mark there. This is
+    /// how long a pulse lasted, if the mark was at its start.
+    ///
+    /// Returns [`TimeoutError::Timeout`], if the condition is still true after
+    /// `max` ticks. The mark is moved forward by `max` then.
+    pub fn wait_while<F>(&mut self, max: C::Ticks, mut condition: F)
+        -> Result<C::Ticks, TimeoutError>
+        where F: FnMut() -> bool
+    {
+        let mut bound = PollBound::new();
+
+        loop {
+            // Read the clock first, so the time is never later than the
+            // moment the condition was checked.
+            let now = self.clock.now();
+            let elapsed = now.wrapping_sub(self.mark);
+
+            if !condition() {
+                self.mark = now;
+                return Ok(elapsed);
+            }
+            if elapsed >= max || bound.exhausted() {
+                self.mark = self.mark.wrapping_add(max);
+                return Err(TimeoutError::Timeout);
+            }
+        }
+    }
+
+    /// Provides access to the clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
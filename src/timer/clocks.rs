@@ -0,0 +1,345 @@
+//! Clocks based on hardware counters and the host's clock
+
+
+use core::time::Duration;
+
+use crate::{
+    ticks_from_duration,
+    Clock,
+    Deadline,
+    FromDuration,
+    Ticks,
+};
+
+
+/// A clock that reads a free-running hardware counter
+///
+/// This is the easiest way to get a [`Clock`] on hardware that isn't supported
+/// by any HAL: Pass a closure that reads a counter register, the frequency at
+/// which that counter counts, and (if it's not a 32-bit counter) its width in
+/// bits.
+///
+/// The tick type `T` of the clock can be `u8`, `u16`, or `u32` (the default),
+/// and should match the width of the counter register. Counters that are
+/// narrower than `T` are extended to the width of `T`. For this to work, the
+/// clock must be read at least once per wraparound of the hardware counter.
+/// This means that on 8- and 16-bit targets, a short counter can be used to
+/// express long timeouts, without any 32-bit arithmetic in the wait loop.
+///
+/// The counter is expected to count up. If it counts down, invert the value in
+/// the closure.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     CounterClock,
+///     Deadline,
+/// };
+/// #
+/// # let counter = core::cell::Cell::new(0u32);
+/// # let read_counter_register = || {
+/// #     counter.set(counter.get().wrapping_add(0x1000));
+/// #     counter.get() & 0xffff
+/// # };
+///
+/// // A 16-bit counter running at 1 MHz, extended to 32 bits
+/// let mut clock = CounterClock::with_bits(16, 1_000_000, || {
+///     read_counter_register()
+/// });
+///
+/// let timeout = clock.millis(100);
+/// assert_eq!(timeout, 100_000);
+///
+/// let mut deadline = Deadline::start(clock, timeout);
+/// # let _ = deadline.is_expired();
+/// ```
+///
+/// On a 16-bit MCU, the clock can use 16-bit ticks:
+///
+/// ``` rust
+/// use embedded_timeout_macros::CounterClock;
+/// #
+/// # let read_timer_register = || 0u16;
+///
+/// // A 16-bit counter running at 32.768 kHz
+/// let clock = CounterClock::<_, u16>::new(32_768, read_timer_register);
+///
+/// assert_eq!(clock.millis(1000), 32_768);
+///
+/// // Timeouts are rounded up, so they're never shorter than requested.
+/// assert_eq!(clock.millis(1), 33);
+///
+/// // The result saturates, if it can't be represented using 16 bits.
+/// assert_eq!(clock.millis(5000), u16::MAX);
+/// ```
+pub struct CounterClock<F, T = u32> {
+    read:      F,
+    frequency: u32,
+    bits:      u32,
+    last:      T,
+    ticks:     T,
+}
+
+impl<F, T> CounterClock<F, T>
+    where
+        F: FnMut() -> T,
+        T: Ticks,
+{
+    /// Creates a clock from a counter that is as wide as the tick type
+    ///
+    /// `frequency` is the frequency of the counter in Hz.
+    pub fn new(frequency: u32, read: F) -> Self {
+        Self::with_bits(T::BITS, frequency, read)
+    }
+
+    /// Creates a clock from a counter with the given width
+    ///
+    /// `frequency` is the frequency of the counter in Hz.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `bits` is zero or larger than the width of the tick type.
+    pub fn with_bits(bits: u32, frequency: u32, mut read: F) -> Self {
+        assert!(
+            bits > 0 && bits <= T::BITS,
+            "Counter width must be at least 1 bit, and fit the tick type",
+        );
+
+        let last = read().low_bits(bits);
+
+        CounterClock {
+            read,
+            frequency,
+            bits,
+            last,
+            ticks: last,
+        }
+    }
+
+    /// Returns the frequency of the clock in Hz
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    /// Converts milliseconds into ticks of this clock, rounding up
+    ///
+    /// Saturates, if the result doesn't fit into the tick type.
+    pub fn millis(&self, ms: u32) -> T {
+        let ticks = ms as u64 * self.frequency as u64;
+        T::saturating_from_u64(ticks / 1_000 + (ticks % 1_000 != 0) as u64)
+    }
+
+    /// Converts microseconds into ticks of this clock, rounding up
+    ///
+    /// Saturates, if the result doesn't fit into the tick type.
+    pub fn micros(&self, us: u32) -> T {
+        let ticks = us as u64 * self.frequency as u64;
+        T::saturating_from_u64(
+            ticks / 1_000_000 + (ticks % 1_000_000 != 0) as u64
+        )
+    }
+
+    /// Converts a `Duration` into ticks of this clock, rounding up
+    ///
+    /// Returns `None`, if the result doesn't fit into the tick type.
+    pub fn convert_duration(&self, duration: Duration) -> Option<T> {
+        let ticks = ticks_from_duration(duration, self.frequency)?;
+        T::checked_from_u64(ticks)
+    }
+}
+
+impl<F, T> Clock for CounterClock<F, T>
+    where
+        F: FnMut() -> T,
+        T: Ticks,
+{
+    type Ticks = T;
+
+    fn now(&mut self) -> Self::Ticks {
+        let raw = (self.read)().low_bits(self.bits);
+        let delta = raw.wrapping_sub(self.last).low_bits(self.bits);
+
+        self.last = raw;
+        self.ticks = self.ticks.wrapping_add(delta);
+
+        self.ticks
+    }
+}
+
+
+/// A clock that reads a 64-bit hardware counter
+///
+/// Like [`CounterClock`], but for counters that are 64 bits wide, and thus
+/// never wrap around in practice. Many modern MCUs have such a counter, for
+/// example the `SYSTIMER` of the ESP32 family, or the `TIMER` of the RP2040.
+/// Pass a closure that reads the counter, using the HAL or the register
+/// directly.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     Counter64Clock,
+///     Deadline,
+/// };
+/// #
+/// # let read_system_timer = || 0u64;
+///
+/// // A 64-bit system timer running at 16 MHz
+/// let clock = Counter64Clock::new(16_000_000, read_system_timer);
+///
+/// let timeout = clock.millis(100);
+/// assert_eq!(timeout, 1_600_000);
+///
+/// let mut deadline = Deadline::start(clock, timeout);
+/// # let _ = deadline.is_expired();
+/// ```
+pub struct Counter64Clock<F> {
+    read:      F,
+    frequency: u32,
+}
+
+impl<F> Counter64Clock<F>
+    where F: FnMut() -> u64
+{
+    /// Creates a clock from a 64-bit counter
+    ///
+    /// `frequency` is the frequency of the counter in Hz.
+    pub fn new(frequency: u32, read: F) -> Self {
+        Counter64Clock {
+            read,
+            frequency,
+        }
+    }
+
+    /// Returns the frequency of the clock in Hz
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    /// Converts milliseconds into ticks of this clock, rounding up
+    pub fn millis(&self, ms: u32) -> u64 {
+        let ticks = ms as u64 * self.frequency as u64;
+        ticks / 1_000 + (ticks % 1_000 != 0) as u64
+    }
+
+    /// Converts microseconds into ticks of this clock, rounding up
+    pub fn micros(&self, us: u32) -> u64 {
+        let ticks = us as u64 * self.frequency as u64;
+        ticks / 1_000_000 + (ticks % 1_000_000 != 0) as u64
+    }
+
+    /// Converts a `Duration` into ticks of this clock, rounding up
+    ///
+    /// Returns `None`, if the result doesn't fit into 64 bits.
+    pub fn convert_duration(&self, duration: Duration) -> Option<u64> {
+        ticks_from_duration(duration, self.frequency)
+    }
+}
+
+impl<F> Clock for Counter64Clock<F>
+    where F: FnMut() -> u64
+{
+    type Ticks = u64;
+
+    fn now(&mut self) -> Self::Ticks {
+        (self.read)()
+    }
+}
+
+
+/// A clock based on `std::time::Instant`
+///
+/// Counts microseconds since its creation. This allows code that uses
+/// [`Deadline`] and the other clock-based types to run unmodified on hosted
+/// platforms, like a Raspberry Pi running Linux, for prototyping.
+///
+/// Only available, if the `std` feature is enabled. Please note that
+/// `linux_embedded_hal::SysTimer` implements `CountDown`, so it can be passed
+/// to the macros directly, without any adapter.
+///
+/// # Example
+///
+/// ``` rust
+/// use std::time::Duration;
+///
+/// use embedded_timeout_macros::{
+///     Deadline,
+///     FromDuration,
+///     StdClock,
+///     StartTimer,
+/// };
+///
+/// let mut deadline = Deadline::start(StdClock::new(), 0);
+///
+/// let duration = deadline.convert_duration(Duration::from_millis(10))
+///     .unwrap();
+/// deadline.try_start(duration).unwrap();
+///
+/// std::thread::sleep(Duration::from_millis(10));
+/// assert!(deadline.is_expired());
+///
+/// // Durations are rounded up to the next microsecond.
+/// assert_eq!(deadline.convert_duration(Duration::from_nanos(1500)), Some(2));
+/// ```
+///
+/// [`Deadline`]: crate::Deadline
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+pub struct StdClock {
+    epoch: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl StdClock {
+    /// Creates a clock that starts counting now
+    pub fn new() -> Self {
+        StdClock {
+            epoch: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    type Ticks = u64;
+
+    fn now(&mut self) -> Self::Ticks {
+        self.epoch.elapsed().as_micros() as u64
+    }
+}
+
+
+impl<F, T> FromDuration for Deadline<CounterClock<F, T>>
+    where
+        F: FnMut() -> T,
+        T: Ticks,
+{
+    fn convert_duration(&self, duration: Duration) -> Option<Self::Duration> {
+        self.clock_ref().convert_duration(duration)
+    }
+}
+
+impl<F> FromDuration for Deadline<Counter64Clock<F>>
+    where F: FnMut() -> u64
+{
+    fn convert_duration(&self, duration: Duration) -> Option<Self::Duration> {
+        self.clock_ref().convert_duration(duration)
+    }
+}
+
+#[cfg(feature = "std")]
+impl FromDuration for Deadline<crate::StdClock> {
+    fn convert_duration(&self, duration: Duration) -> Option<Self::Duration> {
+        // The clock counts microseconds.
+        ticks_from_duration(duration, 1_000_000)
+    }
+}
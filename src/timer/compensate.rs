@@ -0,0 +1,127 @@
+//! Leaving time that shouldn't count out of timeouts
+//!
+//! A timeout is meant to limit how long a wait takes, but while the wait
+//! loop is held up by a long interrupt handler, or the chip sits in a low-power
+//! mode, the clock keeps counting against it. If the firmware knows how much
+//! time that was, [`Compensated`] can leave it out, so all deadlines based on
+//! the clock get that time back.
+//!
+//! Like the rest of the [`timer`](crate::timer) module, this module is only
+//! available, if the `timer` feature is enabled.
+
+
+use crate::{
+    Clock,
+    Ticks,
+};
+
+
+/// A clock that leaves out the ticks reported by a correction source
+///
+/// The correction source is a function that returns the total number of ticks
+/// that shouldn't count, so far, as a wrapping counter. It could read a
+/// counter that long interrupt handlers add their run time to, or that the
+/// code which enters stop mode adds the time spent there to.
+///
+/// Whenever the correction grows, this clock falls behind the wrapped clock by
+/// the same amount, so a deadline that is running at the time ends that much
+/// later. The clock never goes backwards: If the correction grows faster
+/// than the wrapped clock, the clock stands still.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::{
+///     cell::Cell,
+///     sync::atomic::{
+///         AtomicU32,
+///         Ordering,
+///     },
+/// };
+///
+/// use embedded_timeout_macros::{
+///     timer::compensate::Compensated,
+///     Clock,
+///     Deadline,
+/// };
+/// #
+/// # struct Counter<'r>(&'r Cell<u32>);
+/// #
+/// # impl Clock for Counter<'_> {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0.get() }
+/// # }
+///
+/// /// Incremented by a long interrupt handler, by the ticks it took to run
+/// static ISR_TICKS: AtomicU32 = AtomicU32::new(0);
+///
+/// let counter = Cell::new(0);
+/// let clock = Compensated::new(
+///     Counter(&counter),
+///     || ISR_TICKS.load(Ordering::Relaxed),
+/// );
+///
+/// let mut deadline = Deadline::start(clock, 1_000);
+///
+/// // The interrupt handler ran for 300 ticks, while 500 ticks passed.
+/// ISR_TICKS.fetch_add(300, Ordering::Relaxed);
+/// counter.set(500);
+///
+/// assert_eq!(deadline.elapsed(), 200);
+/// ```
+#[derive(Debug)]
+pub struct Compensated<C: Clock, F> {
+    clock:      C,
+    correction: F,
+    last_raw:   C::Ticks,
+    last_corr:  C::Ticks,
+    now:        C::Ticks,
+}
+
+impl<C, F> Compensated<C, F>
+    where
+        C: Clock,
+        F: FnMut() -> C::Ticks,
+{
+    /// Wraps a clock, leaving out the ticks reported by `correction`
+    ///
+    /// Only corrections that happen after this call are left out.
+    pub fn new(mut clock: C, mut correction: F) -> Self {
+        let last_raw  = clock.now();
+        let last_corr = correction();
+
+        Compensated {
+            clock,
+            correction,
+            last_raw,
+            last_corr,
+            now: last_raw,
+        }
+    }
+
+    /// Provides access to the wrapped clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+impl<C, F> Clock for Compensated<C, F>
+    where
+        C: Clock,
+        F: FnMut() -> C::Ticks,
+{
+    type Ticks = C::Ticks;
+
+    fn now(&mut self) -> Self::Ticks {
+        let raw  = self.clock.now();
+        let corr = (self.correction)();
+
+        let delta_raw  = raw.wrapping_sub(self.last_raw);
+        let delta_corr = corr.wrapping_sub(self.last_corr);
+        self.last_raw  = raw;
+        self.last_corr = corr;
+
+        self.now = self.now.wrapping_add(delta_raw.saturating_sub(delta_corr));
+        self.now
+    }
+}
@@ -0,0 +1,177 @@
+//! Correcting for clocks that run too fast or too slow
+//!
+//! RC oscillators are cheap, but their frequency depends on temperature and
+//! voltage. A timeout based on one can end up too short or too long, which
+//! matters for protocols with tight limits. If the drift can be measured at
+//! runtime, for example against a crystal that is only powered up now and
+//! then, [`DriftCorrected`] compensates for it.
+//!
+//! Like the rest of the [`timer`](crate::timer) module, this module is only
+//! available, if the `timer` feature is enabled.
+
+
+use crate::{
+    Clock,
+    Ticks,
+};
+
+
+/// A clock that scales the ticks of another clock by a correction factor
+///
+/// The factor is read from `correction` every time the clock is read, so it
+/// can be updated at runtime. To apply the same factor to several clocks,
+/// let them read it from the same place, like a `Cell`, or a pair of atomics
+/// that a calibration routine updates.
+///
+/// Only the ticks that pass after the factor has changed are scaled by the new
+/// factor. Fractions of a tick are carried over to the next reading, so no
+/// time gets lost to rounding.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::cell::Cell;
+///
+/// use embedded_timeout_macros::{
+///     timer::drift::{
+///         Correction,
+///         DriftCorrected,
+///     },
+///     Clock,
+///     Deadline,
+/// };
+/// #
+/// # struct Counter<'r>(&'r Cell<u32>);
+/// #
+/// # impl Clock for Counter<'_> {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0.get() }
+/// # }
+///
+/// let counter = Cell::new(0);
+///
+/// // Calibration found the RC oscillator to run 2% fast: It counted 10_200
+/// // ticks, while the crystal counted 10_000.
+/// let correction = Cell::new(Correction::new(10_000, 10_200));
+///
+/// let clock = DriftCorrected::new(Counter(&counter), || correction.get());
+/// let mut deadline = Deadline::start(clock, 1_000);
+///
+/// counter.set(1_020);
+/// assert_eq!(deadline.elapsed(), 1_000);
+/// ```
+#[derive(Debug)]
+pub struct DriftCorrected<C: Clock, F> {
+    clock:      C,
+    correction: F,
+    last:       C::Ticks,
+    now:        C::Ticks,
+    remainder:  u64,
+}
+
+impl<C, F> DriftCorrected<C, F>
+    where
+        C: Clock,
+        F: FnMut() -> Correction,
+{
+    /// Wraps a clock, scaling its ticks by the factor that `correction`
+    /// returns
+    pub fn new(mut clock: C, correction: F) -> Self {
+        let last = clock.now();
+
+        DriftCorrected {
+            clock,
+            correction,
+            last,
+            now:       last,
+            remainder: 0,
+        }
+    }
+
+    /// Provides access to the wrapped clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+impl<C, F> Clock for DriftCorrected<C, F>
+    where
+        C: Clock,
+        F: FnMut() -> Correction,
+{
+    type Ticks = C::Ticks;
+
+    fn now(&mut self) -> Self::Ticks {
+        let raw = self.clock.now();
+        let delta = raw.wrapping_sub(self.last).into_u64();
+        self.last = raw;
+
+        let Correction { reference, measured } = (self.correction)();
+
+        let scaled = (delta as u128 * reference as u128)
+            .saturating_add(self.remainder as u128);
+        let ticks = scaled / measured as u128;
+        self.remainder = (scaled % measured as u128) as u64;
+
+        let ticks = C::Ticks::saturating_from_u64(
+            ticks.min(u64::MAX as u128) as u64
+        );
+        self.now = self.now.wrapping_add(ticks);
+        self.now
+    }
+}
+
+
+/// A correction factor for [`DriftCorrected`]
+///
+/// Determined by counting the ticks of the drifting clock (`measured`) and of
+/// an accurate reference clock of the same nominal frequency (`reference`)
+/// over the same interval. Ticks of the drifting clock are then scaled by
+/// `reference / measured`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Correction {
+    reference: u32,
+    measured:  u32,
+}
+
+impl Correction {
+    /// No correction
+    pub const NONE: Self = Correction {
+        reference: 1,
+        measured:  1,
+    };
+
+    /// Creates a correction from a calibration measurement
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `measured` is zero.
+    pub const fn new(reference: u32, measured: u32) -> Self {
+        assert!(measured > 0, "Drift measurement must count at least one tick");
+
+        Correction {
+            reference,
+            measured,
+        }
+    }
+
+    /// Creates a correction for a clock that is off by the given ppm
+    ///
+    /// A positive value means that the clock runs fast, a negative one that
+    /// it runs slow.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `ppm` is -1_000_000 or less.
+    pub const fn from_ppm(ppm: i32) -> Self {
+        assert!(ppm > -1_000_000, "Clock can't be more than 100% slow");
+
+        Self::new(1_000_000, (1_000_000 + ppm) as u32)
+    }
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
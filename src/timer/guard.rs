@@ -0,0 +1,114 @@
+//! Finding unexpectedly slow code paths
+
+
+use crate::TimeoutTimer;
+
+
+/// Reports, if a region of code took longer than expected
+///
+/// Create a `TimeoutGuard` at the beginning of a suspicious region of code,
+/// with a timer (or a [`Deadline`]) that is running, and a label. If the guard
+/// is dropped after the timer expired, the overrun is reported.
+///
+/// By default, the guard panics in debug builds, and does nothing in release
+/// builds. Use [`TimeoutGuard::with_hook`] to report overruns in some other
+/// way, for example by logging them.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::timer::guard::TimeoutGuard;
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> { Ok(()) }
+/// # }
+/// #
+/// # let timer = Timer;
+///
+/// fn report_overrun(label: &'static str) {
+///     // Log the label, increment a counter, ...
+/// }
+///
+/// {
+///     let _guard =
+///         TimeoutGuard::with_hook(timer, "flash write", report_overrun);
+///
+///     // Do something that is expected to finish before the timer expires.
+/// }
+/// ```
+///
+/// [`Deadline`]: crate::Deadline
+pub struct TimeoutGuard<T: TimeoutTimer> {
+    timer: Option<T>,
+    label: &'static str,
+    hook:  Option<fn(&'static str)>,
+}
+
+impl<T> TimeoutGuard<T>
+    where T: TimeoutTimer
+{
+    /// Creates a guard that panics in debug builds, if dropped too late
+    ///
+    /// Please note that panicking while already panicking will abort the
+    /// program. If that is a concern, use [`TimeoutGuard::with_hook`] instead.
+    pub fn new(timer: T, label: &'static str) -> Self {
+        TimeoutGuard {
+            timer: Some(timer),
+            label,
+            hook:  None,
+        }
+    }
+
+    /// Creates a guard that calls `hook` with the label, if dropped too late
+    pub fn with_hook(timer: T, label: &'static str, hook: fn(&'static str))
+        -> Self
+    {
+        TimeoutGuard {
+            timer: Some(timer),
+            label,
+            hook:  Some(hook),
+        }
+    }
+
+    /// Returns the label of the guard
+    pub fn label(&self) -> &'static str {
+        self.label
+    }
+
+    /// Disarms the guard, returning the timer
+    ///
+    /// No overrun is reported, even if the timer has already expired.
+    pub fn disarm(mut self) -> T {
+        self.timer
+            .take()
+            .expect("Timer is only taken when disarming")
+    }
+}
+
+impl<T> Drop for TimeoutGuard<T>
+    where T: TimeoutTimer
+{
+    fn drop(&mut self) {
+        let timer = match &mut self.timer {
+            Some(timer) => timer,
+            None        => return,
+        };
+
+        if !timer.is_expired() {
+            return;
+        }
+
+        match self.hook {
+            Some(hook) =>
+                hook(self.label),
+            None =>
+                if cfg!(debug_assertions) {
+                    panic!("Timeout guard `{}` dropped too late", self.label);
+                },
+        }
+    }
+}
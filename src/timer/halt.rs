@@ -0,0 +1,138 @@
+//! Keeping debugger halts from causing timeouts
+//!
+//! While a debugger halts the core, hardware counters usually keep running.
+//! Once the core resumes, every deadline that was running seems to have
+//! expired, which makes stepping through driver code painful. [`HaltAware`]
+//! wraps a clock, and leaves out the time that the core has most likely
+//! spent halted.
+//!
+//! Like the rest of the [`timer`](crate::timer) module, this module is only
+//! available, if the `timer` feature is enabled.
+
+
+use crate::{
+    Clock,
+    Ticks,
+};
+
+
+/// A clock that pauses, while the core is halted by a debugger
+///
+/// A halt can't be observed while it's going on, as no code runs then. Instead,
+/// `HaltAware` watches for gaps between two readings of the clock that are
+/// longer than `max_gap` ticks. If `debugger_attached` returns `true` when
+/// such a gap is noticed, the gap is assumed to be a halt, and left out of the
+/// time that this clock reports. Without a debugger attached, the clock
+/// behaves like the one it wraps.
+///
+/// For this to work, the clock must be read more often than once per
+/// `max_gap` ticks, while waiting. The wait loops in this crate do that. On
+/// Cortex-M, `cortex_m_debugger_attached` from this module can be used as
+/// `debugger_attached`.
+///
+/// Interrupt handlers that run for longer than `max_gap`, while a debugger is
+/// attached, are mistaken for halts too. Choose `max_gap` accordingly.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::cell::Cell;
+///
+/// use embedded_timeout_macros::{
+///     timer::halt::HaltAware,
+///     Clock,
+///     Deadline,
+/// };
+/// #
+/// # struct Counter<'r>(&'r Cell<u32>);
+/// #
+/// # impl Clock for Counter<'_> {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0.get() }
+/// # }
+///
+/// let counter = Cell::new(0);
+/// let clock = HaltAware::new(Counter(&counter), 1_000, || true);
+///
+/// let mut deadline = Deadline::start(clock, 5_000);
+///
+/// counter.set(500);
+/// assert_eq!(deadline.elapsed(), 500);
+///
+/// // The core was halted for a while.
+/// counter.set(60_500);
+/// assert_eq!(deadline.elapsed(), 500);
+/// assert!(!deadline.is_expired());
+/// ```
+#[derive(Debug)]
+pub struct HaltAware<C: Clock, F> {
+    clock:             C,
+    max_gap:           C::Ticks,
+    debugger_attached: F,
+    last:              C::Ticks,
+    now:               C::Ticks,
+}
+
+impl<C, F> HaltAware<C, F>
+    where
+        C: Clock,
+        F: FnMut() -> bool,
+{
+    /// Wraps a clock
+    ///
+    /// Gaps between two readings that are longer than `max_gap` ticks are
+    /// left out, if `debugger_attached` returns `true`.
+    pub fn new(mut clock: C, max_gap: C::Ticks, debugger_attached: F) -> Self {
+        let last = clock.now();
+
+        HaltAware {
+            clock,
+            max_gap,
+            debugger_attached,
+            last,
+            now: last,
+        }
+    }
+
+    /// Provides access to the wrapped clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+impl<C, F> Clock for HaltAware<C, F>
+    where
+        C: Clock,
+        F: FnMut() -> bool,
+{
+    type Ticks = C::Ticks;
+
+    fn now(&mut self) -> Self::Ticks {
+        let raw = self.clock.now();
+        let delta = raw.wrapping_sub(self.last);
+        self.last = raw;
+
+        if delta <= self.max_gap || !(self.debugger_attached)() {
+            self.now = self.now.wrapping_add(delta);
+        }
+
+        self.now
+    }
+}
+
+
+/// Indicates whether a debugger is attached to a Cortex-M core
+///
+/// Reads the `C_DEBUGEN` bit of the Debug Halting Control and Status Register
+/// (DHCSR), which is set while a debugger has enabled halting debug. Only
+/// available on bare-metal ARM targets.
+#[cfg(all(target_arch = "arm", target_os = "none"))]
+pub fn cortex_m_debugger_attached() -> bool {
+    const DHCSR: *const u32 = 0xE000_EDF0 as *const u32;
+    const C_DEBUGEN: u32 = 1 << 0;
+
+    // The DHCSR is always present on Cortex-M, and reading it has no side
+    // effects.
+    let dhcsr = unsafe { DHCSR.read_volatile() };
+    dhcsr & C_DEBUGEN != 0
+}
@@ -0,0 +1,105 @@
+//! Waiting until an instant of a clock that isn't a [`Clock`]
+//!
+//! Some frameworks already provide a running clock, with their own types for
+//! instants and durations. RTIC, for example, has a `Monotonic` for every
+//! app, whose `now` returns a `fugit` instant. [`Until`] turns such a clock
+//! into a [`TimeoutTimer`], by comparing the current instant to a deadline,
+//! using whatever comparison the instant type provides. This avoids having to
+//! wrap the clock into a countdown timer, just to use it with the macros.
+//!
+//! Like the rest of the [`timer`](crate::timer) module, this module is only
+//! available, if the `timer` feature is enabled.
+//!
+//! [`Clock`]: crate::Clock
+//! [`TimeoutTimer`]: crate::TimeoutTimer
+
+
+use core::ops::Add;
+
+use crate::TimeoutTimer;
+
+
+/// A timer that expires once a clock reaches a deadline
+///
+/// `now` is a closure that returns the current instant, like
+/// `|| Mono::now()` in an RTIC app. The instant type must be ordered, and its
+/// ordering must handle wraparound, if the clock wraps. The `fugit` instants
+/// that RTIC uses do that.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::cell::Cell;
+///
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     timer::instant::Until,
+///     TimeoutError,
+/// };
+///
+/// // Stands in for the monotonic clock of a framework
+/// let ticks = Cell::new(0u64);
+/// let now = || { ticks.set(ticks.get() + 10); ticks.get() };
+///
+/// let mut timer = Until::after(now, 100u64);
+/// assert_eq!(*timer.deadline(), 110);
+///
+/// let result: Result<(), TimeoutError<()>> =
+///     block_timeout!(&mut timer, Err(nb::Error::WouldBlock));
+///
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// assert_eq!(ticks.get(), 110);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Until<F, I> {
+    now:      F,
+    deadline: I,
+}
+
+impl<F, I> Until<F, I>
+    where F: FnMut() -> I
+{
+    /// Creates a timer that expires at the given instant
+    pub fn new(now: F, deadline: I) -> Self {
+        Until {
+            now,
+            deadline,
+        }
+    }
+
+    /// Creates a timer that expires once the duration has passed
+    ///
+    /// The deadline is computed by adding the duration to the current
+    /// instant.
+    pub fn after<D>(mut now: F, duration: D) -> Self
+        where I: Add<D, Output = I>
+    {
+        let deadline = now() + duration;
+        Self::new(now, deadline)
+    }
+
+    /// Returns the deadline
+    pub fn deadline(&self) -> &I {
+        &self.deadline
+    }
+
+    /// Moves the deadline to a different instant
+    pub fn set_deadline(&mut self, deadline: I) {
+        self.deadline = deadline;
+    }
+
+    /// Returns the closure that reads the clock
+    pub fn free(self) -> F {
+        self.now
+    }
+}
+
+impl<F, I> TimeoutTimer for Until<F, I>
+    where
+        F: FnMut() -> I,
+        I: PartialOrd,
+{
+    fn is_expired(&mut self) -> bool {
+        (self.now)() >= self.deadline
+    }
+}
@@ -0,0 +1,137 @@
+//! Measuring the timing of periodic loops
+
+
+use crate::{
+    Clock,
+    Ticks,
+};
+
+
+/// Measures how much the iterations of a periodic loop deviate from its period
+///
+/// Control loops are supposed to run at a fixed period. Timeouts and retries
+/// within the loop can stretch individual iterations, which might go unnoticed
+/// otherwise. Call [`JitterMonitor::mark`] once per iteration, at the same
+/// point in the loop, and the monitor records how far the time since the
+/// previous call deviates from the nominal period, in either direction.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::timer::jitter::JitterMonitor;
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 }
+/// # }
+///
+/// let mut monitor = JitterMonitor::new(Clock(0), 100);
+///
+/// for now in [0, 100, 205, 300, 398] {
+///     monitor.clock().0 = now;
+///     monitor.mark();
+/// }
+///
+/// assert_eq!(monitor.count(), 4);
+/// assert_eq!(monitor.max(), 5);
+/// assert_eq!(monitor.mean(), 3);
+/// assert_eq!(monitor.last(), 2);
+/// ```
+#[derive(Debug)]
+pub struct JitterMonitor<C: Clock> {
+    clock:  C,
+    period: C::Ticks,
+    prev:   Option<C::Ticks>,
+    count:  u32,
+    max:    C::Ticks,
+    last:   C::Ticks,
+    total:  u64,
+}
+
+impl<C> JitterMonitor<C>
+    where C: Clock
+{
+    /// Creates a monitor for a loop with the given period, in ticks
+    pub fn new(clock: C, period: C::Ticks) -> Self {
+        JitterMonitor {
+            clock,
+            period,
+            prev:  None,
+            count: 0,
+            max:   C::Ticks::ZERO,
+            last:  C::Ticks::ZERO,
+            total: 0,
+        }
+    }
+
+    /// Marks the start of a loop iteration
+    ///
+    /// The first call only records the current time. Every later call records
+    /// the deviation of the time since the previous call from the period, and
+    /// returns it.
+    pub fn mark(&mut self) -> Option<C::Ticks> {
+        let now = self.clock.now();
+        let prev = self.prev.replace(now)?;
+
+        let actual = now.wrapping_sub(prev);
+        let deviation = if actual > self.period {
+            actual.wrapping_sub(self.period)
+        }
+        else {
+            self.period.wrapping_sub(actual)
+        };
+
+        self.count = self.count.saturating_add(1);
+        self.max   = self.max.max(deviation);
+        self.last  = deviation;
+        self.total = self.total.saturating_add(deviation.into_u64());
+
+        Some(deviation)
+    }
+
+    /// Returns the number of deviations recorded
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the largest deviation
+    pub fn max(&self) -> C::Ticks {
+        self.max
+    }
+
+    /// Returns the most recent deviation
+    pub fn last(&self) -> C::Ticks {
+        self.last
+    }
+
+    /// Returns the mean deviation, rounded down
+    ///
+    /// Returns zero, if nothing has been recorded yet.
+    pub fn mean(&self) -> C::Ticks {
+        if self.count == 0 {
+            return C::Ticks::ZERO;
+        }
+
+        C::Ticks::saturating_from_u64(self.total / self.count as u64)
+    }
+
+    /// Discards all recorded deviations
+    ///
+    /// The next call to [`JitterMonitor::mark`] only records the current time
+    /// again. Call this after the loop was paused on purpose, so the pause
+    /// isn't counted as jitter.
+    pub fn reset(&mut self) {
+        self.prev  = None;
+        self.count = 0;
+        self.max   = C::Ticks::ZERO;
+        self.last  = C::Ticks::ZERO;
+        self.total = 0;
+    }
+
+    /// Provides access to the clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
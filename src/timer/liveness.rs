@@ -0,0 +1,164 @@
+//! Software watchdog for superloop tasks
+
+
+use crate::{
+    Clock,
+    Ticks,
+};
+
+
+/// Monitors whether a number of tasks are still alive
+///
+/// Each task is identified by an index smaller than `N`. Once a task is being
+/// watched, it is expected to check in ([`LivenessMonitor::checkin`]) at least
+/// once per period. [`LivenessMonitor::poll`] reports all tasks that failed to
+/// do so.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::timer::liveness::LivenessMonitor;
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 }
+/// # }
+/// #
+/// # let mut clock = Clock(0);
+///
+/// const RADIO:  usize = 0;
+/// const SENSOR: usize = 1;
+///
+/// let mut monitor = LivenessMonitor::<_, 2>::new(&mut clock);
+/// monitor.watch(RADIO, 100);
+/// monitor.watch(SENSOR, 500);
+///
+/// // The radio task checks in regularly, the sensor task doesn't.
+/// monitor.clock().0 = 520;
+/// monitor.checkin(RADIO);
+/// monitor.clock().0 = 600;
+///
+/// let mut missed = monitor.poll();
+/// assert_eq!(missed.next(), Some(SENSOR));
+/// assert_eq!(missed.next(), None);
+/// # drop(missed);
+///
+/// // The sensor task's deadline has already passed, so it comes first.
+/// assert_eq!(monitor.next_deadline(), Some(500));
+/// ```
+#[derive(Debug)]
+pub struct LivenessMonitor<C: Clock, const N: usize> {
+    clock: C,
+    tasks: [Option<Task<C::Ticks>>; N],
+}
+
+impl<C, const N: usize> LivenessMonitor<C, N>
+    where C: Clock
+{
+    /// Creates a monitor that doesn't watch any tasks yet
+    pub fn new(clock: C) -> Self {
+        LivenessMonitor {
+            clock,
+            tasks: [None; N],
+        }
+    }
+
+    /// Starts watching a task
+    ///
+    /// The task is expected to check in at least once every `period` ticks,
+    /// starting now. If the task is already being watched, its period is
+    /// updated, and it is considered to have checked in.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `id` is not smaller than `N`.
+    pub fn watch(&mut self, id: usize, period: C::Ticks) {
+        let last = self.clock.now();
+        self.tasks[id] = Some(Task { period, last });
+    }
+
+    /// Stops watching a task
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `id` is not smaller than `N`.
+    pub fn unwatch(&mut self, id: usize) {
+        self.tasks[id] = None;
+    }
+
+    /// Signals that a task is still alive
+    ///
+    /// Does nothing, if the task isn't being watched.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `id` is not smaller than `N`.
+    pub fn checkin(&mut self, id: usize) {
+        let now = self.clock.now();
+
+        if let Some(task) = &mut self.tasks[id] {
+            task.last = now;
+        }
+    }
+
+    /// Returns the ids of all tasks that have missed their deadline
+    ///
+    /// A task that has missed its deadline keeps being reported, until it
+    /// checks in again.
+    pub fn poll(&mut self) -> impl Iterator<Item = usize> + '_ {
+        let now = self.clock.now();
+
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter_map(move |(id, task)| {
+                let task = task.as_ref()?;
+                if now.wrapping_sub(task.last) > task.period {
+                    Some(id)
+                }
+                else {
+                    None
+                }
+            })
+    }
+
+    /// Returns the value of the clock at which the next task will miss its
+    /// deadline, unless it checks in before that
+    ///
+    /// Returns `None`, if no task is being watched. Low-power applications can
+    /// use this to program a wakeup, instead of polling periodically.
+    ///
+    /// This reads the clock, to find out which deadline comes first, which is
+    /// why it requires mutable access.
+    pub fn next_deadline(&mut self) -> Option<C::Ticks> {
+        let now = self.clock.now();
+
+        self.tasks
+            .iter()
+            .flatten()
+            .map(|task| task.last.wrapping_add(task.period))
+            .min_by_key(|&deadline| {
+                // Deadlines that have already passed come first.
+                if now.wrapping_sub(deadline) < C::Ticks::HALF {
+                    C::Ticks::ZERO
+                }
+                else {
+                    deadline.wrapping_sub(now)
+                }
+            })
+    }
+
+    /// Provides access to the clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+
+#[derive(Clone, Copy, Debug)]
+struct Task<T> {
+    period: T,
+    last:   T,
+}
@@ -0,0 +1,56 @@
+//! Clocks, timers, and the things built on top of them
+//!
+//! The macros only need something that implements [`TimeoutTimer`]. This
+//! module provides implementations for hardware counters and other sources of
+//! time, as well as types that manage many timeouts at once.
+//!
+//! This module is only available, if the `timer` feature is enabled (which it
+//! is by default). The most commonly used types are also re-exported from the
+//! crate root.
+//!
+//! [`TimeoutTimer`]: crate::TimeoutTimer
+
+
+pub mod bitbang;
+pub mod compensate;
+pub mod drift;
+pub mod guard;
+pub mod halt;
+pub mod instant;
+pub mod jitter;
+pub mod liveness;
+pub mod pace;
+pub mod rate;
+pub mod watchdog;
+pub mod wheel;
+
+#[cfg(feature = "nrf-rtc")]
+pub mod nrf_rtc;
+
+#[cfg(feature = "rp2040")]
+pub mod rp2040;
+
+mod alarm;
+mod clocks;
+mod yielding;
+
+// Requires a newer version of Rust than the rest of the crate.
+#[cfg(feature = "soft-delay")]
+#[allow(clippy::incompatible_msrv)]
+mod soft_delay;
+
+
+#[cfg(feature = "soft-delay")]
+pub use self::soft_delay::SoftDelay;
+
+#[cfg(feature = "std")]
+pub use self::clocks::StdClock;
+
+pub use self::{
+    alarm::Alarm,
+    clocks::{
+        Counter64Clock,
+        CounterClock,
+    },
+    yielding::Yielding,
+};
@@ -0,0 +1,229 @@
+//! A low-power clock based on the RTC peripheral of the nRF52 family
+//!
+//! The RTC peripherals of the nRF52 are clocked by the 32.768 kHz low-frequency
+//! clock, which keeps running in the low-power modes, where the high-frequency
+//! timers are stopped. [`NrfRtc`] uses one of them as a clock, so deadlines
+//! keep running while the core sleeps in between polls (for example with the
+//! `idle-wfi` feature).
+//!
+//! The registers are accessed directly, so this module doesn't depend on any
+//! particular HAL. The low-frequency clock must have been started by the
+//! application, using the `CLOCK` peripheral.
+//!
+//! This module is only available, if the `nrf-rtc` feature is enabled.
+
+
+use core::{
+    convert::TryFrom,
+    time::Duration,
+};
+
+use crate::{
+    Clock,
+    Deadline,
+    FromDuration,
+};
+
+
+const TASKS_START: usize = 0x000;
+const TASKS_STOP:  usize = 0x004;
+const TASKS_CLEAR: usize = 0x008;
+const COUNTER:     usize = 0x504;
+const PRESCALER:   usize = 0x508;
+
+const LFCLK_HZ:      u64 = 32_768;
+const COUNTER_BITS:  u32 = 24;
+const MAX_PRESCALER: u16 = 0xfff;
+
+
+/// Identifies one of the RTC peripherals
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RtcInstance {
+    /// `RTC0`, which is used by the SoftDevice, if one is present
+    Rtc0,
+
+    /// `RTC1`
+    Rtc1,
+
+    /// `RTC2`, which isn't available on all chips of the family
+    Rtc2,
+}
+
+impl RtcInstance {
+    fn base(&self) -> usize {
+        match self {
+            RtcInstance::Rtc0 => 0x4000_b000,
+            RtcInstance::Rtc1 => 0x4001_1000,
+            RtcInstance::Rtc2 => 0x4002_4000,
+        }
+    }
+}
+
+
+/// A clock based on an RTC peripheral of the nRF52
+///
+/// The counter of the RTC runs at 32.768 kHz, divided by the prescaler plus
+/// one, and is 24 bits wide. `NrfRtc` extends it to 64 bits, which requires
+/// it to be read at least once per wrap-around of the counter. Without a
+/// prescaler, that's every 512 seconds.
+///
+/// The frequency is not an integer for most values of the prescaler, so
+/// conversions into ticks are done exactly, rounding up.
+///
+/// # Example
+///
+/// ``` rust no_run
+/// use embedded_timeout_macros::{
+///     timer::nrf_rtc::{
+///         NrfRtc,
+///         RtcInstance,
+///     },
+///     Deadline,
+/// };
+///
+/// // A prescaler of 31 results in ticks of about 1 ms, and a counter that
+/// // wraps around every 4.5 hours.
+/// let rtc = unsafe { NrfRtc::new(RtcInstance::Rtc1, 31) };
+/// let timeout = rtc.millis(500).unwrap();
+/// assert_eq!(timeout, 512);
+///
+/// let mut deadline = Deadline::start(rtc, timeout);
+/// # let _ = deadline.is_expired();
+/// ```
+#[derive(Debug)]
+pub struct NrfRtc {
+    base:      usize,
+    prescaler: u16,
+    last:      u32,
+    ticks:     u64,
+}
+
+impl NrfRtc {
+    /// Sets up and starts an RTC peripheral
+    ///
+    /// Restarts the counter from zero, with the given prescaler. Events,
+    /// interrupts, and compare registers are left alone.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may use the peripheral while this instance exists,
+    /// including the HAL, and other instances of this type.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `prescaler` is larger than 4095.
+    pub unsafe fn new(instance: RtcInstance, prescaler: u16) -> Self {
+        assert!(
+            prescaler <= MAX_PRESCALER,
+            "The prescaler of the RTC is only 12 bits wide"
+        );
+
+        let base = instance.base();
+
+        // The prescaler can only be written while the RTC is stopped.
+        write(base + TASKS_STOP, 1);
+        write(base + PRESCALER, prescaler as u32);
+        write(base + TASKS_CLEAR, 1);
+        write(base + TASKS_START, 1);
+
+        NrfRtc {
+            base,
+            prescaler,
+            last:  0,
+            ticks: 0,
+        }
+    }
+
+    /// Returns the prescaler that results in the tick duration closest to the
+    /// given one, in microseconds
+    ///
+    /// Saturates at the largest prescaler. Can be used in constants.
+    pub const fn prescaler_for_tick_us(us: u32) -> u16 {
+        let prescaler = (us as u64 * LFCLK_HZ + 500_000) / 1_000_000;
+        let prescaler = if prescaler == 0 { 0 } else { prescaler - 1 };
+
+        if prescaler > MAX_PRESCALER as u64 {
+            MAX_PRESCALER
+        }
+        else {
+            prescaler as u16
+        }
+    }
+
+    /// Returns the prescaler
+    pub fn prescaler(&self) -> u16 {
+        self.prescaler
+    }
+
+    /// Converts milliseconds into ticks of this clock, rounding up
+    ///
+    /// Returns `None`, if the result doesn't fit into 64 bits.
+    pub fn millis(&self, ms: u32) -> Option<u64> {
+        self.convert_duration(Duration::from_millis(ms as u64))
+    }
+
+    /// Converts microseconds into ticks of this clock, rounding up
+    ///
+    /// Returns `None`, if the result doesn't fit into 64 bits.
+    pub fn micros(&self, us: u32) -> Option<u64> {
+        self.convert_duration(Duration::from_micros(us as u64))
+    }
+
+    /// Converts a `Duration` into ticks of this clock, rounding up
+    ///
+    /// Returns `None`, if the result doesn't fit into 64 bits.
+    pub fn convert_duration(&self, duration: Duration) -> Option<u64> {
+        let divisor = (self.prescaler as u128 + 1) * 1_000_000_000;
+        let ticks = duration.as_nanos() * LFCLK_HZ as u128;
+        u64::try_from(ticks / divisor + (ticks % divisor != 0) as u128).ok()
+    }
+
+    /// Converts ticks of this clock into a `Duration`, rounding down
+    pub fn to_duration(&self, ticks: u64) -> Duration {
+        let nanos = ticks as u128 * (self.prescaler as u128 + 1)
+            * 1_000_000_000
+            / LFCLK_HZ as u128;
+        Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        )
+    }
+
+    /// Stops the RTC, and releases the peripheral
+    pub fn stop(self) {
+        write(self.base + TASKS_STOP, 1);
+    }
+}
+
+impl Clock for NrfRtc {
+    type Ticks = u64;
+
+    fn now(&mut self) -> Self::Ticks {
+        let mask = (1 << COUNTER_BITS) - 1;
+
+        let raw = read(self.base + COUNTER) & mask;
+        let delta = raw.wrapping_sub(self.last) & mask;
+
+        self.last = raw;
+        self.ticks = self.ticks.wrapping_add(delta as u64);
+
+        self.ticks
+    }
+}
+
+impl FromDuration for Deadline<NrfRtc> {
+    fn convert_duration(&self, duration: Duration) -> Option<Self::Duration> {
+        self.clock_ref().convert_duration(duration)
+    }
+}
+
+
+fn read(address: usize) -> u32 {
+    // Reading `COUNTER` doesn't have side effects.
+    unsafe { (address as *const u32).read_volatile() }
+}
+
+fn write(address: usize, value: u32) {
+    // Only the registers of the peripheral owned by this module are written.
+    unsafe { (address as *mut u32).write_volatile(value) }
+}
@@ -0,0 +1,415 @@
+//! Pacing of repeated attempts, and strategies for waiting between polls
+//!
+//! By default, the macros in this crate evaluate the operation as often as
+//! they can. This is the most responsive option, but it can be wasteful, for
+//! example when polling an I2C device, which would then be addressed thousands
+//! of times per second. The types in this module limit how often an operation
+//! is attempted.
+//!
+//! [`Pace`] is used by [`repeat_timeout`] to space out complete attempts.
+//! [`PollStrategy`] decides what happens between two polls of an operation
+//! that isn't finished yet, and can be used with all macros and functions that
+//! take a timer, by wrapping the timer in [`WithStrategy`].
+//!
+//! Like the rest of the [`timer`](crate::timer) module, this module is only
+//! available, if the `timer` feature is enabled.
+//!
+//! [`repeat_timeout`]: crate::repeat_timeout
+
+
+use crate::{
+    bound::PollBound,
+    Delay,
+    Elapsed,
+    StartTimer,
+    TimeoutTimer,
+};
+
+
+/// Waits between two attempts of an operation
+///
+/// Used by [`repeat_timeout`] to limit how often the operation is attempted.
+///
+/// [`repeat_timeout`]: crate::repeat_timeout
+pub trait Pace {
+    /// Waits until the next attempt is due
+    ///
+    /// `timer` is the timer that limits the total duration. Implementations
+    /// that can stop waiting early should return `false`, once it has expired.
+    /// Otherwise, `true` is returned.
+    fn pace<T>(&mut self, timer: &mut T) -> bool
+        where T: TimeoutTimer + ?Sized;
+}
+
+impl<P> Pace for &mut P
+    where P: Pace + ?Sized
+{
+    fn pace<T>(&mut self, timer: &mut T) -> bool
+        where T: TimeoutTimer + ?Sized
+    {
+        (**self).pace(timer)
+    }
+}
+
+
+/// Paces attempts using a periodic timer
+///
+/// Waits until the periodic timer expires before each attempt, so the
+/// operation is attempted at most once per period. The timer is expected to
+/// restart itself when it expires, like periodic `CountDown` timers do.
+///
+/// While waiting, the timer that limits the total duration is still checked,
+/// so the total duration is never exceeded by much.
+#[derive(Debug)]
+pub struct Periodic<T>(pub T);
+
+impl<P> Pace for Periodic<P>
+    where P: TimeoutTimer
+{
+    fn pace<T>(&mut self, timer: &mut T) -> bool
+        where T: TimeoutTimer + ?Sized
+    {
+        let mut bound = PollBound::new();
+
+        while !self.0.is_expired() {
+            if timer.is_expired() || bound.exhausted() {
+                return false;
+            }
+
+            timer.idle();
+        }
+
+        true
+    }
+}
+
+
+/// Paces attempts using a delay provider
+///
+/// Waits for a fixed number of microseconds between attempts. Unlike
+/// [`Periodic`], this doesn't take the time required by the attempt itself into
+/// account, and the timer that limits the total duration is not checked while
+/// waiting.
+#[derive(Debug)]
+pub struct Delayed<D> {
+    delay: D,
+    us:    u32,
+}
+
+impl<D> Delayed<D>
+    where D: Delay
+{
+    /// Creates a pacer that waits for `us` microseconds between attempts
+    pub fn new(delay: D, us: u32) -> Self {
+        Delayed {
+            delay,
+            us,
+        }
+    }
+
+    /// Returns the wrapped delay provider
+    pub fn free(self) -> D {
+        self.delay
+    }
+}
+
+impl<D> Pace for Delayed<D>
+    where D: Delay
+{
+    fn pace<T>(&mut self, _: &mut T) -> bool
+        where T: TimeoutTimer + ?Sized
+    {
+        self.delay.delay_us(self.us);
+        true
+    }
+}
+
+
+/// Decides what to do between two polls of an operation
+///
+/// This is a reusable policy for how to wait, that can be passed around and
+/// tested independently of the operation being waited for. Use it by wrapping
+/// a timer in [`WithStrategy`].
+///
+/// The following strategies are available:
+///
+/// - [`BusySpin`]: Don't wait at all
+/// - [`SpinThenDelay`]: Don't wait for a number of polls, then start delaying
+/// - [`DelayOnly`]: Always delay
+/// - [`WaitForInterrupt`]: Sleep until the next interrupt
+pub trait PollStrategy {
+    /// Called after a poll, if the operation isn't finished yet
+    ///
+    /// `attempt` is the number of polls so far, starting at 1.
+    fn idle(&mut self, attempt: u32);
+}
+
+impl<S> PollStrategy for &mut S
+    where S: PollStrategy + ?Sized
+{
+    fn idle(&mut self, attempt: u32) {
+        (**self).idle(attempt)
+    }
+}
+
+
+/// Polls as fast as possible
+///
+/// This is the default behavior of all macros, and gives the lowest latency.
+#[derive(Clone, Copy, Debug)]
+pub struct BusySpin;
+
+impl PollStrategy for BusySpin {
+    fn idle(&mut self, _: u32) {}
+}
+
+
+/// Polls as fast as possible at first, then starts delaying between polls
+///
+/// Operations that usually finish quickly are handled with low latency, while
+/// operations that take long don't keep the CPU or bus busy.
+#[derive(Debug)]
+pub struct SpinThenDelay<D> {
+    spins: u32,
+    delay: D,
+    us:    u32,
+}
+
+impl<D> SpinThenDelay<D>
+    where D: Delay
+{
+    /// Creates the strategy
+    ///
+    /// The first `spins` polls happen without delay. After that, the strategy
+    /// waits for `us` microseconds between polls.
+    pub fn new(spins: u32, delay: D, us: u32) -> Self {
+        SpinThenDelay {
+            spins,
+            delay,
+            us,
+        }
+    }
+}
+
+impl<D> PollStrategy for SpinThenDelay<D>
+    where D: Delay
+{
+    fn idle(&mut self, attempt: u32) {
+        if attempt > self.spins {
+            self.delay.delay_us(self.us);
+        }
+    }
+}
+
+
+/// Waits for a fixed number of microseconds between polls
+#[derive(Debug)]
+pub struct DelayOnly<D> {
+    delay: D,
+    us:    u32,
+}
+
+impl<D> DelayOnly<D>
+    where D: Delay
+{
+    /// Creates the strategy
+    pub fn new(delay: D, us: u32) -> Self {
+        DelayOnly {
+            delay,
+            us,
+        }
+    }
+}
+
+impl<D> PollStrategy for DelayOnly<D>
+    where D: Delay
+{
+    fn idle(&mut self, _: u32) {
+        self.delay.delay_us(self.us);
+    }
+}
+
+
+/// Sleeps until the next interrupt between polls
+///
+/// Wraps the function that puts the CPU to sleep, like `cortex_m::asm::wfi`
+/// or `riscv::asm::wfi`. This only makes sense, if whatever is being waited
+/// for, as well as the timer, generate interrupts. Otherwise the CPU might
+/// sleep indefinitely.
+///
+/// The function is just called between polls, so it can also be used for
+/// other ways of waiting, like yielding to an RTOS. See [`Yielding`].
+///
+/// [`Yielding`]: crate::Yielding
+#[derive(Clone, Copy, Debug)]
+pub struct WaitForInterrupt<F>(pub F);
+
+impl<F> PollStrategy for WaitForInterrupt<F>
+    where F: FnMut()
+{
+    fn idle(&mut self, _: u32) {
+        (self.0)()
+    }
+}
+
+
+/// Wraps a timer, applying a [`PollStrategy`] while waiting
+///
+/// Every time the wrapped timer is checked and hasn't expired yet, the strategy
+/// decides how to wait. Since the macros check the timer once per iteration,
+/// this means the strategy is applied between polls of the operation.
+///
+/// The strategy isn't applied before the first poll, so an operation that
+/// finishes right away isn't delayed. It replaces the crate-wide default that
+/// is selected using cargo features (see [`TimeoutTimer::idle`]), so
+/// individual call sites can use a different strategy than the rest of the
+/// application. To just opt out of the default, use [`BusySpin`].
+///
+/// The number of polls passed to the strategy starts over, whenever the timer
+/// is started through [`StartTimer`], after it has expired, or when
+/// [`WithStrategy::reset`] is called. Otherwise, it carries over from one wait
+/// to the next. If the same timer is used for several waits without restarting
+/// it, call [`WithStrategy::reset`] before each of them, so every wait starts
+/// with the full number of spins, for example.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     timer::pace::{
+///         SpinThenDelay,
+///         WithStrategy,
+///     },
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         self.0 -= 1;
+/// #         if self.0 == 0 { Ok(()) } else { Err(nb::Error::WouldBlock) }
+/// #     }
+/// # }
+/// #
+/// # struct Delay<'r>(&'r core::cell::Cell<u32>);
+/// #
+/// # impl embedded_hal::blocking::delay::DelayUs<u32> for Delay<'_> {
+/// #     fn delay_us(&mut self, us: u32) { self.0.set(self.0.get() + us); }
+/// # }
+/// #
+/// # let delayed = core::cell::Cell::new(0);
+/// # let delay = Delay(&delayed);
+/// # let timer = Timer(10);
+///
+/// let mut timer = WithStrategy::new(
+///     timer,
+///     SpinThenDelay::new(5, delay, 100),
+/// );
+///
+/// let result: Result<(), TimeoutError<()>> = block_timeout!(
+///     &mut timer,
+///     Err(nb::Error::WouldBlock)
+/// );
+///
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+///
+/// // 9 polls with 8 gaps between them. The first 5 gaps were spun through,
+/// // the rest was delayed.
+/// assert_eq!(delayed.get(), 300);
+/// ```
+#[derive(Debug)]
+pub struct WithStrategy<T, S> {
+    timer:    T,
+    strategy: S,
+    attempt:  u32,
+}
+
+impl<T, S> WithStrategy<T, S>
+    where
+        T: TimeoutTimer,
+        S: PollStrategy,
+{
+    /// Wraps the timer
+    pub fn new(timer: T, strategy: S) -> Self {
+        WithStrategy {
+            timer,
+            strategy,
+            attempt: 0,
+        }
+    }
+
+    /// Starts counting polls over, without restarting the timer
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    /// Provides access to the wrapped timer
+    pub fn inner(&mut self) -> &mut T {
+        &mut self.timer
+    }
+
+    /// Provides access to the strategy
+    pub fn strategy(&mut self) -> &mut S {
+        &mut self.strategy
+    }
+
+    /// Returns the wrapped timer and the strategy
+    pub fn free(self) -> (T, S) {
+        (self.timer, self.strategy)
+    }
+}
+
+impl<T, S> TimeoutTimer for WithStrategy<T, S>
+    where
+        T: TimeoutTimer,
+        S: PollStrategy,
+{
+    fn is_expired(&mut self) -> bool {
+        if self.timer.is_expired() {
+            self.attempt = 0;
+            return true;
+        }
+
+        if self.attempt > 0 {
+            self.strategy.idle(self.attempt);
+        }
+        self.attempt = self.attempt.saturating_add(1);
+
+        false
+    }
+
+    fn idle(&mut self) {
+        // The strategy is already applied in `is_expired`, which also works
+        // for wait loops that don't call this method.
+    }
+}
+
+impl<T, S> StartTimer for WithStrategy<T, S>
+    where
+        T: StartTimer,
+        S: PollStrategy,
+{
+    type Duration = T::Duration;
+    type Error    = T::Error;
+
+    fn try_start(&mut self, duration: Self::Duration)
+        -> Result<(), Self::Error>
+    {
+        self.attempt = 0;
+        self.timer.try_start(duration)
+    }
+}
+
+impl<T, S> Elapsed for WithStrategy<T, S>
+    where T: Elapsed
+{
+    type Ticks = T::Ticks;
+
+    fn elapsed_ticks(&mut self) -> Self::Ticks {
+        self.timer.elapsed_ticks()
+    }
+}
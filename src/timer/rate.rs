@@ -0,0 +1,144 @@
+//! Measuring the throughput of operations
+
+
+use crate::TimeoutTimer;
+
+
+/// Measures how many operations complete per time window
+///
+/// Timeouts only tell you that an operation didn't complete at all. If a link
+/// degrades, operations might still complete, after enough retries, while the
+/// throughput collapses. `RateMeter` can be used to detect that.
+///
+/// Call [`RateMeter::record`] whenever an operation completes, and
+/// [`RateMeter::poll`] regularly. The timer is expected to be periodic, meaning
+/// it expires once per time window and restarts automatically, like a periodic
+/// `CountDown` timer. The meter keeps the counts of the last `W` windows, and
+/// reports their mean as the rolling rate.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::timer::rate::RateMeter;
+/// #
+/// # struct Timer(bool);
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         if !self.0 { return Err(nb::Error::WouldBlock) }
+/// #         self.0 = false;
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # let timer = Timer(false);
+///
+/// let mut meter = RateMeter::<_, 4>::new(timer);
+///
+/// for _ in 0 .. 10 {
+///     meter.record();
+/// }
+/// # meter.timer().0 = true;
+///
+/// // The timer expires, and the window is complete.
+/// assert!(meter.poll());
+/// assert_eq!(meter.last(), Some(10));
+/// assert_eq!(meter.rate(), Some(10));
+/// ```
+#[derive(Debug)]
+pub struct RateMeter<T, const W: usize> {
+    timer:   T,
+    current: u32,
+    windows: [u32; W],
+    next:    usize,
+    filled:  usize,
+}
+
+impl<T, const W: usize> RateMeter<T, W>
+    where T: TimeoutTimer
+{
+    /// Creates a rate meter
+    ///
+    /// The timer should already be running, and must be periodic.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `W` is zero.
+    pub fn new(timer: T) -> Self {
+        assert!(W > 0, "Rate meter must keep at least one window");
+
+        RateMeter {
+            timer,
+            current: 0,
+            windows: [0; W],
+            next:    0,
+            filled:  0,
+        }
+    }
+
+    /// Records one completed operation
+    pub fn record(&mut self) {
+        self.record_n(1);
+    }
+
+    /// Records a number of completed operations
+    pub fn record_n(&mut self, n: u32) {
+        self.current = self.current.saturating_add(n);
+    }
+
+    /// Checks the timer, completing the current window, if it expired
+    ///
+    /// Returns whether a window was completed.
+    pub fn poll(&mut self) -> bool {
+        if !self.timer.is_expired() {
+            return false;
+        }
+
+        self.windows[self.next] = self.current;
+        self.current = 0;
+        self.next = (self.next + 1) % W;
+        self.filled = (self.filled + 1).min(W);
+
+        true
+    }
+
+    /// Returns the number of operations in the last completed window
+    ///
+    /// Returns `None`, if no window has been completed yet.
+    pub fn last(&self) -> Option<u32> {
+        if self.filled == 0 {
+            return None;
+        }
+
+        Some(self.windows[(self.next + W - 1) % W])
+    }
+
+    /// Returns the mean number of operations per window
+    ///
+    /// The mean is computed over the last `W` completed windows, or fewer, if
+    /// fewer have been completed. Returns `None`, if no window has been
+    /// completed yet.
+    pub fn rate(&self) -> Option<u32> {
+        if self.filled == 0 {
+            return None;
+        }
+
+        // Divide first, to avoid 64-bit arithmetic on small targets. The
+        // result is still exact, as the remainders are summed up too.
+        let filled = self.filled as u32;
+        let (quotients, remainders) = self.windows
+            .iter()
+            .fold((0u32, 0u32), |(q, r), &count| {
+                (q.saturating_add(count / filled), r + count % filled)
+            });
+
+        Some(quotients.saturating_add(remainders / filled))
+    }
+
+    /// Provides access to the timer
+    pub fn timer(&mut self) -> &mut T {
+        &mut self.timer
+    }
+}
@@ -0,0 +1,242 @@
+//! Clocks and alarms based on the `TIMER` peripheral of the RP2040
+//!
+//! The `TIMER` of the RP2040 is a 64-bit counter that counts microseconds,
+//! along with four alarms that compare against its lower 32 bits.
+//! [`Rp2040Clock`] reads the counter, and can be used with [`Deadline`] and
+//! the other clock-based types. [`Rp2040Alarm`] uses one of the hardware
+//! alarms as a timer. If its interrupt is enabled, it wakes up the core, so it
+//! works well with the `idle-wfi` feature.
+//!
+//! The registers are accessed directly, so this module doesn't depend on any
+//! particular HAL. The `TIMER` must have been taken out of reset, and its tick
+//! generator started, which the HALs do during clock initialization. By
+//! default, the counter stops while a debugger halts either core, so debugger
+//! halts don't cause timeouts.
+//!
+//! This module is only available, if the `rp2040` feature is enabled.
+//!
+//! [`Deadline`]: crate::Deadline
+
+
+use core::{
+    convert::Infallible,
+    time::Duration,
+};
+
+use crate::{
+    clock::ticks_from_duration,
+    Clock,
+    Deadline,
+    FromDuration,
+    StartTimer,
+    TimeoutTimer,
+};
+
+
+const TIMER:    usize = 0x4005_4000;
+const ALARM0:   usize = TIMER + 0x10;
+const ARMED:    usize = TIMER + 0x20;
+const TIMERAWH: usize = TIMER + 0x24;
+const TIMERAWL: usize = TIMER + 0x28;
+
+
+/// A clock that reads the 64-bit microsecond counter of the RP2040
+///
+/// Reading the counter doesn't interfere with anything else that uses it, so
+/// any number of instances can exist.
+///
+/// # Example
+///
+/// ``` rust no_run
+/// use embedded_timeout_macros::{
+///     timer::rp2040::Rp2040Clock,
+///     Deadline,
+/// };
+///
+/// let clock = Rp2040Clock::new();
+/// let timeout = clock.millis(100);
+///
+/// let mut deadline = Deadline::start(clock, timeout);
+/// # let _ = deadline.is_expired();
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rp2040Clock;
+
+impl Rp2040Clock {
+    /// Creates an instance
+    pub fn new() -> Self {
+        Rp2040Clock
+    }
+
+    /// Converts milliseconds into ticks of this clock
+    pub fn millis(&self, ms: u32) -> u64 {
+        ms as u64 * 1_000
+    }
+
+    /// Converts microseconds into ticks of this clock
+    pub fn micros(&self, us: u32) -> u64 {
+        us as u64
+    }
+
+    /// Converts a `Duration` into ticks of this clock, rounding up
+    pub fn convert_duration(&self, duration: Duration) -> Option<u64> {
+        ticks_from_duration(duration, 1_000_000)
+    }
+}
+
+impl Clock for Rp2040Clock {
+    type Ticks = u64;
+
+    fn now(&mut self) -> Self::Ticks {
+        // The raw registers don't latch, so the high word is read again, to
+        // detect a carry out of the low word in the meantime.
+        loop {
+            let high = read(TIMERAWH);
+            let low = read(TIMERAWL);
+
+            if read(TIMERAWH) == high {
+                return (high as u64) << 32 | low as u64;
+            }
+        }
+    }
+}
+
+impl FromDuration for Deadline<Rp2040Clock> {
+    fn convert_duration(&self, duration: Duration) -> Option<Self::Duration> {
+        self.clock_ref().convert_duration(duration)
+    }
+}
+
+
+/// A timer that uses one of the four hardware alarms of the RP2040
+///
+/// Durations are in microseconds. Since the alarms only compare against the
+/// lower 32 bits of the counter, the longest duration is about 35 minutes
+/// (half of the range of 32 bits, to compare in a wrap-safe way). An alarm
+/// that hasn't been started never expires.
+///
+/// Only the `ALARMx` and `ARMED` registers are accessed. Enabling the
+/// interrupt of the alarm, and handling it, is left to the application.
+///
+/// # Example
+///
+/// ``` rust no_run
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     timer::rp2040::Rp2040Alarm,
+///     StartTimeoutError,
+/// };
+///
+/// // Not used by anything else in this application
+/// let mut alarm = unsafe { Rp2040Alarm::new(2) };
+///
+/// let result: Result<(), StartTimeoutError<_, ()>> = block_timeout!(
+///     &mut alarm,
+///     10_000,
+///     Err(nb::Error::WouldBlock)
+/// );
+/// ```
+#[derive(Debug)]
+pub struct Rp2040Alarm {
+    index:  u8,
+    target: Option<u32>,
+}
+
+impl Rp2040Alarm {
+    /// Creates a timer that uses the alarm with the given index
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may use the alarm while this instance exists, including
+    /// the HAL, and other instances of this type.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `index` is larger than 3.
+    pub unsafe fn new(index: u8) -> Self {
+        assert!(index < 4, "The RP2040 only has alarms 0 to 3");
+
+        Rp2040Alarm {
+            index,
+            target: None,
+        }
+    }
+
+    /// Returns the index of the alarm
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Disarms the alarm, so it never expires
+    pub fn cancel(&mut self) {
+        self.disarm();
+        self.target = None;
+    }
+
+    fn mask(&self) -> u32 {
+        1 << self.index
+    }
+
+    fn disarm(&mut self) {
+        // Writing a 1 to a bit of `ARMED` disarms the alarm.
+        write(ARMED, self.mask());
+    }
+}
+
+impl TimeoutTimer for Rp2040Alarm {
+    fn is_expired(&mut self) -> bool {
+        let target = match self.target {
+            Some(target) => target,
+            None         => return false,
+        };
+
+        if read(ARMED) & self.mask() == 0 {
+            return true;
+        }
+
+        // The hardware only fires, if the counter matches exactly. If the
+        // target has already passed when it was armed, that takes another
+        // trip around the 32-bit range, so check in software too.
+        if read(TIMERAWL).wrapping_sub(target) < 1 << 31 {
+            self.disarm();
+            return true;
+        }
+
+        false
+    }
+}
+
+impl StartTimer for Rp2040Alarm {
+    type Duration = u32;
+    type Error    = Infallible;
+
+    fn try_start(&mut self, duration: Self::Duration)
+        -> Result<(), Self::Error>
+    {
+        let target = read(TIMERAWL).wrapping_add(duration);
+
+        // Writing the target arms the alarm.
+        write(ALARM0 + self.index as usize * 4, target);
+        self.target = Some(target);
+
+        Ok(())
+    }
+}
+
+impl Drop for Rp2040Alarm {
+    fn drop(&mut self) {
+        self.disarm();
+    }
+}
+
+
+fn read(address: usize) -> u32 {
+    // All registers used in this module can be read at any time, without
+    // side effects.
+    unsafe { (address as *const u32).read_volatile() }
+}
+
+fn write(address: usize, value: u32) {
+    // Only the registers of alarms owned by this module are written.
+    unsafe { (address as *mut u32).write_volatile(value) }
+}
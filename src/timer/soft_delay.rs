@@ -0,0 +1,131 @@
+//! A busy-loop delay for chips without a spare timer
+
+
+use core::hint::{
+    black_box,
+    spin_loop,
+};
+
+use crate::{
+    Clock,
+    Delay,
+    Ticks,
+};
+
+
+/// An approximate delay, implemented by counting loop iterations
+///
+/// On chips where all timers are taken, there might be nothing left to
+/// implement [`Delay`] with. `SoftDelay` waits by spinning in a loop for a
+/// number of iterations instead. How long an iteration takes depends on the
+/// CPU, its clock frequency, the memory that the code runs from, and any
+/// interrupts that happen while spinning, so **the resulting delays are only
+/// approximate**. They can be much longer than requested, if interrupts are
+/// handled in the meantime. Don't use this where precise timing matters.
+///
+/// The number of iterations per millisecond can be passed in directly, if it
+/// is known, or determined at startup using [`SoftDelay::calibrate`], against
+/// any clock that is available, even a slow one.
+///
+/// This type is only available, if the `soft-delay` feature is enabled. It
+/// requires Rust 1.66 or later.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     timer::SoftDelay,
+///     Delay,
+/// };
+/// #
+/// # struct Rtc(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Rtc {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1000; self.0 }
+/// # }
+/// #
+/// # let mut rtc = Rtc(0);
+///
+/// // Calibrate against a clock running at 1 MHz.
+/// let mut delay = SoftDelay::calibrate(&mut rtc, 1_000_000);
+/// # assert_eq!(delay.iterations_per_ms(), 1024);
+///
+/// delay.delay_us(100);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct SoftDelay {
+    iterations_per_ms: u32,
+}
+
+impl SoftDelay {
+    /// The minimum number of clock ticks that calibration measures
+    ///
+    /// Measuring more ticks makes the result more accurate, but calibration
+    /// takes longer.
+    pub const CALIBRATION_TICKS: u64 = 100;
+
+    /// Creates a delay that spins for the given number of iterations per ms
+    pub fn new(iterations_per_ms: u32) -> Self {
+        SoftDelay {
+            iterations_per_ms,
+        }
+    }
+
+    /// Creates a delay by measuring the loop against a clock
+    ///
+    /// `frequency` is the frequency of the clock in Hz. The loop is run with
+    /// an increasing number of iterations, until it takes at least
+    /// [`SoftDelay::CALIBRATION_TICKS`] ticks of the clock. The clock must not
+    /// wrap around more than once while measuring.
+    ///
+    /// Calibration should happen under the same conditions that the delay is
+    /// used under later, for example with the same CPU clock frequency, and
+    /// ideally without interrupts being handled in the meantime.
+    pub fn calibrate<C>(clock: &mut C, frequency: u32) -> Self
+        where C: Clock
+    {
+        let mut iterations: u32 = 1024;
+
+        loop {
+            let start = clock.now();
+            spin(iterations);
+            let elapsed = clock.now().wrapping_sub(start).into_u64();
+
+            if elapsed >= Self::CALIBRATION_TICKS || iterations == u32::MAX {
+                let per_ms = iterations as u64 * frequency as u64
+                    / (elapsed.max(1) * 1000);
+                return Self::new(per_ms.clamp(1, u32::MAX as u64) as u32);
+            }
+
+            iterations = iterations.saturating_mul(2);
+        }
+    }
+
+    /// Returns the number of loop iterations per millisecond
+    pub fn iterations_per_ms(&self) -> u32 {
+        self.iterations_per_ms
+    }
+}
+
+impl Delay for SoftDelay {
+    fn delay_us(&mut self, us: u32) {
+        let scaled = us as u64 * self.iterations_per_ms as u64;
+        let mut iterations = scaled / 1000 + (scaled % 1000 != 0) as u64;
+
+        while iterations > 0 {
+            let chunk = iterations.min(u32::MAX as u64);
+            spin(chunk as u32);
+            iterations -= chunk;
+        }
+    }
+}
+
+
+fn spin(iterations: u32) {
+    for i in 0..iterations {
+        // Keep the compiler from optimizing the loop away.
+        black_box(i);
+        spin_loop();
+    }
+}
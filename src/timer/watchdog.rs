@@ -0,0 +1,324 @@
+//! Feeding watchdogs during long waits
+
+
+use crate::{
+    codes,
+    timer::pace::PollStrategy,
+    Clock,
+    Delay,
+    Ticks,
+};
+
+
+/// Feeds a window watchdog, neither too early nor too late
+///
+/// A window watchdog resets the chip, if it is fed too late, like a regular
+/// watchdog does, but also if it is fed too early. `WindowWatchdog` keeps
+/// track of the time since the last feeding, using a clock, and only feeds the
+/// watchdog once the window has opened.
+///
+/// The window is given in ticks of the clock, relative to the last feeding:
+/// The watchdog may be fed once at least `open` ticks have passed, and must
+/// be fed before `close` ticks have passed.
+///
+/// `WindowWatchdog` implements [`PollStrategy`], so it can be used with
+/// [`WithStrategy`], to feed the watchdog while waiting. Then the watchdog is
+/// fed as soon as the window opens, as long as the timer is checked at least
+/// once per `close - open` ticks.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::cell::Cell;
+///
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     timer::{
+///         pace::WithStrategy,
+///         watchdog::WindowWatchdog,
+///     },
+///     TimeoutError,
+/// };
+/// #
+/// # struct Clock<'r>(&'r Cell<u32>);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock<'_> {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 {
+/// #         self.0.set(self.0.get() + 1);
+/// #         self.0.get()
+/// #     }
+/// # }
+/// #
+/// # struct Timer(u32);
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool {
+/// #         self.0 = self.0.saturating_sub(1);
+/// #         self.0 == 0
+/// #     }
+/// # }
+/// #
+/// # let time = Cell::new(0);
+/// # let clock = Clock(&time);
+/// # let timer = Timer(100);
+///
+/// let fed = Cell::new(0);
+/// let feed = || fed.set(fed.get() + 1);
+/// let watchdog = WindowWatchdog::new(clock, 20, 30, feed);
+///
+/// let mut timer = WithStrategy::new(timer, watchdog);
+///
+/// let result: Result<(), TimeoutError<()>> = block_timeout!(
+///     &mut timer,
+///     Err(nb::Error::WouldBlock)
+/// );
+///
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// assert!(fed.get() >= 4);
+/// ```
+///
+/// [`WithStrategy`]: crate::timer::pace::WithStrategy
+#[derive(Debug)]
+pub struct WindowWatchdog<C: Clock, F> {
+    clock: C,
+    open:  C::Ticks,
+    close: C::Ticks,
+    feed:  F,
+    last:  C::Ticks,
+}
+
+impl<C, F> WindowWatchdog<C, F>
+    where
+        C: Clock,
+        F: FnMut(),
+{
+    /// Creates an instance that feeds the watchdog using `feed`
+    ///
+    /// The watchdog is expected to have just been started or fed, so the
+    /// first window starts now.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `open` is larger than `close`.
+    pub fn new(mut clock: C, open: C::Ticks, close: C::Ticks, feed: F)
+        -> Self
+    {
+        assert!(
+            open <= close,
+            "Watchdog window must not close before it opens"
+        );
+
+        let last = clock.now();
+
+        WindowWatchdog {
+            clock,
+            open,
+            close,
+            feed,
+            last,
+        }
+    }
+
+    /// Feeds the watchdog, if the window is open
+    ///
+    /// Returns `Ok(true)`, if the watchdog was fed, or `Ok(false)`, if the
+    /// window hasn't opened yet. If the window has already closed, the
+    /// watchdog is fed anyway, but [`FeedError::TooLate`] is returned, as the
+    /// watchdog might have reset the chip already (unless it was halted by a
+    /// debugger, for example).
+    pub fn poll(&mut self) -> Result<bool, FeedError> {
+        let now = self.clock.now();
+        let elapsed = now.wrapping_sub(self.last);
+
+        if elapsed < self.open {
+            return Ok(false);
+        }
+
+        (self.feed)();
+        self.last = now;
+
+        if elapsed > self.close {
+            return Err(FeedError::TooLate);
+        }
+
+        Ok(true)
+    }
+
+    /// Feeds the watchdog right away, unless that would be too early
+    ///
+    /// Returns [`FeedError::TooEarly`] without feeding the watchdog, if the
+    /// window hasn't opened yet.
+    pub fn feed(&mut self) -> Result<(), FeedError> {
+        match self.poll()? {
+            true  => Ok(()),
+            false => Err(FeedError::TooEarly),
+        }
+    }
+
+    /// Returns the number of ticks until the window opens
+    ///
+    /// Returns zero, if the window is already open.
+    pub fn ticks_until_open(&mut self) -> C::Ticks {
+        let elapsed = self.clock.now().wrapping_sub(self.last);
+        self.open.saturating_sub(elapsed)
+    }
+
+    /// Returns the number of ticks until the window closes
+    ///
+    /// Returns zero, if the window has already closed.
+    pub fn ticks_until_close(&mut self) -> C::Ticks {
+        let elapsed = self.clock.now().wrapping_sub(self.last);
+        self.close.saturating_sub(elapsed)
+    }
+
+    /// Provides access to the clock
+    pub fn clock(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+impl<C, F> PollStrategy for WindowWatchdog<C, F>
+    where
+        C: Clock,
+        F: FnMut(),
+{
+    fn idle(&mut self, _: u32) {
+        // Feeding too late can't be helped at this point, and there's no way
+        // to report it from here. Use `poll` directly, to find out about it.
+        let _ = self.poll();
+    }
+}
+
+
+/// An error returned by [`WindowWatchdog`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeedError {
+    /// The window hasn't opened yet, so the watchdog wasn't fed
+    TooEarly,
+
+    /// The window had already closed, when the watchdog was fed
+    TooLate,
+}
+
+impl FeedError {
+    /// Returns the stable numeric code of this error
+    ///
+    /// See [`codes`] for details.
+    pub fn code(&self) -> u16 {
+        match self {
+            FeedError::TooEarly => codes::FEED_TOO_EARLY,
+            FeedError::TooLate  => codes::FEED_TOO_LATE,
+        }
+    }
+}
+
+
+/// A delay that feeds a watchdog, while waiting for long
+///
+/// A single long delay, like a multi-second backoff between two retries, runs
+/// past the watchdog period, unless something feeds the watchdog in the
+/// meantime. `WatchdogDelay` wraps a delay, and splits every delay into chunks
+/// of at most half the watchdog period, feeding the watchdog before each
+/// chunk. The other half of the period is left as a margin, for the time the
+/// wrapped delay takes longer than requested.
+///
+/// This covers the delays with which [`Retrier`] and the poll strategies in
+/// [`pace`] wait. Wait loops that poll an operation can feed the watchdog
+/// between polls instead, using [`WithStrategy`].
+///
+/// # Example
+///
+/// ``` rust
+/// use core::cell::Cell;
+///
+/// use embedded_timeout_macros::{
+///     timer::watchdog::WatchdogDelay,
+///     Delay,
+/// };
+/// #
+/// # struct Sleep<'r>(&'r Cell<u32>);
+/// #
+/// # impl Delay for Sleep<'_> {
+/// #     fn delay_us(&mut self, us: u32) {
+/// #         assert!(us <= 500_000);
+/// #         self.0.set(self.0.get() + us);
+/// #     }
+/// # }
+/// #
+/// # let slept = Cell::new(0);
+/// # let sleep = Sleep(&slept);
+///
+/// let fed = Cell::new(0);
+///
+/// // The watchdog resets the chip, if it isn't fed for a second.
+/// let mut delay = WatchdogDelay::new(sleep, 1_000_000, || {
+///     fed.set(fed.get() + 1);
+/// });
+///
+/// delay.delay_us(3_000_000);
+///
+/// assert_eq!(fed.get(), 6);
+/// # assert_eq!(slept.get(), 3_000_000);
+/// ```
+///
+/// [`pace`]: crate::timer::pace
+/// [`Retrier`]: crate::retry::Retrier
+/// [`WithStrategy`]: crate::timer::pace::WithStrategy
+#[derive(Debug)]
+pub struct WatchdogDelay<D, F> {
+    delay:    D,
+    chunk_us: u32,
+    feed:     F,
+}
+
+impl<D, F> WatchdogDelay<D, F>
+    where
+        D: Delay,
+        F: FnMut(),
+{
+    /// Wraps a delay, for a watchdog with the given period
+    ///
+    /// `period_us` is the time after which the watchdog resets the chip, if
+    /// it isn't fed, in microseconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `period_us` is smaller than 2.
+    pub fn new(delay: D, period_us: u32, feed: F) -> Self {
+        assert!(period_us >= 2, "Watchdog period is too short to split delays");
+
+        WatchdogDelay {
+            delay,
+            chunk_us: period_us / 2,
+            feed,
+        }
+    }
+
+    /// Returns the length of the longest chunk, in microseconds
+    pub fn chunk_us(&self) -> u32 {
+        self.chunk_us
+    }
+
+    /// Provides access to the wrapped delay
+    pub fn delay(&mut self) -> &mut D {
+        &mut self.delay
+    }
+}
+
+impl<D, F> Delay for WatchdogDelay<D, F>
+    where
+        D: Delay,
+        F: FnMut(),
+{
+    fn delay_us(&mut self, mut us: u32) {
+        while us > 0 {
+            let chunk = us.min(self.chunk_us);
+
+            (self.feed)();
+            self.delay.delay_us(chunk);
+
+            us -= chunk;
+        }
+    }
+}
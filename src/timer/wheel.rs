@@ -0,0 +1,261 @@
+//! Many concurrent timeouts, driven by a single tick source
+
+
+/// A hashed timer wheel with a fixed capacity
+///
+/// Tracks up to `CAP` timeouts, each identified by a user-defined id. The wheel
+/// is driven by calling [`TimerWheel::tick`] periodically, for example from a
+/// timer interrupt or whenever a periodic `CountDown` timer expires. Timeouts
+/// are measured in calls to `tick`.
+///
+/// The wheel has `SLOTS` slots. Every call to `tick` only looks at the timeouts
+/// in one slot, so the cost of a tick doesn't depend on the number of timeouts
+/// that are being tracked, as long as `SLOTS` is chosen large enough to spread
+/// them out.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::timer::wheel::TimerWheel;
+///
+/// let mut wheel = TimerWheel::<_, 8, 4>::new();
+///
+/// wheel.insert(2, "connection 1").unwrap();
+/// let handle = wheel.insert(2, "connection 2").unwrap();
+/// wheel.insert(20, "connection 3").unwrap();
+///
+/// // Connection 2 got a response, so its timeout is no longer needed.
+/// wheel.cancel(handle);
+///
+/// let mut expired = Vec::new();
+/// wheel.tick(|id| expired.push(id));
+/// wheel.tick(|id| expired.push(id));
+///
+/// assert_eq!(expired, ["connection 1"]);
+/// assert_eq!(wheel.len(), 1);
+/// assert_eq!(wheel.ticks_until_next(), Some(18));
+/// ```
+#[derive(Debug)]
+pub struct TimerWheel<Id, const SLOTS: usize, const CAP: usize> {
+    slots:   [Option<usize>; SLOTS],
+    entries: [Entry<Id>; CAP],
+    free:    Option<usize>,
+    cursor:  usize,
+    len:     usize,
+}
+
+impl<Id, const SLOTS: usize, const CAP: usize> TimerWheel<Id, SLOTS, CAP>
+    where Id: Copy
+{
+    /// Creates an empty timer wheel
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `SLOTS` is zero.
+    pub fn new() -> Self {
+        assert!(SLOTS > 0, "Timer wheel must have at least one slot");
+
+        let mut entries = [
+            Entry {
+                id:         None,
+                rounds:     0,
+                slot:       0,
+                next:       None,
+                generation: 0,
+            };
+            CAP
+        ];
+        for (i, entry) in entries.iter_mut().enumerate() {
+            entry.next = if i + 1 < CAP { Some(i + 1) } else { None };
+        }
+
+        TimerWheel {
+            slots: [None; SLOTS],
+            entries,
+            free:   if CAP > 0 { Some(0) } else { None },
+            cursor: 0,
+            len:    0,
+        }
+    }
+
+    /// Adds a timeout that expires after `ticks` calls to [`TimerWheel::tick`]
+    ///
+    /// A timeout of zero ticks is treated like a timeout of one tick. The full
+    /// range of `u32` is supported, regardless of the width of `usize` on the
+    /// target. Returns a handle that can be used to cancel the timeout, or an
+    /// error, if the wheel is full.
+    pub fn insert(&mut self, ticks: u32, id: Id) -> Result<Handle, Full> {
+        let index = self.free.ok_or(Full)?;
+
+        // Computed in `u64`, so no ticks get lost on targets with a 16-bit
+        // `usize`. The slot is always smaller than `SLOTS`, and the number of
+        // rounds never larger than `ticks`, so converting back is lossless.
+        let ticks = u64::from(ticks.max(1));
+        let slot = ((self.cursor as u64 + ticks) % SLOTS as u64) as usize;
+        let rounds = ((ticks - 1) / SLOTS as u64) as u32;
+
+        let entry = &mut self.entries[index];
+        self.free = entry.next;
+
+        entry.id = Some(id);
+        entry.rounds = rounds;
+        entry.slot = slot;
+        entry.next = self.slots[slot];
+
+        self.slots[slot] = Some(index);
+        self.len += 1;
+
+        Ok(Handle {
+            index,
+            generation: entry.generation,
+        })
+    }
+
+    /// Cancels a timeout
+    ///
+    /// Returns the id of the timeout, or `None`, if the timeout has already
+    /// expired or been cancelled.
+    pub fn cancel(&mut self, handle: Handle) -> Option<Id> {
+        let entry = self.entries.get(handle.index)?;
+        if entry.generation != handle.generation {
+            return None;
+        }
+        entry.id?;
+
+        let mut prev = None;
+        let mut current = self.slots[entry.slot];
+        while let Some(index) = current {
+            if index == handle.index {
+                return Some(self.remove(entry.slot, prev, index));
+            }
+
+            prev = current;
+            current = self.entries[index].next;
+        }
+
+        None
+    }
+
+    /// Advances the wheel by one tick
+    ///
+    /// Calls `on_expired` with the id of each timeout that expires.
+    pub fn tick<F>(&mut self, mut on_expired: F)
+        where F: FnMut(Id)
+    {
+        self.cursor = (self.cursor + 1) % SLOTS;
+        let slot = self.cursor;
+
+        let mut prev = None;
+        let mut current = self.slots[slot];
+        while let Some(index) = current {
+            let entry = &mut self.entries[index];
+            current = entry.next;
+
+            if entry.rounds > 0 {
+                entry.rounds -= 1;
+                prev = Some(index);
+                continue;
+            }
+
+            on_expired(self.remove(slot, prev, index));
+        }
+    }
+
+    /// Returns the number of ticks until the next timeout expires
+    ///
+    /// Unlike the `next_deadline` methods of the clock-based types in this
+    /// crate, this returns a number of ticks relative to now, not an absolute
+    /// clock value, as the wheel isn't based on a clock.
+    ///
+    /// Returns `None`, if there are no pending timeouts. Low-power applications
+    /// can use this to sleep for the returned number of tick periods, instead
+    /// of waking up for every tick. After waking up, [`TimerWheel::tick`] must
+    /// still be called once per tick period that has passed.
+    pub fn ticks_until_next(&self) -> Option<u32> {
+        let mut next = None;
+
+        for (slot, head) in self.slots.iter().enumerate() {
+            let distance = match (slot + SLOTS - self.cursor) % SLOTS {
+                0        => SLOTS,
+                distance => distance,
+            };
+
+            let mut current = *head;
+            while let Some(index) = current {
+                let entry = &self.entries[index];
+                let ticks = (entry.rounds as u64)
+                    .saturating_mul(SLOTS as u64)
+                    .saturating_add(distance as u64)
+                    .min(u32::MAX as u64) as u32;
+
+                next = Some(next.map_or(ticks, |next: u32| next.min(ticks)));
+                current = entry.next;
+            }
+        }
+
+        next
+    }
+
+    /// Returns the number of pending timeouts
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Indicates whether there are no pending timeouts
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn remove(&mut self, slot: usize, prev: Option<usize>, index: usize)
+        -> Id
+    {
+        let next = self.entries[index].next;
+        match prev {
+            Some(prev) => self.entries[prev].next = next,
+            None       => self.slots[slot] = next,
+        }
+
+        let entry = &mut self.entries[index];
+        let id = entry.id.take()
+            .expect("Removed entry must be in use");
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.next = self.free;
+
+        self.free = Some(index);
+        self.len -= 1;
+
+        id
+    }
+}
+
+impl<Id, const SLOTS: usize, const CAP: usize> Default
+    for TimerWheel<Id, SLOTS, CAP>
+    where Id: Copy
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Identifies a timeout in a [`TimerWheel`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Handle {
+    index:      usize,
+    generation: u32,
+}
+
+
+/// Returned by [`TimerWheel::insert`], if the wheel is full
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Full;
+
+
+#[derive(Clone, Copy, Debug)]
+struct Entry<Id> {
+    id:         Option<Id>,
+    rounds:     u32,
+    slot:       usize,
+    next:       Option<usize>,
+    generation: u32,
+}
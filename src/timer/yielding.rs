@@ -0,0 +1,52 @@
+//! Yielding to the rest of the system while waiting
+
+
+use crate::timer::pace::{
+    WaitForInterrupt,
+    WithStrategy,
+};
+
+
+/// Wraps a timer, yielding while waiting
+///
+/// This is a [`WithStrategy`] that calls the yield function between polls of
+/// the operation. [`WaitForInterrupt`] just calls the function it wraps, so it
+/// works for any yield function, not just for entering a low-power mode.
+///
+/// This is useful when running on top of an RTOS, where busy-waiting would
+/// starve other tasks.
+///
+/// # Example
+///
+/// Using the FreeRTOS tick count as a clock, and yielding to other tasks
+/// between polls:
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     timer::pace::WaitForInterrupt,
+///     CounterClock,
+///     Deadline,
+///     TimeoutError,
+///     Yielding,
+/// };
+/// #
+/// # const configTICK_RATE_HZ: u32 = 1000;
+/// # fn xTaskGetTickCount() -> u32 { 0 }
+/// # fn taskYIELD() {}
+///
+/// let clock = CounterClock::new(configTICK_RATE_HZ, || xTaskGetTickCount());
+/// let timeout = clock.millis(500);
+///
+/// let mut timer = Yielding::new(
+///     Deadline::start(clock, timeout),
+///     WaitForInterrupt(|| taskYIELD()),
+/// );
+///
+/// let result: Result<(), TimeoutError<()>> = block_timeout!(
+///     &mut timer,
+///     Ok(())
+/// );
+/// # assert_eq!(result, Ok(()));
+/// ```
+pub type Yielding<T, Y> = WithStrategy<T, WaitForInterrupt<Y>>;
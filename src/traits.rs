@@ -0,0 +1,626 @@
+//! The abstraction over timers that the macros are built on
+
+
+use core::{
+    convert::Infallible,
+    time::Duration,
+};
+
+#[cfg(feature = "eh0")]
+use embedded_hal::timer::CountDown;
+
+use crate::{
+    Clock,
+    Deadline,
+    TimeoutError,
+};
+
+
+/// Anything that can tell whether a timeout has expired
+///
+/// This is what the macros in this crate use to decide when to give up. It is
+/// implemented for [`Deadline`] and the other timers in this crate, as well as
+/// for all `embedded_hal::timer::CountDown` timers from embedded-hal 0.2, if
+/// the `eh0` feature is enabled.
+///
+/// Closures that return whether the deadline has passed can be used by
+/// wrapping them in [`FnTimer`].
+pub trait TimeoutTimer {
+    /// Indicates whether the timeout has expired
+    fn is_expired(&mut self) -> bool;
+
+    /// Waits between two polls of an operation that isn't finished yet
+    ///
+    /// Called by the macros and the other wait loops in this crate. The
+    /// default implementation applies the crate-wide default, which is chosen
+    /// using cargo features:
+    ///
+    /// - Without any of the following features, it returns right away, so the
+    ///   operation is polled as fast as possible.
+    /// - `idle-wfi`: Sleeps until the next interrupt, on ARM and RISC-V
+    ///   targets. Only use this, if whatever is being waited for, as well as
+    ///   the timer, generate interrupts.
+    /// - `idle-yield`: Calls the function set using [`set_yield_hook`], to
+    ///   yield to an RTOS. Takes precedence over `idle-wfi`.
+    ///
+    /// Timers can override this, to use a different strategy. Wrapping a timer
+    /// in [`WithStrategy`] does that for individual call sites.
+    ///
+    /// [`set_yield_hook`]: crate::set_yield_hook
+    /// [`WithStrategy`]: crate::timer::pace::WithStrategy
+    #[inline(always)]
+    fn idle(&mut self) {
+        crate::idle::idle()
+    }
+}
+
+#[cfg(feature = "eh0")]
+impl<T> TimeoutTimer for T
+    where T: CountDown
+{
+    fn is_expired(&mut self) -> bool {
+        match self.wait() {
+            Ok(()) =>
+                true,
+            Err(nb::Error::WouldBlock) =>
+                false,
+            Err(_) =>
+                unreachable!(),
+        }
+    }
+}
+
+impl<C> TimeoutTimer for Deadline<C>
+    where C: Clock
+{
+    fn is_expired(&mut self) -> bool {
+        Deadline::is_expired(self)
+    }
+}
+
+
+/// A timer that calls a closure to tell whether it has expired
+///
+/// The closure returns whether the deadline has passed. That is the simplest
+/// way to use sources of time that don't fit any of the other abstractions,
+/// like network time, or an RTC chip that has to be read over I2C.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     FnTimer,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Rtc(u32);
+/// #
+/// # impl Rtc {
+/// #     fn read_seconds(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+/// #
+/// # let mut rtc = Rtc(100);
+///
+/// let end = rtc.read_seconds() + 5;
+/// let mut timer = FnTimer(|| rtc.read_seconds() >= end);
+///
+/// let result: Result<(), TimeoutError<()>> = block_timeout!(
+///     &mut timer,
+///     Err(nb::Error::WouldBlock)
+/// );
+///
+/// assert_eq!(result, Err(TimeoutError::Timeout));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct FnTimer<F>(pub F);
+
+impl<F> TimeoutTimer for FnTimer<F>
+    where F: FnMut() -> bool
+{
+    fn is_expired(&mut self) -> bool {
+        (self.0)()
+    }
+}
+
+
+/// A timer that can be started with a given duration
+///
+/// Used by the forms of the macros that take a duration, to start the timer
+/// before waiting. Starting the timer can fail, for example if the duration is
+/// out of range for the timer. [`Deadline`] and `CountDown` timers can't fail
+/// to start, so their error type is `Infallible`.
+pub trait StartTimer: TimeoutTimer {
+    /// The type used to represent durations
+    type Duration;
+
+    /// The error that can occur when starting the timer
+    type Error;
+
+    /// Starts the timer, so it expires after the given duration
+    ///
+    /// If the timer is already running, it is restarted.
+    fn try_start(&mut self, duration: Self::Duration)
+        -> Result<(), Self::Error>;
+}
+
+#[cfg(feature = "eh0")]
+impl<T> StartTimer for T
+    where T: CountDown
+{
+    type Duration = T::Time;
+    type Error    = Infallible;
+
+    fn try_start(&mut self, duration: Self::Duration)
+        -> Result<(), Self::Error>
+    {
+        self.start(duration);
+        Ok(())
+    }
+}
+
+impl<C> StartTimer for Deadline<C>
+    where C: Clock
+{
+    type Duration = C::Ticks;
+    type Error    = Infallible;
+
+    fn try_start(&mut self, duration: Self::Duration)
+        -> Result<(), Self::Error>
+    {
+        self.restart_with(duration);
+        Ok(())
+    }
+}
+
+
+/// A timer that can convert a `Duration` into its native duration type
+///
+/// This allows application code to express timeouts as `core::time::Duration`,
+/// independently of the HAL-specific types a timer uses. How the conversion is
+/// done depends on the timer, but implementations should round up, so
+/// timeouts are never shorter than requested.
+///
+/// # Example
+///
+/// ``` rust
+/// use core::time::Duration;
+///
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     CounterClock,
+///     Deadline,
+///     FromDuration,
+///     StartTimeoutError,
+/// };
+/// #
+/// # let read_counter_register = || 0u32;
+///
+/// // A 32-bit counter running at 32.768 kHz
+/// let clock = CounterClock::new(32_768, read_counter_register);
+/// let mut deadline = Deadline::start(clock, 0);
+///
+/// let result: Result<(), StartTimeoutError<_, ()>> = block_timeout!(
+///     &mut deadline,
+///     deadline.convert_duration(Duration::from_millis(100)).unwrap(),
+///     Ok(())
+/// );
+///
+/// assert_eq!(result, Ok(()));
+/// assert_eq!(deadline.duration(), 3277);
+/// ```
+pub trait FromDuration: StartTimer {
+    /// Converts the `Duration` into the timer's native duration type
+    ///
+    /// Returns `None`, if the duration can't be represented.
+    fn convert_duration(&self, duration: Duration) -> Option<Self::Duration>;
+}
+
+
+/// A timer that can tell how much time has elapsed since it was started
+///
+/// Used by [`block_timeout`] to fill in [`Context::elapsed`], which requires
+/// the timer to implement this trait. Timers that can't provide this
+/// information, like `CountDown` timers or [`Alarm`], don't implement it, so
+/// the form of [`block_timeout`] that passes a [`Context`] can't be used with
+/// them.
+///
+/// [`Alarm`]: crate::Alarm
+/// [`block_timeout`]: crate::block_timeout
+pub trait Elapsed {
+    /// The type used to represent elapsed time
+    type Ticks;
+
+    /// Returns the time that has elapsed since the timer was started
+    fn elapsed_ticks(&mut self) -> Self::Ticks;
+}
+
+impl<C> Elapsed for Deadline<C>
+    where C: Clock
+{
+    type Ticks = C::Ticks;
+
+    fn elapsed_ticks(&mut self) -> Self::Ticks {
+        self.elapsed()
+    }
+}
+
+
+/// Information about the state of a wait loop
+///
+/// Made available to the operation by [`block_timeout`], if the operation is
+/// written as a pseudo-closure.
+///
+/// [`block_timeout`]: crate::block_timeout
+#[derive(Clone, Copy, Debug)]
+pub struct Context<T> {
+    /// The number of the current attempt, starting at 1
+    ///
+    /// Every evaluation of the operation counts as an attempt.
+    pub attempt: u32,
+
+    /// The time elapsed since the timer was started
+    ///
+    /// The type of this depends on the timer. See [`Elapsed`].
+    pub elapsed: T,
+}
+
+
+/// An operation that can be polled until it finishes
+///
+/// This is the integration point between driver crates and the timeout
+/// handling in this crate. Instead of a driver exposing its own
+/// `*_with_timeout` methods, it can return a value that implements
+/// `TimedOperation`, and leave it to the application to decide how to wait for
+/// it. Such a value can be polled from any of the macros, or passed to
+/// [`Retrier::run`] and the other helpers that take an operation. Types that
+/// aren't operations themselves, but can create one, can implement
+/// [`IntoTimeoutOp`] instead.
+///
+/// Implemented for all closures that return `nb::Result`.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     TimedOperation,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_hal::timer::CountDown for Timer {
+/// #     type Time = ();
+/// #     fn start<T>(&mut self, _: T) {}
+/// #     fn wait(&mut self) -> nb::Result<(), void::Void> {
+/// #         Err(nb::Error::WouldBlock)
+/// #     }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// // Defined by a driver crate
+/// struct Conversion<'r> {
+///     adc:   &'r mut u32,
+///     polls: u32,
+/// }
+///
+/// impl TimedOperation for Conversion<'_> {
+///     type Output = u32;
+///     type Error  = ();
+///
+///     fn poll(&mut self) -> nb::Result<Self::Output, Self::Error> {
+///         self.polls += 1;
+///         if self.polls < 3 {
+///             return Err(nb::Error::WouldBlock);
+///         }
+///         Ok(*self.adc)
+///     }
+/// }
+///
+/// // Application code
+/// let mut adc = 123;
+/// let mut conversion = Conversion { adc: &mut adc, polls: 0 };
+///
+/// let result: Result<u32, TimeoutError<()>> =
+///     block_timeout!(&mut timer, conversion.poll());
+///
+/// assert_eq!(result, Ok(123));
+/// ```
+///
+/// [`Retrier::run`]: crate::retry::Retrier::run
+pub trait TimedOperation {
+    /// The value that the operation produces, once it has finished
+    type Output;
+
+    /// The error that can occur while polling the operation
+    type Error;
+
+    /// Polls the operation
+    ///
+    /// Returns `WouldBlock`, if the operation hasn't finished yet.
+    fn poll(&mut self) -> nb::Result<Self::Output, Self::Error>;
+
+    /// Reports how far the operation has gotten
+    ///
+    /// Operations that consist of several steps, like transferring a block of
+    /// data, can override this, so code that waits for them can show or log
+    /// their progress. The default implementation returns `None`, meaning
+    /// that the operation doesn't report its progress.
+    fn progress(&self) -> Option<Progress> {
+        None
+    }
+}
+
+impl<F, T, E> TimedOperation for F
+    where F: FnMut() -> nb::Result<T, E>
+{
+    type Output = T;
+    type Error  = E;
+
+    fn poll(&mut self) -> nb::Result<Self::Output, Self::Error> {
+        self()
+    }
+}
+
+
+/// Conversion into a [`TimedOperation`]
+///
+/// Everything in this crate that takes an operation by value accepts this
+/// trait: [`block_timeout_op`], [`TimeoutResultExt::and_then_with`], the
+/// functions in [`retry`], and so on. It is implemented for every
+/// [`TimedOperation`], which converts into itself.
+///
+/// Driver crates can implement it for their own types, like a driver, or a
+/// mutable reference to one, to make them usable as operations directly,
+/// without a method call that creates the operation: a driver can then be
+/// passed where an operation is expected. That works like `IntoIterator`
+/// does for iterators.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout_op,
+///     IntoTimeoutOp,
+///     Progress,
+///     TimedOperation,
+///     TimeoutError,
+/// };
+/// #
+/// # struct Timer;
+/// #
+/// # impl embedded_timeout_macros::TimeoutTimer for Timer {
+/// #     fn is_expired(&mut self) -> bool { false }
+/// # }
+/// #
+/// # let mut timer = Timer;
+///
+/// // Defined by a driver crate
+/// struct Eeprom {
+///     pages: [u8; 4],
+/// }
+///
+/// struct ReadPages<'r> {
+///     eeprom: &'r mut Eeprom,
+///     read:   usize,
+///     sum:    u32,
+/// }
+///
+/// impl TimedOperation for ReadPages<'_> {
+///     type Output = u32;
+///     type Error  = ();
+///
+///     fn poll(&mut self) -> nb::Result<u32, ()> {
+///         self.sum += self.eeprom.pages[self.read] as u32;
+///         self.read += 1;
+///
+///         if self.read < self.eeprom.pages.len() {
+///             return Err(nb::Error::WouldBlock);
+///         }
+///         Ok(self.sum)
+///     }
+///
+///     fn progress(&self) -> Option<Progress> {
+///         let total = self.eeprom.pages.len() as u32;
+///         Some(Progress::new(self.read as u32, total))
+///     }
+/// }
+///
+/// impl<'r> IntoTimeoutOp for &'r mut Eeprom {
+///     type Output = u32;
+///     type Error  = ();
+///     type Op     = ReadPages<'r>;
+///
+///     fn into_timeout_op(self) -> ReadPages<'r> {
+///         ReadPages { eeprom: self, read: 0, sum: 0 }
+///     }
+/// }
+///
+/// // Application code
+/// let mut eeprom = Eeprom { pages: [1, 2, 3, 4] };
+///
+/// let result: Result<u32, TimeoutError<()>> =
+///     block_timeout_op!(&mut timer, &mut eeprom);
+///
+/// assert_eq!(result, Ok(10));
+/// ```
+///
+/// [`block_timeout_op`]: crate::block_timeout_op
+/// [`retry`]: crate::retry
+pub trait IntoTimeoutOp {
+    /// The value that the operation produces, once it has finished
+    type Output;
+
+    /// The error that can occur while polling the operation
+    type Error;
+
+    /// The operation
+    type Op: TimedOperation<Output = Self::Output, Error = Self::Error>;
+
+    /// Converts into the operation
+    fn into_timeout_op(self) -> Self::Op;
+}
+
+impl<Op> IntoTimeoutOp for Op
+    where Op: TimedOperation
+{
+    type Output = Op::Output;
+    type Error  = Op::Error;
+    type Op     = Op;
+
+    fn into_timeout_op(self) -> Self::Op {
+        self
+    }
+}
+
+
+/// How far an operation has gotten
+///
+/// Returned by [`TimedOperation::progress`]. The units are up to the
+/// operation, like bytes or pages.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// The number of units that have been completed
+    pub done: u32,
+
+    /// The total number of units
+    pub total: u32,
+}
+
+impl Progress {
+    /// Creates a progress report
+    pub const fn new(done: u32, total: u32) -> Self {
+        Progress {
+            done,
+            total,
+        }
+    }
+
+    /// Returns the completed fraction, in percent
+    ///
+    /// Returns 100, if `total` is zero.
+    pub fn percent(&self) -> u8 {
+        if self.total == 0 {
+            return 100;
+        }
+
+        let percent = self.done.min(self.total) as u64 * 100
+            / self.total as u64;
+        percent as u8
+    }
+}
+
+
+/// An operation that needs access to the timer, while it is being polled
+///
+/// Some operations have to restart or reconfigure the timer that limits them,
+/// for example after switching to a different baud rate. An operation that
+/// borrows the timer can't be passed along with the timer itself, so instead,
+/// the timer is passed to each poll. Use the `with_timer` form of
+/// [`block_timeout_op`] to wait for such an operation.
+///
+/// Implemented for all closures that take a mutable reference to the timer,
+/// and return `nb::Result`.
+///
+/// [`block_timeout_op`]: crate::block_timeout_op
+pub trait TimerOperation<T: ?Sized> {
+    /// The value that the operation produces, once it has finished
+    type Output;
+
+    /// The error that can occur while polling the operation
+    type Error;
+
+    /// Polls the operation
+    ///
+    /// Returns `WouldBlock`, if the operation hasn't finished yet.
+    fn poll(&mut self, timer: &mut T)
+        -> nb::Result<Self::Output, Self::Error>;
+}
+
+impl<F, T, O, E> TimerOperation<T> for F
+    where
+        F: FnMut(&mut T) -> nb::Result<O, E>,
+        T: ?Sized,
+{
+    type Output = O;
+    type Error  = E;
+
+    fn poll(&mut self, timer: &mut T)
+        -> nb::Result<Self::Output, Self::Error>
+    {
+        self(timer)
+    }
+}
+
+
+/// Chains timeout-limited operations onto a result
+///
+/// An alternative to nesting macros, for code that prefers method chains.
+/// Implemented for the results of the macros in this crate.
+///
+/// # Example
+///
+/// ``` rust
+/// use embedded_timeout_macros::{
+///     block_timeout,
+///     Deadline,
+///     TimeoutError,
+///     TimeoutResultExt,
+/// };
+/// #
+/// # struct Clock(u32);
+/// #
+/// # impl embedded_timeout_macros::Clock for Clock {
+/// #     type Ticks = u32;
+/// #     fn now(&mut self) -> u32 { self.0 += 1; self.0 }
+/// # }
+///
+/// // Both operations have to finish within the same 100 ticks.
+/// let mut deadline = Deadline::start(Clock(0), 100);
+///
+/// let address: Result<u8, TimeoutError<()>> =
+///     block_timeout!(&mut deadline, Ok(0x20));
+///
+/// let result = address
+///     .and_then_with(&mut deadline, |address| {
+///         let mut polls = 0;
+///         move || {
+///             polls += 1;
+///             if polls < 3 {
+///                 return Err(nb::Error::WouldBlock);
+///             }
+///             Ok(address + 1)
+///         }
+///     });
+///
+/// assert_eq!(result, Ok(0x21));
+/// ```
+pub trait TimeoutResultExt<T, E> {
+    /// Polls a second operation, if the first one succeeded
+    ///
+    /// `op` receives the result of the first operation and returns the second
+    /// one, which is then polled until it finishes, or the timer expires. The
+    /// timer isn't restarted, so both operations share its deadline. Errors
+    /// of the first operation are passed through, without calling `op`.
+    fn and_then_with<Tm, F, O>(self, timer: &mut Tm, op: F)
+        -> Result<O::Output, TimeoutError<E>>
+        where
+            Tm: TimeoutTimer + ?Sized,
+            F:  FnOnce(T) -> O,
+            O:  IntoTimeoutOp<Error = E>;
+}
+
+impl<T, E> TimeoutResultExt<T, E> for Result<T, TimeoutError<E>> {
+    fn and_then_with<Tm, F, O>(self, timer: &mut Tm, op: F)
+        -> Result<O::Output, TimeoutError<E>>
+        where
+            Tm: TimeoutTimer + ?Sized,
+            F:  FnOnce(T) -> O,
+            O:  IntoTimeoutOp<Error = E>,
+    {
+        let mut op = op(self?).into_timeout_op();
+        crate::block_timeout!(timer, op.poll())
+    }
+}